@@ -0,0 +1,245 @@
+use amf_viewer::amf::amf_highlight::AMFReader;
+use amf_viewer::amf::amf_writer::AMFWriter;
+use amf_viewer::amf::object_info::ObjectInfo;
+use amf_viewer::amf::object_properties::{ObjectProperties, TypeProperties};
+use amf_viewer::amf::object_type::ObjectType;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Counts every allocation so we can tell how much of the time is the allocator
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[derive(Default)]
+struct Document {
+    objects: HashMap<isize, ObjectInfo<'static>>,
+    roots: Vec<isize>,
+}
+
+impl Document {
+    fn add(
+        &mut self,
+        object_type: ObjectType<'static>,
+        object_properties: TypeProperties,
+    ) -> isize {
+        let object_id = self.objects.len() as isize;
+        self.objects.insert(
+            object_id,
+            ObjectInfo::detached(object_id, object_type, object_properties),
+        );
+        object_id
+    }
+
+    fn value(&mut self, object_type: ObjectType<'static>) -> isize {
+        self.add(object_type, TypeProperties::AmfNoProperties)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = AMFWriter::new(false);
+        writer.write_document(&self.objects, &self.roots).unwrap();
+        writer.into_bytes()
+    }
+}
+
+fn amf3_object(
+    doc: &mut Document,
+    class: &str,
+    sealed: usize,
+    members: Vec<(String, isize)>,
+) -> isize {
+    let dynamic = sealed < members.len();
+    let properties = ObjectProperties::new(
+        false,
+        sealed,
+        (dynamic as usize) << 1,
+        false,
+        dynamic,
+        String::from(class),
+    );
+    doc.add(
+        ObjectType::Amf3Object(members),
+        TypeProperties::Amf3ObjectProperties(properties),
+    )
+}
+
+// connect commands one after the other, plain AMF0 with nothing shared between them
+fn flat_amf0_commands() -> Vec<u8> {
+    let mut doc = Document::default();
+    for transaction in 0..20_000 {
+        let name = doc.add(
+            ObjectType::Amf0String("connect".into()),
+            TypeProperties::Amf0StringProperties,
+        );
+        let id = doc.value(ObjectType::Amf0Number(transaction as f64));
+        let mut members = Vec::new();
+        for (key, value) in [
+            ("app", "live"),
+            ("flashVer", "FMLE/3.0 (compatible; FMSc/1.0)"),
+            ("tcUrl", "rtmp://localhost:1935/live"),
+        ] {
+            let value = doc.add(
+                ObjectType::Amf0String(value.into()),
+                TypeProperties::Amf0StringProperties,
+            );
+            members.push((String::from(key), value));
+        }
+        for (key, value) in [
+            ("capabilities", 239.0),
+            ("audioCodecs", 3575.0),
+            ("videoCodecs", 252.0),
+        ] {
+            members.push((String::from(key), doc.value(ObjectType::Amf0Number(value))));
+        }
+        members.push((String::from("fpad"), doc.value(ObjectType::Amf0Bool(false))));
+        let command = doc.add(
+            ObjectType::Amf0Object(members),
+            TypeProperties::Amf0ObjectProperties,
+        );
+        let null = doc.value(ObjectType::Amf0Null);
+        doc.roots.extend([name, id, command, null]);
+    }
+    doc.encode()
+}
+
+// Chains of AMF3 objects nested 200 deep
+fn deep_amf3_graphs() -> Vec<u8> {
+    let mut doc = Document::default();
+    for _ in 0..500 {
+        let mut child = doc.value(ObjectType::Amf3Null);
+        for depth in 0..200 {
+            let members = vec![
+                (String::from("child"), child),
+                (
+                    String::from("depth"),
+                    doc.value(ObjectType::Amf3Integer(depth)),
+                ),
+                (
+                    String::from("name"),
+                    doc.value(ObjectType::Amf3String(format!("node{}", depth).into())),
+                ),
+            ];
+            child = amf3_object(&mut doc, "com.example.Node", 3, members);
+        }
+        doc.roots.push(child);
+    }
+    doc.encode()
+}
+
+fn large_byte_arrays() -> Vec<u8> {
+    let mut doc = Document::default();
+    for i in 0..4u8 {
+        let bytes = (0..4 << 20).map(|b: u32| (b as u8) ^ i).collect();
+        let array = doc.value(ObjectType::Amf3ByteArray(bytes));
+        doc.roots.push(array);
+    }
+    doc.encode()
+}
+
+// What a Flex remoting response looks like, one class for every message, the same strings
+// over and over and a headers object they all point at
+fn flex_messages() -> Vec<u8> {
+    let mut doc = Document::default();
+    let headers = {
+        let id = doc.value(ObjectType::Amf3String("my-rtmp".into()));
+        amf3_object(&mut doc, "", 0, vec![(String::from("DSId"), id)])
+    };
+    let mut messages = Vec::new();
+    for i in 0..10_000 {
+        let members = vec![
+            (String::from("body"), doc.value(ObjectType::Amf3Integer(i))),
+            (
+                String::from("clientId"),
+                doc.value(ObjectType::Amf3String("9E2A1C04-7B1D-4F3A".into())),
+            ),
+            (
+                String::from("correlationId"),
+                doc.value(ObjectType::Amf3String(format!("{:08X}", i / 4).into())),
+            ),
+            (
+                String::from("destination"),
+                doc.value(ObjectType::Amf3String("lcdsService".into())),
+            ),
+            (String::from("headers"), headers),
+            (
+                String::from("messageId"),
+                doc.value(ObjectType::Amf3String(format!("MSG-{:08X}", i).into())),
+            ),
+            (
+                String::from("timestamp"),
+                doc.value(ObjectType::Amf3Double(1.7e12 + i as f64)),
+            ),
+            (
+                String::from("timeToLive"),
+                doc.value(ObjectType::Amf3Integer(0)),
+            ),
+        ];
+        messages.push(amf3_object(
+            &mut doc,
+            "flex.messaging.messages.AcknowledgeMessage",
+            8,
+            members,
+        ));
+    }
+    let array = doc.value(ObjectType::Amf3Array(Vec::new(), messages));
+    doc.roots.push(array);
+    doc.encode()
+}
+
+fn decode(bytes: &[u8]) -> usize {
+    let mut reader = AMFReader::new(bytes, false);
+    reader.highlight();
+    assert!(!reader.objects.contains_key(&-1));
+    reader.objects.len()
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.sample_size(10); // A decode of the bigger payloads takes a good fraction of a second
+    for (name, bytes) in [
+        ("flat_amf0_commands", flat_amf0_commands()),
+        ("deep_amf3_graphs", deep_amf3_graphs()),
+        ("large_byte_arrays", large_byte_arrays()),
+        ("flex_messages", flex_messages()),
+    ] {
+        // Criterion only knows about time, so allocations are counted over a single decode
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        decode(&bytes);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "{}: {} bytes, {} allocations, {:.4} per byte",
+            name,
+            bytes.len(),
+            allocations,
+            allocations as f64 / bytes.len() as f64
+        );
+
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_function(name, |b| b.iter(|| decode(black_box(&bytes))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
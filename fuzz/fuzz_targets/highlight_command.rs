@@ -0,0 +1,18 @@
+#![no_main]
+
+use amf_viewer::amf::amf_highlight::AMFReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = AMFReader::new(data, true);
+    reader.highlight();
+
+    // Every byte belongs to exactly one span, in order. The format byte is skipped
+    let mut head = 1.min(data.len());
+    for span in &reader.out {
+        assert_eq!(span.start, head);
+        assert!(span.end > span.start);
+        head = span.end;
+    }
+    assert_eq!(head, data.len());
+});
@@ -1,29 +1,42 @@
-use std::collections::HashMap;
-
-pub struct AmfObject {
-    pub(crate) property_count: usize,
+#[derive(Clone, Debug, PartialEq)]
+pub struct AmfTraits {
     pub(crate) encoding: i32,
     pub(crate) externalisable: bool,
     pub(crate) dynamic: bool,
     pub(crate) object_type: String,
-    pub(crate) properties: HashMap<String, Option<isize>>,
+    pub(crate) keys: Vec<String>, // Sealed member names, in the order they are sent
 }
 
-impl AmfObject {
+impl AmfTraits {
     pub fn new(
         encoding: i32,
         externalisable: bool,
         dynamic: bool,
         object_type: String,
-        properties: HashMap<String, Option<isize>>,
+        keys: Vec<String>,
     ) -> Self {
         Self {
-            property_count: properties.keys().len(),
             encoding,
             externalisable,
             dynamic,
             object_type,
-            properties,
+            keys,
+        }
+    }
+}
+
+pub struct AmfObject {
+    pub(crate) traits: AmfTraits,
+    pub(crate) is_reference: bool, // Traits came from the traits table
+    pub(crate) properties: Vec<(String, isize)>,
+}
+
+impl AmfObject {
+    pub fn new(traits: AmfTraits, is_reference: bool) -> Self {
+        Self {
+            traits,
+            is_reference,
+            properties: Vec::new(),
         }
     }
 }
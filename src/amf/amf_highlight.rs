@@ -1,147 +1,404 @@
-use crate::amf::amf3_object::AmfObject;
+use crate::amf::amf3_object::{AmfObject, AmfTraits};
+use crate::amf::decode_limits::{DecodeLimits, Limit};
 use crate::amf::object_info::ObjectInfo;
 use crate::amf::object_properties::TypeProperties::{
-    Amf0ObjectProperties, Amf0StringProperties, Amf0TypedObjectProperties, Amf3ArrayProperties,
-    Amf3ObjectProperties, Amf3StringProperties, AmfNoProperties,
+    Amf0EcmaArrayProperties, Amf0ObjectProperties, Amf0StringProperties, Amf0TypedObjectProperties,
+    Amf3ArrayProperties, Amf3DictionaryProperties, Amf3ObjectProperties, Amf3StringProperties,
+    Amf3VectorProperties, AmfNoProperties,
+};
+use crate::amf::object_properties::{
+    DictionaryProperties, GenericProperties, ObjectProperties, TypeProperties, VectorProperties,
 };
-use crate::amf::object_properties::{GenericProperties, ObjectProperties};
 use crate::amf::object_type::ObjectType;
 use crate::amf::object_type::ObjectType::{
-    Amf0Number, Amf0Undefined, Amf3Array, Amf3Object, Amf3String, Amf3Undefined,
+    Amf0Number, Amf0Undefined, Amf3Array, Amf3Object, Amf3Undefined,
 };
-use crate::amf::syntax_byte::SyntaxByte;
-use dioxus::logger::tracing;
+use crate::amf::syntax_byte::{SyntaxByte, SyntaxSpan};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::Read;
-
-const AMF0_NUMBER: &'static str = "text-ctp-blue";
-const AMF0_NUMBER_MARKER: &'static str = "text-ctp-blue/80";
-const AMF0_BOOL_TRUE: &'static str = "text-ctp-green/80";
-const AMF0_BOOL_TRUE_MARKER: &'static str = "text-ctp-green/80";
-const AMF0_BOOL_FALSE: &'static str = "text-ctp-red/80";
-const AMF0_BOOL_FALSE_MARKER: &'static str = "text-ctp-red/80";
-const AMF0_STRING: &'static str = "text-ctp-yellow";
-const AMF0_STRING_MARKER: &'static str = "text-ctp-yellow/80";
-const AMF0_OBJECT: &'static str = "text-ctp-teal";
-const AMF0_OBJECT_MARKER: &'static str = "text-ctp-teal/80";
-const AMF0_NULL: &'static str = "text-ctp-rosewater";
-const AMF0_TYPED_OBJECT: &'static str = "text-ctp-mauve";
-const AMF0_TYPED_OBJECT_MARKER: &'static str = "text-ctp-yellow/80";
-const AMF0_SWITCH_MARKER: &'static str = "text-ctp-pink/80";
-const AMF3_UNDEFINED: &'static str = "text-ctp-pink";
-const AMF3_NULL: &'static str = "text-ctp-rosewater";
-const AMF3_FALSE: &'static str = "text-ctp-red";
-const AMF3_TRUE: &'static str = "text-ctp-green";
-const AMF3_INTEGER: &'static str = "text-ctp-sky";
-const AMF3_DOUBLE: &'static str = "text-ctp-blue";
-const AMF3_STRING: &'static str = "text-ctp-yellow";
-const AMF3_DATE: &'static str = "text-ctp-rosewater";
-const AMF3_ARRAY: &'static str = "text-ctp-lavender";
-const AMF3_OBJECT: &'static str = "text-ctp-mauve";
-const AMF_ERROR: &'static str = "text-red-500";
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const AMF0_NUMBER: &str = "text-ctp-blue";
+const AMF0_NUMBER_MARKER: &str = "text-ctp-blue/80";
+const AMF0_BOOL_TRUE: &str = "text-ctp-green/80";
+const AMF0_BOOL_TRUE_MARKER: &str = "text-ctp-green/80";
+const AMF0_BOOL_FALSE: &str = "text-ctp-red/80";
+const AMF0_BOOL_FALSE_MARKER: &str = "text-ctp-red/80";
+const AMF0_STRING: &str = "text-ctp-yellow";
+const AMF0_STRING_MARKER: &str = "text-ctp-yellow/80";
+const AMF0_OBJECT: &str = "text-ctp-teal";
+const AMF0_OBJECT_MARKER: &str = "text-ctp-teal/80";
+const AMF0_NULL: &str = "text-ctp-rosewater";
+const AMF0_REFERENCE: &str = "text-ctp-overlay2";
+const AMF0_ECMA_ARRAY: &str = "text-ctp-lavender";
+const AMF0_ECMA_ARRAY_MARKER: &str = "text-ctp-lavender/80";
+const AMF0_STRICT_ARRAY: &str = "text-ctp-sapphire";
+const AMF0_STRICT_ARRAY_MARKER: &str = "text-ctp-sapphire/80";
+const AMF0_DATE: &str = "text-ctp-rosewater";
+const AMF0_DATE_MARKER: &str = "text-ctp-rosewater/80";
+const AMF0_LONG_STRING: &str = "text-ctp-peach";
+const AMF0_LONG_STRING_MARKER: &str = "text-ctp-peach/80";
+const AMF0_XML: &str = "text-ctp-flamingo";
+const AMF0_XML_MARKER: &str = "text-ctp-flamingo/80";
+const AMF0_TYPED_OBJECT: &str = "text-ctp-mauve";
+const AMF0_TYPED_OBJECT_MARKER: &str = "text-ctp-yellow/80";
+const AMF0_SWITCH_MARKER: &str = "text-ctp-pink/80";
+const AMF3_UNDEFINED: &str = "text-ctp-pink";
+const AMF3_NULL: &str = "text-ctp-rosewater";
+const AMF3_FALSE: &str = "text-ctp-red";
+const AMF3_TRUE: &str = "text-ctp-green";
+const AMF3_INTEGER: &str = "text-ctp-sky";
+const AMF3_DOUBLE: &str = "text-ctp-blue";
+const AMF3_STRING: &str = "text-ctp-yellow";
+const AMF3_XML: &str = "text-ctp-flamingo";
+const AMF3_DATE: &str = "text-ctp-rosewater";
+const AMF3_ARRAY: &str = "text-ctp-lavender";
+const AMF3_OBJECT: &str = "text-ctp-mauve";
+const AMF3_BYTE_ARRAY: &str = "text-ctp-peach";
+const AMF3_VECTOR: &str = "text-ctp-sapphire";
+const AMF3_DICTIONARY: &str = "text-ctp-teal";
+pub(crate) const AMF_ERROR: &str = "text-red-500";
+
+// Externalizable classes whose body is a single AMF3 value, anything else is opaque to us
+const AMF3_PROXY_CLASSES: [&str; 6] = [
+    "flex.messaging.io.ArrayCollection",
+    "flex.messaging.io.ArrayList",
+    "flex.messaging.io.ObjectProxy",
+    "mx.collections.ArrayCollection",
+    "mx.collections.ArrayList",
+    "mx.utils.ObjectProxy",
+];
+
+// Strings that aren't valid UTF-8 are shown as their raw bytes
+fn amf3_text(bytes: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Cow::Borrowed(s),
+        Err(_) => Cow::Owned(format!("{:?}", bytes)),
+    }
+}
 
 // I fucking LOVE Action Message Format
-pub struct AMFReader {
-    buffer: Vec<u8>,
+pub struct AMFReader<'a> {
+    buffer: &'a [u8],
     read_head: usize,
-    pub(crate) out: Vec<SyntaxByte>,
+    highlight_head: usize, // Next byte that push_byte will colour
+    pub out: Vec<SyntaxSpan>,
+    pub roots: Vec<isize>, // Top level values, in the order they were read
     encoding: u8,
-    current_layer: u8, // Change colour depending on object layer
-    pub(crate) objects: HashMap<isize, ObjectInfo>,
-    strings: Vec<String>,
+    current_layer: usize, // Change colour depending on object layer
+    depth: usize,
+    limits: DecodeLimits,
+    exceeded: Option<(Limit, usize)>, // Which limit stopped us, and where
+    progress: Option<&'a AtomicUsize>, // Bytes coloured so far, for whoever is waiting on us
+    pub objects: HashMap<isize, ObjectInfo<'a>>,
+    first_id: usize,            // IDs carry on from an earlier reader's when resuming
+    strings: Vec<Range<usize>>, // Where each referencable string sits in the buffer
+    traits: Vec<AmfTraits>,
+    amf0_references: Vec<isize>,
+    amf3_references: Vec<isize>,
 
-    is_error: bool, // Are we in an error state right now?
+    is_error: bool,  // Are we in an error state right now?
+    truncated: bool, // Did we run off the end of the buffer?
 }
 
-impl AMFReader {
-    pub fn new(buffer: &Vec<u8>, is_command: bool) -> Self {
-        if is_command {
-            //let encoding = buffer[0];
-            AMFReader {
-                buffer: buffer.clone(),
-                read_head: 1,
-                out: Vec::new(),
-                encoding: 0,
-                current_layer: 0,
-                objects: HashMap::new(),
-                strings: Vec::new(),
-                is_error: false,
-            }
-        } else {
-            AMFReader {
-                buffer: buffer.clone(),
-                read_head: 0,
-                out: Vec::new(),
-                encoding: 0,
-                current_layer: 0,
-                objects: HashMap::new(),
-                strings: Vec::new(),
-                is_error: false,
-            }
+// Everything the reader has learnt from a buffer apart from the buffer itself and the
+// objects, which borrow it, so a stream can pick up where it left off when more bytes arrive
+#[derive(Default)]
+pub(crate) struct ReaderState {
+    pub(crate) out: Vec<SyntaxSpan>,
+    next_id: usize,
+    strings: Vec<Range<usize>>,
+    traits: Vec<AmfTraits>,
+    amf0_references: Vec<isize>,
+    amf3_references: Vec<isize>,
+}
+
+// Table lengths before a value was read
+pub(crate) struct Checkpoint([usize; 6]);
+
+impl ReaderState {
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint([
+            self.out.len(),
+            self.next_id,
+            self.strings.len(),
+            self.traits.len(),
+            self.amf0_references.len(),
+            self.amf3_references.len(),
+        ])
+    }
+
+    // Forgets a value that was only partly read. Object IDs are handed out in order and a new
+    // value never shares a span with the one before it, so cutting everything back is enough.
+    pub(crate) fn rollback(&mut self, checkpoint: Checkpoint) {
+        let [out, next_id, strings, traits, amf0_references, amf3_references] = checkpoint.0;
+        self.out.truncate(out);
+        self.next_id = next_id;
+        self.strings.truncate(strings);
+        self.traits.truncate(traits);
+        self.amf0_references.truncate(amf0_references);
+        self.amf3_references.truncate(amf3_references);
+    }
+}
+
+impl<'a> AMFReader<'a> {
+    pub fn new(buffer: &'a [u8], is_command: bool) -> Self {
+        // Commands have a format byte in front, skip it
+        //let encoding = buffer[0];
+        let read_head = if is_command { 1 } else { 0 };
+        AMFReader {
+            buffer,
+            read_head,
+            highlight_head: read_head,
+            out: Vec::new(),
+            roots: Vec::new(),
+            encoding: 0,
+            current_layer: 0,
+            depth: 0,
+            limits: DecodeLimits::default(),
+            exceeded: None,
+            progress: None,
+            objects: HashMap::new(),
+            first_id: 0,
+            strings: Vec::new(),
+            traits: Vec::new(),
+            amf0_references: Vec::new(),
+            amf3_references: Vec::new(),
+            is_error: false,
+            truncated: false,
         }
     }
 
-    pub fn highlight(&mut self) {
-        while &self.read_head < &self.buffer.len() {
-            if self.is_error {
-                let b = match self.read_byte() {
-                    None => break,
-                    Some(b) => *b,
-                };
+    // Carries on reading from read_head with the tables from an earlier reader
+    pub(crate) fn resume(
+        buffer: &'a [u8],
+        read_head: usize,
+        state: ReaderState,
+        limits: DecodeLimits,
+    ) -> Self {
+        AMFReader {
+            buffer,
+            read_head,
+            highlight_head: read_head,
+            out: state.out,
+            roots: Vec::new(),
+            encoding: 0,
+            current_layer: 0,
+            depth: 0,
+            limits,
+            exceeded: None,
+            progress: None,
+            objects: HashMap::new(),
+            first_id: state.next_id,
+            strings: state.strings,
+            traits: state.traits,
+            amf0_references: state.amf0_references,
+            amf3_references: state.amf3_references,
+            is_error: false,
+            truncated: false,
+        }
+    }
 
-                let syn = SyntaxByte {
-                    value: b,
-                    object_id: -1,
-                    color: AMF_ERROR.parse().unwrap(),
-                };
-                self.push_byte(syn)
-            }
-            if self.encoding == 0 {
-                self.read_amf0();
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Keeps a count of the bytes decoded so far, which another thread can read to show progress.
+    pub fn with_progress(mut self, progress: &'a AtomicUsize) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// The limit that stopped decoding and the offset it was hit at, if any.
+    pub fn limit_exceeded(&self) -> Option<(Limit, usize)> {
+        self.exceeded
+    }
+
+    // The tables to resume with, and the objects read since this reader started
+    pub(crate) fn into_state(self) -> (ReaderState, HashMap<isize, ObjectInfo<'a>>) {
+        let state = ReaderState {
+            out: self.out,
+            next_id: self.first_id + self.objects.len(),
+            strings: self.strings,
+            traits: self.traits,
+            amf0_references: self.amf0_references,
+            amf3_references: self.amf3_references,
+        };
+        (state, self.objects)
+    }
+
+    // Object IDs are handed out in the order objects are read
+    fn next_id(&self) -> isize {
+        (self.first_id + self.objects.len()) as isize
+    }
+
+    pub(crate) fn read_head(&self) -> usize {
+        self.read_head
+    }
+
+    pub(crate) fn is_error(&self) -> bool {
+        self.is_error
+    }
+
+    // True when the error was only the input ending early
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn highlight(&mut self) {
+        while self.read_head < self.buffer.len() && !self.is_error {
+            let object_id = if self.encoding == 0 {
+                self.read_amf0()
             } else {
-                self.read_amf3();
+                self.read_amf3()
+            };
+            if !self.is_error {
+                self.roots.push(object_id);
             }
         }
         if self.is_error {
+            let syn = SyntaxByte {
+                object_id: -1,
+                color: AMF_ERROR.parse().unwrap(),
+            };
+            // Anything read but not coloured yet goes red as well, even if it was the last byte
+            let error_start = self.highlight_head;
+            self.read_head = self.highlight_head;
+            self.push_bytes(syn, self.buffer.len() - error_start);
             self.objects.insert(
                 -1,
                 ObjectInfo {
                     object_id: -1,
                     object_type: Amf0Undefined,
                     object_properties: AmfNoProperties,
+                    span: error_start..self.buffer.len(),
                 },
             );
         }
     }
 
+    fn insert_object(
+        &mut self,
+        object_id: isize,
+        object_type: ObjectType<'a>,
+        object_properties: TypeProperties,
+    ) {
+        if !self.objects.contains_key(&object_id)
+            && self.next_id() as usize >= self.limits.max_objects
+        {
+            self.exceed(Limit::Objects);
+            return;
+        }
+        let info = ObjectInfo {
+            object_id,
+            object_type,
+            object_properties,
+            span: self.read_head..self.read_head,
+        };
+        self.objects.insert(object_id, info);
+    }
+
+    fn exceed(&mut self, limit: Limit) {
+        if !self.is_error {
+            tracing::error!("{} limit exceeded at offset {}", limit, self.read_head);
+            self.exceeded = Some((limit, self.read_head));
+        }
+        self.is_error = true;
+    }
+
+    // Takes the colour back off everything read since start, so the error span covers it
+    fn fail_from(&mut self, start: usize) {
+        while let Some(last) = self.out.last_mut() {
+            if last.start < start {
+                last.end = last.end.min(start);
+                break;
+            }
+            self.out.pop();
+        }
+        self.highlight_head = self.highlight_head.min(start);
+        self.is_error = true;
+    }
+
+    // Declared counts are checked up front, we don't trust them to loop on
+    fn check_count(&mut self, count: usize) -> bool {
+        if count > self.limits.max_elements {
+            self.exceed(Limit::Elements);
+            return false;
+        }
+        true
+    }
+
+    fn check_string_length(&mut self, length: usize) -> bool {
+        if length > self.limits.max_string_length {
+            self.exceed(Limit::StringLength);
+            return false;
+        }
+        true
+    }
+
+    // Claims the ID before any children are read so they can't take it from us
+    fn reserve_object(&mut self, object_type: ObjectType<'a>) -> isize {
+        let object_id = self.next_id();
+        self.insert_object(object_id, object_type, AmfNoProperties);
+        object_id
+    }
+
+    // A reference points back at an object we have already read, so hand out that object's ID
+    // and colour the reference bytes as part of it.
+    fn resolve_reference(
+        &mut self,
+        object_id: isize,
+        start: usize,
+        target: Option<isize>,
+    ) -> isize {
+        let target = match target {
+            Some(target) => target,
+            None => {
+                tracing::error!("Reference to an object that has not been read");
+                self.fail_from(start);
+                return object_id;
+            }
+        };
+        for syntax in self.out.iter_mut().rev() {
+            if syntax.start < start {
+                break;
+            }
+            syntax.object_id = target;
+        }
+        // Nothing else has been inserted since the reservation, so this frees the ID again
+        self.objects.remove(&object_id);
+        target
+    }
+
     fn read_amf0_integer(&mut self, object_id: Option<isize>) {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
-        };
-        let syntax = SyntaxByte {
-            value: 0,
-            object_id,
-            color: AMF0_NUMBER.parse().unwrap(),
+            None => self.next_id(),
         };
-        let bytes = self.push_bytes(syntax, 7);
+        let number = self.read_double(object_id, AMF0_NUMBER);
 
-        let number = f64::from_be_bytes(<[u8; 8]>::try_from(bytes).unwrap());
+        self.insert_object(object_id, Amf0Number(number), AmfNoProperties);
+    }
 
-        self.objects.insert(
+    fn read_double(&mut self, object_id: isize, colour: &str) -> f64 {
+        let syntax = SyntaxByte {
             object_id,
-            ObjectInfo {
-                object_id,
-                object_type: Amf0Number(number),
-                object_properties: AmfNoProperties,
-            },
-        );
+            color: colour.parse().unwrap(),
+        };
+        let bytes = self.push_bytes(syntax, 8);
+        // Short reads have already flagged the error
+        match <[u8; 8]>::try_from(bytes) {
+            Ok(bytes) => f64::from_be_bytes(bytes),
+            Err(_) => 0.0,
+        }
     }
 
     fn read_amf0_bool(&mut self, object_id: Option<isize>) {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
         let byte = match self.read_byte() {
             Some(b) => *b,
@@ -151,7 +408,6 @@ impl AMFReader {
             }
         };
         let mut syntax = SyntaxByte {
-            value: byte,
             object_id,
             color: AMF0_BOOL_TRUE.parse().unwrap(),
         };
@@ -160,161 +416,270 @@ impl AMFReader {
         }
         self.push_byte(syntax);
 
-        let info = ObjectInfo {
-            object_id,
-            object_type: ObjectType::Amf0Bool(byte == 1),
-            object_properties: AmfNoProperties,
-        };
-
-        self.objects.insert(object_id, info);
+        self.insert_object(object_id, ObjectType::Amf0Bool(byte == 1), AmfNoProperties);
     }
 
-    fn read_amf0_string(&mut self, object_id: Option<isize>) -> String {
+    fn read_amf0_string(&mut self, object_id: Option<isize>) -> Cow<'a, str> {
         self.read_amf0_utf8(None, object_id)
     }
 
     fn read_amf0_utf_length(&mut self, colour: Option<String>, object_id: isize) -> u16 {
         let colour = colour.unwrap_or_else(|| AMF0_STRING.parse().unwrap());
-        // I love fighting the borrow checker
-        let len_bytes = (&self.buffer).get(self.read_head..self.read_head + 2);
-        let len_bytes = match len_bytes {
+        let len_bytes = match self.read_bytes(2) {
             Some(b) => b,
             None => {
                 self.is_error = true;
                 return 0;
             }
         };
-        self.read_head += 2;
         let length = ((len_bytes[0] as u16) << 8) | len_bytes[1] as u16;
-        for i in len_bytes {
-            let syntax = SyntaxByte {
-                value: *i,
-                object_id,
-                color: colour.clone(),
-            };
-            self.out.push(syntax);
-        }
+        let syntax = SyntaxByte {
+            object_id,
+            color: colour,
+        };
+        self.push_run(syntax, 2);
         length
     }
 
-    fn read_amf0_utf8(&mut self, colour: Option<String>, object_id: Option<isize>) -> String {
+    fn read_amf0_utf8(&mut self, colour: Option<String>, object_id: Option<isize>) -> Cow<'a, str> {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
 
+        let start = self.read_head;
         let length = self.read_amf0_utf_length(colour.clone(), object_id);
 
         let colour = colour.unwrap_or_else(|| AMF0_STRING.parse().unwrap());
-        let mut out = String::new();
-        match self.read_bytes(length as usize) {
-            Some(mut b) => match b.read_to_string(&mut out) {
+        let out = self.read_utf8_bytes(length as usize, object_id, colour);
+
+        self.insert_object(
+            object_id,
+            ObjectType::Amf0String(out.clone()),
+            Amf0StringProperties,
+        );
+        self.set_span(object_id, start);
+
+        out
+    }
+
+    fn read_utf8_bytes(&mut self, length: usize, object_id: isize, colour: String) -> Cow<'a, str> {
+        if !self.check_string_length(length) {
+            return Cow::Borrowed("");
+        }
+        let out = match self.read_bytes(length) {
+            Some(b) => match std::str::from_utf8(b) {
                 Ok(s) => s,
                 Err(e) => {
                     tracing::error!("Could not read stream: {:?}", e);
                     self.is_error = true;
-                    return String::new();
+                    return Cow::Borrowed("");
                 }
             },
             None => {
                 self.is_error = true;
-                return String::new();
+                return Cow::Borrowed("");
             }
         };
-        for i in out.as_bytes() {
-            let syntax = SyntaxByte {
-                value: *i,
-                object_id,
-                color: colour.clone(),
-            };
-            self.push_byte(syntax);
-        }
-
-        let info = ObjectInfo {
+        let syntax = SyntaxByte {
             object_id,
-            object_type: ObjectType::Amf0String(out.clone()),
-            object_properties: Amf0StringProperties,
+            color: colour,
         };
-
-        self.objects.insert(object_id, info);
-
-        out
+        self.push_run(syntax, length);
+        Cow::Borrowed(out)
     }
 
-    fn read_amf0_object(&mut self, object_id: Option<isize>) {
-        let object_id: isize = match object_id {
-            Some(id) => id,
-            None => self.objects.len() as isize,
-        };
-
-        let info = ObjectInfo {
+    fn read_u32(&mut self, object_id: isize, colour: &str) -> u32 {
+        let syntax = SyntaxByte {
             object_id,
-            object_type: ObjectType::Amf0Object,
-            object_properties: Amf0ObjectProperties,
+            color: colour.parse().unwrap(),
         };
+        let bytes = self.push_bytes(syntax, 4);
+        match <[u8; 4]>::try_from(bytes) {
+            Ok(bytes) => u32::from_be_bytes(bytes),
+            Err(_) => 0,
+        }
+    }
 
-        self.objects.insert(object_id, info);
+    fn read_amf0_long_utf8(&mut self, colour: &str, object_id: isize) -> Cow<'a, str> {
+        let length = self.read_u32(object_id, colour);
+        if self.is_error {
+            return Cow::Borrowed("");
+        }
+        self.read_utf8_bytes(length as usize, object_id, colour.parse().unwrap())
+    }
 
-        self.current_layer += 1;
+    fn read_amf0_members(&mut self, object_id: isize) -> Vec<(String, isize)> {
+        let mut members = Vec::new();
         loop {
             let key = self.read_amf0_utf8(
-                Some(format!("{}/{}", AMF0_OBJECT, 100 - self.current_layer * 20)),
+                Some(format!(
+                    "{}/{}",
+                    AMF0_OBJECT,
+                    100 - self.current_layer.min(4) * 20
+                )),
                 None,
             );
+            if self.is_error {
+                return members;
+            }
             tracing::debug!("Key: '{}'", key);
-            if key.is_empty() {
-                let b = match self.read_byte() {
-                    Some(b) => *b,
-                    None => {
-                        self.is_error = true;
-                        return;
-                    }
-                };
+            if key.is_empty() && self.buffer.get(self.read_head) == Some(&0x09) {
+                if self.read_byte().is_none() {
+                    self.is_error = true;
+                    return members;
+                }
                 let syntax = SyntaxByte {
-                    value: b,
                     object_id,
-                    color: format!("{}", AMF0_OBJECT_MARKER,),
+                    color: AMF0_OBJECT_MARKER.to_string(),
                 };
                 self.push_byte(syntax);
-                if b == 0x09 {
-                    if self.current_layer != 0 {
-                        self.current_layer -= 1;
-                    }
-                    return;
-                }
+                return members;
+            }
+            if !self.check_count(members.len() + 1) {
+                return members;
+            }
+            let id = self.read_amf0();
+            members.push((key.into_owned(), id));
+            if self.is_error {
+                return members;
             }
-            self.read_amf0();
             tracing::debug!("Next...");
         }
     }
 
-    fn read_amf0_typed_object(&mut self, object_id: Option<isize>) {
+    fn read_amf0_object(&mut self, object_id: Option<isize>) {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
+        self.amf0_references.push(object_id);
 
-        let info = ObjectInfo {
+        self.current_layer += 1;
+        let members = self.read_amf0_members(object_id);
+        if self.current_layer != 0 {
+            self.current_layer -= 1;
+        }
+
+        self.insert_object(
             object_id,
-            object_type: ObjectType::Amf0TypedObject,
-            object_properties: Amf0TypedObjectProperties,
-        };
+            ObjectType::Amf0Object(members),
+            Amf0ObjectProperties,
+        );
+    }
 
-        self.objects.insert(object_id, info);
+    fn read_amf0_typed_object(&mut self, object_id: Option<isize>) {
+        let object_id: isize = match object_id {
+            Some(id) => id,
+            None => self.next_id(),
+        };
+        self.amf0_references.push(object_id);
 
         self.current_layer += 1;
-        self.read_amf0_utf8(
+        let class_name = self.read_amf0_utf8(
             Some(format!(
                 "{}/{}",
                 AMF0_TYPED_OBJECT,
-                100 - self.current_layer * 20
+                100 - self.current_layer.min(4) * 20
             )),
             None,
         );
-        self.read_amf0_object(Some(object_id));
+        let members = self.read_amf0_members(object_id);
+        if self.current_layer != 0 {
+            self.current_layer -= 1;
+        }
+
+        self.insert_object(
+            object_id,
+            ObjectType::Amf0TypedObject(members),
+            Amf0TypedObjectProperties(class_name.into_owned()),
+        );
+    }
+
+    fn read_amf0_ecma_array(&mut self, object_id: isize) {
+        self.amf0_references.push(object_id);
+
+        // The count is only a hint, the members are terminated like an object's. It's kept so
+        // the array can be written back the way it came.
+        let count = self.read_u32(object_id, AMF0_ECMA_ARRAY);
+
+        self.current_layer += 1;
+        let members = self.read_amf0_members(object_id);
+        if self.current_layer != 0 {
+            self.current_layer -= 1;
+        }
+
+        self.insert_object(
+            object_id,
+            ObjectType::Amf0EcmaArray(members),
+            Amf0EcmaArrayProperties(count),
+        );
+    }
+
+    fn read_amf0_strict_array(&mut self, object_id: isize) {
+        self.amf0_references.push(object_id);
+
+        let count = self.read_u32(object_id, AMF0_STRICT_ARRAY);
+        self.check_count(count as usize);
+        let mut values = Vec::new();
+        for _ in 0..count {
+            if self.is_error {
+                break;
+            }
+            values.push(self.read_amf0());
+        }
+
+        self.insert_object(
+            object_id,
+            ObjectType::Amf0StrictArray(values),
+            AmfNoProperties,
+        );
+    }
+
+    fn read_amf0_date(&mut self, object_id: isize) {
+        let date = self.read_double(object_id, AMF0_DATE);
+        // Time zone, reserved and always zero
+        let syntax = SyntaxByte {
+            object_id,
+            color: AMF0_DATE.parse().unwrap(),
+        };
+        self.push_bytes(syntax, 2);
+
+        self.insert_object(object_id, ObjectType::Amf0Date(date), AmfNoProperties);
+    }
+
+    fn read_amf0_reference(&mut self, object_id: isize, start: usize) -> isize {
+        let syntax = SyntaxByte {
+            object_id,
+            color: AMF0_REFERENCE.parse().unwrap(),
+        };
+        let bytes = self.push_bytes(syntax, 2);
+        let index = match <[u8; 2]>::try_from(bytes) {
+            Ok(bytes) => u16::from_be_bytes(bytes) as usize,
+            Err(_) => return object_id,
+        };
+        let target = self.amf0_references.get(index).copied();
+        self.resolve_reference(object_id, start, target)
     }
 
     pub fn read_amf0(&mut self) -> isize {
+        let start = self.read_head;
+        let fresh = self.next_id();
+        if self.depth >= self.limits.max_depth {
+            self.exceed(Limit::Depth);
+            return -1;
+        }
+        self.depth += 1;
+        let object_id = self.read_amf0_value();
+        self.depth -= 1;
+        // References hand back an older object, which keeps the span it was read from
+        if object_id == fresh {
+            self.set_span(object_id, start);
+        }
+        object_id
+    }
+
+    fn read_amf0_value(&mut self) -> isize {
         if self.is_error {
             return -1;
         }
@@ -325,11 +690,11 @@ impl AMFReader {
                 return -1;
             }
         };
-        let object_id = self.objects.len() as isize;
+        let start = self.read_head - 1;
+        let object_id = self.reserve_object(Amf0Undefined);
         match current_byte {
             0x00 => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF0_NUMBER_MARKER.parse().unwrap(),
                 });
@@ -337,7 +702,6 @@ impl AMFReader {
             }
             0x01 => {
                 let mut syntax = SyntaxByte {
-                    value: current_byte,
                     object_id,
                     color: AMF0_BOOL_TRUE_MARKER.parse().unwrap(),
                 };
@@ -349,7 +713,6 @@ impl AMFReader {
             }
             0x02 => {
                 let syntax = SyntaxByte {
-                    value: current_byte,
                     object_id,
                     color: AMF0_STRING_MARKER.parse().unwrap(),
                 };
@@ -358,7 +721,6 @@ impl AMFReader {
             }
             0x03 => {
                 let syntax = SyntaxByte {
-                    value: current_byte,
                     object_id,
                     color: AMF0_OBJECT_MARKER.parse().unwrap(),
                 };
@@ -367,30 +729,75 @@ impl AMFReader {
             }
             0x05 | 0x06 => {
                 let syntax = SyntaxByte {
-                    value: current_byte,
                     object_id,
                     color: AMF0_NULL.parse().unwrap(),
                 };
-                let info = ObjectInfo {
-                    object_id,
-                    object_type: ObjectType::Amf0Null,
-                    object_properties: AmfNoProperties,
+                let object_type = if current_byte == 0x05 {
+                    ObjectType::Amf0Null
+                } else {
+                    ObjectType::Amf0Undefined
                 };
 
-                self.objects.insert(object_id, info);
+                self.insert_object(object_id, object_type, AmfNoProperties);
                 self.push_byte(syntax);
             }
-            // 0x07 => {}
-            // 0x08 => {}
-            // 0x09 => {}
-            // 0x0A => {}
-            // 0x0B => {}
-            // 0x0C => {}
-            // 0x0D => {}
-            // 0x0F => {}
+            0x07 => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_REFERENCE.parse().unwrap(),
+                });
+                return self.read_amf0_reference(object_id, start);
+            }
+            0x08 => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_ECMA_ARRAY_MARKER.parse().unwrap(),
+                });
+                self.read_amf0_ecma_array(object_id);
+            }
+            0x0A => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_STRICT_ARRAY_MARKER.parse().unwrap(),
+                });
+                self.read_amf0_strict_array(object_id);
+            }
+            0x0B => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_DATE_MARKER.parse().unwrap(),
+                });
+                self.read_amf0_date(object_id);
+            }
+            0x0C => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_LONG_STRING_MARKER.parse().unwrap(),
+                });
+                let out = self.read_amf0_long_utf8(AMF0_LONG_STRING, object_id);
+                self.insert_object(
+                    object_id,
+                    ObjectType::Amf0LongString(out),
+                    Amf0StringProperties,
+                );
+            }
+            0x0D => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_NULL.parse().unwrap(),
+                });
+                self.insert_object(object_id, ObjectType::Amf0Unsupported, AmfNoProperties);
+            }
+            0x0F => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF0_XML_MARKER.parse().unwrap(),
+                });
+                let out = self.read_amf0_long_utf8(AMF0_XML, object_id);
+                self.insert_object(object_id, ObjectType::Amf0XML(out), Amf0StringProperties);
+            }
             0x10 => {
                 let syntax = SyntaxByte {
-                    value: current_byte,
                     object_id,
                     color: AMF0_TYPED_OBJECT_MARKER.parse().unwrap(),
                 };
@@ -398,33 +805,20 @@ impl AMFReader {
                 self.read_amf0_typed_object(Some(object_id));
             }
             0x11 => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF0_SWITCH_MARKER.parse().unwrap(),
                 });
-                self.encoding = 3;
-                let info = ObjectInfo {
-                    object_id,
-                    object_type: ObjectType::Amf0Switch,
-                    object_properties: AmfNoProperties,
-                };
-
-                self.objects.insert(object_id, info);
+                // Only the value straight after the marker is AMF3
+                let value = self.read_amf3();
+                self.insert_object(object_id, ObjectType::Amf0Switch(value), AmfNoProperties);
             }
+            // 0x04 (MovieClip), 0x09 (object end) and 0x0E (RecordSet) can't start a value
             _ => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
-                    object_id,
-                    color: "text-ctp-red".parse().unwrap(),
-                });
-                let info = ObjectInfo {
-                    object_id,
-                    object_type: ObjectType::Amf0Undefined,
-                    object_properties: AmfNoProperties,
-                };
-
-                self.objects.insert(object_id, info);
+                tracing::error!("Reserved AMF0 marker {:#04X}", current_byte);
+                self.objects.remove(&object_id);
+                self.fail_from(start);
+                return -1;
             }
         }
         object_id
@@ -433,24 +827,22 @@ impl AMFReader {
     pub fn read_amf3_integer(&mut self, object_id: Option<isize>) -> i32 {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
         let syntax = SyntaxByte {
-            value: 0,
             object_id,
             color: AMF3_INTEGER.parse().unwrap(),
         };
-        let out = self.amf3_integer(syntax);
-        let info = ObjectInfo {
-            object_id,
-            object_type: ObjectType::Amf3Integer(out),
-            object_properties: AmfNoProperties,
-        };
-        self.objects.insert(object_id, info);
+        let mut out = self.amf3_integer(syntax);
+        // Integers are signed 29 bit, lengths and references are not
+        if out & 0x1000_0000 != 0 {
+            out -= 0x2000_0000;
+        }
+        self.insert_object(object_id, ObjectType::Amf3Integer(out), AmfNoProperties);
         out
     }
 
-    fn amf3_integer(&mut self, mut syntax: SyntaxByte) -> i32 {
+    fn amf3_integer(&mut self, syntax: SyntaxByte) -> i32 {
         let mut out: i32 = 0;
         let mut i = 0;
         let mut current_byte = match self.read_byte() {
@@ -460,8 +852,6 @@ impl AMFReader {
                 return -1;
             }
         };
-        syntax.value = current_byte;
-        self.out.push(syntax.clone());
 
         while current_byte & 0x80 != 0 && i < 3 {
             out = (out << 7) + (current_byte & 0x7F) as i32;
@@ -472,11 +862,9 @@ impl AMFReader {
                     return -1;
                 }
             };
-            let mut syntax = syntax.clone();
-            syntax.value = current_byte;
-            self.out.push(syntax);
             i += 1;
         }
+        self.push_run(syntax, i + 1);
         if i < 3 {
             out = (out << 7) | current_byte as i32;
         } else {
@@ -488,10 +876,9 @@ impl AMFReader {
     pub fn read_amf3_string_length(&mut self, object_id: Option<isize>) -> i32 {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
         let syntax = SyntaxByte {
-            value: 0,
             object_id,
             color: AMF3_INTEGER.parse().unwrap(),
         };
@@ -499,160 +886,402 @@ impl AMFReader {
         self.amf3_integer(syntax)
     }
 
-    pub fn read_amf3_string(&mut self, object_id: Option<isize>) -> String {
+    pub fn read_amf3_string(&mut self, object_id: Option<isize>) -> Cow<'a, str> {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
 
+        let start = self.read_head;
         let mut refe = self.read_amf3_string_length(Some(object_id));
+        if self.is_error {
+            return Cow::Borrowed("");
+        }
         let inline = (refe & 0x01) == 1;
         refe >>= 1;
         if inline {
-            if refe == 0 {
-                return String::from("");
-            }
-            let out = self.read_amf3_utf8(refe, Some(object_id));
-            let info = ObjectInfo {
-                object_id,
-                object_type: ObjectType::Amf3String(out.clone()),
-                object_properties: Amf3StringProperties(GenericProperties::new(
-                    false,
-                    out.len() as i32,
-                )),
+            let out = if refe == 0 {
+                // Empty strings are never added to the reference table
+                Cow::Borrowed("")
+            } else {
+                let text_start = self.read_head;
+                let out = self.read_amf3_utf8(refe, Some(object_id));
+                self.strings.push(text_start..self.read_head);
+                out
             };
-            self.objects.insert(object_id, info);
-            self.strings.push(out.clone());
+            self.insert_object(
+                object_id,
+                ObjectType::Amf3String(out.clone()),
+                Amf3StringProperties(GenericProperties::new(false, out.len() as i32)),
+            );
+            self.set_span(object_id, start);
             out
         } else {
-            let s: String = match self.strings.get(refe as usize) {
-                Some(s) => s.clone(),
-                None => String::from("String Not Found"),
+            let buffer = self.buffer;
+            let s = match self.strings.get(refe as usize) {
+                Some(range) => amf3_text(&buffer[range.clone()]),
+                None => {
+                    tracing::error!("String reference {} has not been read", refe);
+                    self.fail_from(start);
+                    return Cow::Borrowed("");
+                }
             };
 
-            let info = ObjectInfo {
+            self.insert_object(
                 object_id,
-                object_type: ObjectType::Amf3String(s.clone()),
-                object_properties: Amf3StringProperties(GenericProperties::new(true, refe)),
-            };
-            self.objects.insert(object_id, info);
+                ObjectType::Amf3String(s.clone()),
+                Amf3StringProperties(GenericProperties::new(true, refe)),
+            );
+            self.set_span(object_id, start);
             s
         }
     }
 
-    pub fn read_amf3_utf8(&mut self, length: i32, object_id: Option<isize>) -> String {
+    pub fn read_amf3_utf8(&mut self, length: i32, object_id: Option<isize>) -> Cow<'a, str> {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
+        if !self.check_string_length(length as usize) {
+            return Cow::Borrowed("");
+        }
         let syntax = SyntaxByte {
-            value: 0,
             object_id,
             color: AMF3_STRING.parse().unwrap(),
         };
-        let string_bytes = self.push_bytes(syntax, length as usize - 1);
-        let out = String::from_utf8(string_bytes.clone()).unwrap_or(format!("{:?}", string_bytes));
+        let string_bytes = self.push_bytes(syntax, length as usize);
+        amf3_text(string_bytes)
+    }
 
-        out
+    // XML, XMLDocument, Date, ByteArray and friends all start with the same U29 header,
+    // an object reference when the low bit is clear, otherwise a length or count
+    fn read_amf3_header(&mut self, object_id: isize, start: usize) -> Result<i32, isize> {
+        let header = self.read_amf3_string_length(Some(object_id));
+        if self.is_error {
+            return Err(object_id);
+        }
+        if header & 0x01 == 0 {
+            let target = self.amf3_references.get((header >> 1) as usize).copied();
+            return Err(self.resolve_reference(object_id, start, target));
+        }
+        self.amf3_references.push(object_id);
+        Ok(header >> 1)
     }
 
-    pub fn read_amf3_array(&mut self, object_id: Option<isize>) -> Vec<isize> {
+    fn read_amf3_xml(&mut self, object_id: isize, start: usize, is_document: bool) -> isize {
+        let length = match self.read_amf3_header(object_id, start) {
+            Ok(length) => length,
+            Err(id) => return id,
+        };
+        let out = self.read_utf8_bytes(length as usize, object_id, AMF3_XML.parse().unwrap());
+        let object_type = if is_document {
+            ObjectType::Amf3XMLDocument(out)
+        } else {
+            ObjectType::Amf3XML(out)
+        };
+        self.insert_object(object_id, object_type, AmfNoProperties);
+        object_id
+    }
+
+    fn read_amf3_date(&mut self, object_id: isize, start: usize) -> isize {
+        if let Err(id) = self.read_amf3_header(object_id, start) {
+            return id;
+        }
+        let date = self.read_double(object_id, AMF3_DATE);
+        self.insert_object(object_id, ObjectType::Amf3Date(date), AmfNoProperties);
+        object_id
+    }
+
+    fn read_amf3_byte_array(&mut self, object_id: isize, start: usize) -> isize {
+        let length = match self.read_amf3_header(object_id, start) {
+            Ok(length) => length,
+            Err(id) => return id,
+        };
+        let syntax = SyntaxByte {
+            object_id,
+            color: AMF3_BYTE_ARRAY.parse().unwrap(),
+        };
+        let bytes = self.push_bytes(syntax, length as usize);
+        self.insert_object(
+            object_id,
+            ObjectType::Amf3ByteArray(Cow::Borrowed(bytes)),
+            AmfNoProperties,
+        );
+        object_id
+    }
+
+    pub fn read_amf3_array(&mut self, object_id: Option<isize>, start: usize) -> isize {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
+            None => self.next_id(),
         };
 
-        let mut out = Vec::new();
-        let mut refer = self.read_amf3_string_length(Some(object_id));
-        refer >>= 1;
+        let refer = match self.read_amf3_header(object_id, start) {
+            Ok(refer) => refer,
+            Err(id) => return id,
+        };
 
+        let mut associative = Vec::new();
+        loop {
+            let key = self.read_amf3_string(None);
+            if key.is_empty() || self.is_error {
+                break;
+            }
+            if !self.check_count(associative.len() + 1) {
+                break;
+            }
+            associative.push((key.into_owned(), self.read_amf3()));
+        }
+        self.check_count(refer as usize);
+
+        let mut out = Vec::new();
         for _ in 0..refer {
+            if self.is_error {
+                break;
+            }
             let id = self.read_amf3();
             out.push(id);
         }
-        let obj = ObjectInfo {
+        self.insert_object(
             object_id,
-            object_type: Amf3Array(out.clone()),
-            object_properties: Amf3ArrayProperties(GenericProperties::new(false, refer)),
-        };
-        self.objects.insert(object_id, obj.clone());
-        out
+            Amf3Array(associative, out),
+            Amf3ArrayProperties(GenericProperties::new(false, refer)),
+        );
+        object_id
+    }
+
+    fn read_amf3_traits(&mut self, refer: i32, header_start: usize) -> Option<AmfObject> {
+        let is_inline_class_def = (refer & 0x01) != 0;
+        let refer = refer >> 1;
+        if !is_inline_class_def {
+            return match self.traits.get(refer as usize) {
+                Some(traits) => Some(AmfObject::new(traits.clone(), true)),
+                None => {
+                    tracing::error!("Traits reference {} has not been read", refer);
+                    self.fail_from(header_start);
+                    None
+                }
+            };
+        }
+
+        let encoding = refer & 0x03;
+        let mut traits = AmfTraits::new(
+            encoding,
+            (encoding & 0x01) != 0,
+            (encoding & 0x02) != 0,
+            String::new(),
+            Vec::new(),
+        );
+        traits.object_type = self.read_amf3_string(None).into_owned();
+        if !self.check_count((refer >> 2) as usize) {
+            return None;
+        }
+        for _ in 0..(refer >> 2) {
+            if self.is_error {
+                return None;
+            }
+            let key = self.read_amf3_string(None).into_owned();
+            traits.keys.push(key);
+        }
+        self.traits.push(traits.clone());
+        Some(AmfObject::new(traits, false))
     }
 
-    fn read_amf3_object(&mut self, object_id: Option<isize>) -> AmfObject {
+    fn read_amf3_object(&mut self, object_id: Option<isize>, start: usize) -> isize {
         let object_id: isize = match object_id {
             Some(id) => id,
-            None => self.objects.len() as isize,
-        };
-
-        let mut result = AmfObject::new(0, false, false, String::new(), HashMap::new());
-
-        let mut refer = self.read_amf3_string_length(Some(object_id));
-        refer >>= 1;
-        let is_reference = refer & 0x01 == 0;
-        if !is_reference {
-            let is_inline_class_def = (refer & 0x01) != 0;
-            refer >>= 1;
-            if is_inline_class_def {
-                result.encoding = refer & 0x03;
-                result.externalisable = (result.encoding & 0x01) != 0;
-                result.dynamic = ((refer >> 1) & 0x01) != 0;
-                result.property_count = refer as usize >> 2;
-                result.object_type = self.read_amf3_string(None);
-
-                for i in 0..result.property_count {
-                    let key = self.read_amf3_string(None);
-                    result.properties.insert(key, None);
-                }
+            None => self.next_id(),
+        };
+
+        let header_start = self.read_head;
+        let refer = match self.read_amf3_header(object_id, start) {
+            Ok(refer) => refer,
+            Err(id) => return id,
+        };
+        let mut result = match self.read_amf3_traits(refer, header_start) {
+            Some(result) => result,
+            None => return object_id,
+        };
+
+        if result.traits.externalisable {
+            if AMF3_PROXY_CLASSES.contains(&result.traits.object_type.as_str()) {
+                let id = self.read_amf3();
+                result.properties.push((String::from("source"), id));
             } else {
+                tracing::error!(
+                    "Cannot read externalizable class '{}'",
+                    result.traits.object_type
+                );
+                self.is_error = true;
             }
-            if result.externalisable {
-            } else {
-                for (_, (key, value)) in result.properties.iter_mut().enumerate() {
-                    *value = Some(self.read_amf3());
+        } else {
+            for key in result.traits.keys.clone() {
+                if self.is_error {
+                    break;
                 }
-                if result.dynamic {
-                    loop {
-                        if self.read_head == self.buffer.len() {
-                            break;
-                        }
-                        let byte = self.buffer[self.read_head];
-                        if byte == 1 {
-                            self.read_head += 1;
-                            break;
-                        }
-                        let key = self.read_amf3_string(None);
-                        result.properties.insert(key, Some(self.read_amf3()));
+                let id = self.read_amf3();
+                result.properties.push((key, id));
+            }
+            if result.traits.dynamic {
+                loop {
+                    if self.is_error {
+                        break;
                     }
+                    let key = self.read_amf3_string(None).into_owned();
+                    if key.is_empty() || !self.check_count(result.properties.len() + 1) {
+                        break;
+                    }
+                    let id = self.read_amf3();
+                    result.properties.push((key, id));
                 }
             }
-        } else {
         }
 
         let handle = &result;
-        let obj = ObjectInfo {
+        self.insert_object(
             object_id,
-            object_type: Amf3Object(handle.properties.clone()),
-            object_properties: Amf3ObjectProperties(ObjectProperties::new(
-                is_reference,
-                handle.properties.len(),
-                handle.encoding as usize,
-                handle.externalisable,
-                handle.dynamic,
-                handle.object_type.clone(),
+            Amf3Object(handle.properties.clone()),
+            Amf3ObjectProperties(ObjectProperties::new(
+                handle.is_reference,
+                handle.traits.keys.len(),
+                handle.traits.encoding as usize,
+                handle.traits.externalisable,
+                handle.traits.dynamic,
+                handle.traits.object_type.clone(),
             )),
+        );
+
+        object_id
+    }
+
+    fn read_amf3_vector(&mut self, marker: u8, object_id: isize, start: usize) -> isize {
+        let count = match self.read_amf3_header(object_id, start) {
+            Ok(count) => count,
+            Err(id) => return id,
         };
+        let is_fixed = match self.read_byte() {
+            Some(b) => *b,
+            None => {
+                self.is_error = true;
+                return object_id;
+            }
+        };
+        self.push_byte(SyntaxByte {
+            object_id,
+            color: AMF3_VECTOR.parse().unwrap(),
+        });
+        self.check_count(count as usize);
 
-        self.objects.insert(object_id, obj);
+        let mut object_type = String::new();
+        let vector = match marker {
+            0x0D => {
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    if self.is_error {
+                        break;
+                    }
+                    items.push(self.read_u32(object_id, AMF3_VECTOR) as i32);
+                }
+                ObjectType::Amf3VectorInt(items)
+            }
+            0x0E => {
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    if self.is_error {
+                        break;
+                    }
+                    items.push(self.read_u32(object_id, AMF3_VECTOR));
+                }
+                ObjectType::Amf3VectorUInt(items)
+            }
+            0x0F => {
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    if self.is_error {
+                        break;
+                    }
+                    items.push(self.read_double(object_id, AMF3_VECTOR));
+                }
+                ObjectType::Amf3VectorDouble(items)
+            }
+            _ => {
+                object_type = self.read_amf3_string(None).into_owned();
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    if self.is_error {
+                        break;
+                    }
+                    items.push(self.read_amf3());
+                }
+                ObjectType::Amf3VectorObject(items)
+            }
+        };
 
-        result
+        self.insert_object(
+            object_id,
+            vector,
+            Amf3VectorProperties(VectorProperties::new(is_fixed != 0, object_type)),
+        );
+        object_id
+    }
+
+    fn read_amf3_dictionary(&mut self, object_id: isize, start: usize) -> isize {
+        let count = match self.read_amf3_header(object_id, start) {
+            Ok(count) => count,
+            Err(id) => return id,
+        };
+        let weak_keys = match self.read_byte() {
+            Some(b) => *b,
+            None => {
+                self.is_error = true;
+                return object_id;
+            }
+        };
+        self.push_byte(SyntaxByte {
+            object_id,
+            color: AMF3_DICTIONARY.parse().unwrap(),
+        });
+        self.check_count(count as usize);
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            if self.is_error {
+                break;
+            }
+            let key = self.read_amf3();
+            let value = self.read_amf3();
+            entries.push((key, value));
+        }
+
+        self.insert_object(
+            object_id,
+            ObjectType::Amf3Dictionary(entries),
+            Amf3DictionaryProperties(DictionaryProperties::new(weak_keys != 0)),
+        );
+        object_id
     }
 
     pub fn read_amf3(&mut self) -> isize {
+        let start = self.read_head;
+        let fresh = self.next_id();
+        if self.depth >= self.limits.max_depth {
+            self.exceed(Limit::Depth);
+            return -1;
+        }
+        self.depth += 1;
+        let object_id = self.read_amf3_value();
+        self.depth -= 1;
+        // References hand back an older object, which keeps the span it was read from
+        if object_id == fresh {
+            self.set_span(object_id, start);
+        }
+        object_id
+    }
+
+    fn read_amf3_value(&mut self) -> isize {
         if self.is_error {
             return -1;
         }
-        let object_id = self.objects.len() as isize;
         let current_byte = match self.read_byte() {
             Some(byte) => *byte,
             None => {
@@ -660,141 +1289,370 @@ impl AMFReader {
                 return -1;
             }
         };
+        let start = self.read_head - 1;
+        let object_id = self.reserve_object(Amf3Undefined);
         match current_byte {
+            0x00 => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF3_UNDEFINED.parse().unwrap(),
+                });
+            }
             0x01 => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_NULL.parse().unwrap(),
                 });
-                let info = ObjectInfo {
-                    object_id,
-                    object_type: ObjectType::Amf3Null,
-                    object_properties: AmfNoProperties,
-                };
-                self.objects.insert(object_id, info);
+                self.insert_object(object_id, ObjectType::Amf3Null, AmfNoProperties);
             }
             0x02 => {
                 // False
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_FALSE.parse().unwrap(),
                 });
-                let info = ObjectInfo {
-                    object_id,
-                    object_type: ObjectType::Amf3False,
-                    object_properties: AmfNoProperties,
-                };
-                self.objects.insert(object_id, info);
+                self.insert_object(object_id, ObjectType::Amf3False, AmfNoProperties);
             }
             0x03 => {
                 // True
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_TRUE.parse().unwrap(),
                 });
-                let info = ObjectInfo {
-                    object_id,
-                    object_type: ObjectType::Amf3True,
-                    object_properties: AmfNoProperties,
-                };
-                self.objects.insert(object_id, info);
+                self.insert_object(object_id, ObjectType::Amf3True, AmfNoProperties);
             }
             0x04 => {
                 // Integer
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_INTEGER.parse().unwrap(),
                 });
                 self.read_amf3_integer(Some(object_id));
             }
             0x05 => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF0_NUMBER_MARKER.parse().unwrap(),
                 });
-                self.read_amf0_integer(Some(object_id));
+                let number = self.read_double(object_id, AMF3_DOUBLE);
+                self.insert_object(object_id, ObjectType::Amf3Double(number), AmfNoProperties);
             }
             0x06 => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_STRING.parse().unwrap(),
                 });
                 self.read_amf3_string(Some(object_id));
             }
+            0x07 | 0x0B => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF3_XML.parse().unwrap(),
+                });
+                return self.read_amf3_xml(object_id, start, current_byte == 0x07);
+            }
             0x08 => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF3_DATE.parse().unwrap(),
+                });
+                return self.read_amf3_date(object_id, start);
+            }
+            0x09 => {
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_ARRAY.parse().unwrap(),
                 });
-                self.read_amf3_array(Some(object_id));
+                return self.read_amf3_array(Some(object_id), start);
             }
             0x0A => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+                self.push_byte(SyntaxByte {
                     object_id,
                     color: AMF3_OBJECT.parse().unwrap(),
                 });
-                self.read_amf3_object(Some(object_id));
+                return self.read_amf3_object(Some(object_id), start);
             }
-            _ => {
-                self.out.push(SyntaxByte {
-                    value: current_byte,
+            0x0C => {
+                self.push_byte(SyntaxByte {
                     object_id,
-                    color: "text-ctp-maroon".parse().unwrap(),
+                    color: AMF3_BYTE_ARRAY.parse().unwrap(),
                 });
-                let info = ObjectInfo {
+                return self.read_amf3_byte_array(object_id, start);
+            }
+            0x0D..=0x10 => {
+                self.push_byte(SyntaxByte {
                     object_id,
-                    object_type: ObjectType::Amf3Undefined,
-                    object_properties: AmfNoProperties,
-                };
-                self.objects.insert(object_id, info);
+                    color: AMF3_VECTOR.parse().unwrap(),
+                });
+                return self.read_amf3_vector(current_byte, object_id, start);
+            }
+            0x11 => {
+                self.push_byte(SyntaxByte {
+                    object_id,
+                    color: AMF3_DICTIONARY.parse().unwrap(),
+                });
+                return self.read_amf3_dictionary(object_id, start);
+            }
+            // Nothing past Dictionary (0x11) has been defined
+            _ => {
+                tracing::error!("Unknown AMF3 marker {:#04X}", current_byte);
+                self.objects.remove(&object_id);
+                self.fail_from(start);
+                return -1;
             }
         }
         object_id
     }
 
-    pub fn read_byte(&mut self) -> Option<&u8> {
+    pub fn read_byte(&mut self) -> Option<&'a u8> {
         let b = self.buffer.get(self.read_head);
-        if b.is_some() {
-            self.read_head += 1;
+        match b {
+            Some(_) => self.read_head += 1,
+            None => self.truncated = true,
         }
         b
     }
 
-    pub fn read_bytes(&mut self, len: usize) -> Option<&[u8]> {
-        let b = (&self.buffer).get(self.read_head..self.read_head + len);
-        if b.is_some() {
-            self.read_head += len;
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let b = self.buffer.get(self.read_head..self.read_head + len);
+        match b {
+            Some(_) => self.read_head += len,
+            None => self.truncated = true,
         }
         b
     }
 
     pub fn push_byte(&mut self, syntax_byte: SyntaxByte) {
-        self.out.push(syntax_byte);
+        self.push_run(syntax_byte, 1);
     }
 
-    pub fn push_bytes(&mut self, syntax_byte: SyntaxByte, len: usize) -> Vec<u8> {
-        let mut arr = Vec::new();
-        for _ in 0..=len {
-            let mut copy = syntax_byte.clone();
-            let byte = match self.read_byte() {
-                Some(byte) => *byte,
-                None => {
-                    self.is_error = true;
-                    break;
-                }
-            };
-            copy.value = byte;
-            arr.push(byte);
-            self.push_byte(copy);
+    // Colours the next len bytes, joining onto the last span when it is the same object and colour
+    fn push_run(&mut self, syntax_byte: SyntaxByte, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let start = self.highlight_head;
+        self.highlight_head += len;
+        if let Some(progress) = self.progress {
+            progress.store(self.highlight_head, Ordering::Relaxed);
+        }
+        if let Some(last) = self.out.last_mut() {
+            if last.end == start
+                && last.object_id == syntax_byte.object_id
+                && last.color == syntax_byte.color
+            {
+                last.end = self.highlight_head;
+                return;
+            }
+        }
+        self.out.push(SyntaxSpan {
+            start,
+            end: self.highlight_head,
+            object_id: syntax_byte.object_id,
+            color: syntax_byte.color,
+        });
+    }
+
+    // Reads and colours len bytes, or as many as there are left if the buffer runs out
+    pub fn push_bytes(&mut self, syntax_byte: SyntaxByte, len: usize) -> &'a [u8] {
+        let available = self.buffer.len().saturating_sub(self.read_head).min(len);
+        if available < len {
+            self.is_error = true;
+            self.truncated = true;
+        }
+        let bytes = match self.read_bytes(available) {
+            Some(bytes) => bytes,
+            None => return &[],
+        };
+        if available > 0 {
+            self.push_run(syntax_byte, available);
+        }
+        bytes
+    }
+
+    // Sets the span of a freshly read object to everything read since start
+    fn set_span(&mut self, object_id: isize, start: usize) {
+        let end = self.read_head;
+        if let Some(info) = self.objects.get_mut(&object_id) {
+            info.span = start..end;
+        }
+    }
+
+    /// The bytes an object was decoded from, marker included. References share the span of
+    /// the object they point at.
+    pub fn bytes(&self, object_id: isize) -> Option<&'a [u8]> {
+        let info = self.objects.get(&object_id)?;
+        self.buffer.get(info.span.clone())
+    }
+
+    /// A string value as a slice of the input, with the input's lifetime rather than the
+    /// reader's. AMF3 string references point at the bytes of the original string. None for
+    /// anything else, or text that wasn't valid UTF-8.
+    pub fn str(&self, object_id: isize) -> Option<&'a str> {
+        match &self.objects.get(&object_id)?.object_type {
+            ObjectType::Amf0String(Cow::Borrowed(s))
+            | ObjectType::Amf0LongString(Cow::Borrowed(s))
+            | ObjectType::Amf0XML(Cow::Borrowed(s))
+            | ObjectType::Amf3String(Cow::Borrowed(s))
+            | ObjectType::Amf3XML(Cow::Borrowed(s))
+            | ObjectType::Amf3XMLDocument(Cow::Borrowed(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The contents of a ByteArray as a slice of the input.
+    pub fn byte_array(&self, object_id: isize) -> Option<&'a [u8]> {
+        match &self.objects.get(&object_id)?.object_type {
+            ObjectType::Amf3ByteArray(Cow::Borrowed(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_reaches_end() {
+        let bytes = vec![
+            0x02, 0x00, 0x03, b'a', b'b', b'c', 0x00, 0x3F, 0xF0, 0, 0, 0, 0, 0, 0,
+        ];
+        let progress = AtomicUsize::new(0);
+        let mut reader = AMFReader::new(&bytes, false).with_progress(&progress);
+        reader.highlight();
+        assert_eq!(progress.load(Ordering::Relaxed), bytes.len());
+    }
+
+    #[test]
+    fn spans_and_borrowed_values() {
+        // "abc", then a reference back to it, then a two byte ByteArray
+        let bytes = vec![
+            0x11, 0x06, 0x07, b'a', b'b', b'c', 0x11, 0x06, 0x00, 0x11, 0x0C, 0x05, 0xDE, 0xAD,
+        ];
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        assert!(!reader.objects.contains_key(&-1));
+
+        let inner: Vec<isize> = reader
+            .roots
+            .iter()
+            .map(|id| match reader.objects[id].object_type {
+                ObjectType::Amf0Switch(inner) => inner,
+                _ => panic!("expected a switch"),
+            })
+            .collect();
+        assert_eq!(reader.objects[&reader.roots[0]].span, 0..6);
+        assert_eq!(reader.objects[&inner[0]].span, 1..6);
+        assert_eq!(reader.str(inner[0]), Some("abc"));
+        assert_eq!(reader.str(inner[1]), Some("abc"));
+        assert_eq!(reader.bytes(inner[1]), Some(&bytes[7..9]));
+        assert_eq!(reader.byte_array(inner[2]), Some(&bytes[12..]));
+        // Nothing was copied out of the input
+        assert!(matches!(
+            reader.objects[&inner[2]].object_type,
+            ObjectType::Amf3ByteArray(Cow::Borrowed(_))
+        ));
+
+        // Spans are in order and cover every byte exactly once
+        let mut head = 0;
+        for span in &reader.out {
+            assert_eq!(span.start, head);
+            assert!(span.end > span.start);
+            head = span.end;
+        }
+        assert_eq!(head, bytes.len());
+    }
+
+    #[test]
+    fn dangling_references() {
+        // "a", then an AMF0 reference to object 5 which was never read
+        let bytes = [0x02, 0x00, 0x01, b'a', 0x07, 0x00, 0x05];
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        assert_eq!(reader.roots.len(), 1);
+        assert_eq!(reader.objects[&-1].span, 4..7);
+        assert_eq!(reader.out.last().unwrap().start, 4);
+
+        // AMF3 object reference 1 with nothing in the table
+        let mut reader = AMFReader::new(&[0x11, 0x0A, 0x02], false);
+        reader.highlight();
+        assert!(reader.roots.is_empty());
+        assert_eq!(reader.objects[&-1].span, 1..3);
+
+        // AMF3 string reference 0, and the same as an object's class name
+        for (bytes, start) in [(&[0x11, 0x06, 0x00][..], 2), (&[0x11, 0x0A, 0x03, 0x00], 3)] {
+            let mut reader = AMFReader::new(bytes, false);
+            reader.highlight();
+            assert!(reader.roots.is_empty());
+            assert_eq!(reader.objects[&-1].span, start..bytes.len());
+        }
+    }
+
+    #[test]
+    fn reserved_markers() {
+        for marker in [0x04, 0x09, 0x0E] {
+            let bytes = [0x05, marker];
+            let mut reader = AMFReader::new(&bytes, false);
+            reader.highlight();
+            assert_eq!(reader.roots, [0]);
+            assert_eq!(reader.objects.len(), 2);
+            assert_eq!(reader.objects[&-1].span, 1..2);
+        }
+    }
+
+    fn exceeded(bytes: &[u8], limits: DecodeLimits) -> Option<(Limit, usize)> {
+        let mut reader = AMFReader::new(bytes, false).with_limits(limits);
+        reader.highlight();
+        if reader.limit_exceeded().is_some() {
+            assert!(reader.objects.contains_key(&-1));
+        }
+        reader.limit_exceeded()
+    }
+
+    #[test]
+    fn limits() {
+        // A hundred thousand arrays inside each other would blow the stack without a depth limit
+        let mut deep = vec![0x11];
+        for _ in 0..100_000 {
+            deep.extend_from_slice(&[0x09, 0x03, 0x01]);
         }
-        arr
+        assert_eq!(
+            exceeded(&deep, DecodeLimits::default()).map(|e| e.0),
+            Some(Limit::Depth)
+        );
+
+        // Strict array claiming four billion items
+        let huge = [0x0A, 0xFF, 0xFF, 0xFF, 0xFF, 0x05];
+        assert_eq!(
+            exceeded(&huge, DecodeLimits::default()),
+            Some((Limit::Elements, 5))
+        );
+
+        let small = DecodeLimits {
+            max_depth: 2,
+            max_elements: 2,
+            max_string_length: 3,
+            max_objects: 4,
+            ..DecodeLimits::default()
+        };
+        let string = [0x02, 0x00, 0x04, b'a', b'b', b'c', b'd'];
+        assert_eq!(exceeded(&string, small), Some((Limit::StringLength, 3)));
+        let amf3_string = [0x11, 0x06, 0x09, b'a', b'b', b'c', b'd'];
+        assert_eq!(
+            exceeded(&amf3_string, small),
+            Some((Limit::StringLength, 3))
+        );
+        let object = [
+            0x03, 0x00, 0x01, b'a', 0x05, 0x00, 0x01, b'b', 0x05, 0x00, 0x00, 0x09,
+        ];
+        assert_eq!(exceeded(&object, small).map(|e| e.0), Some(Limit::Objects));
+        let nested = [
+            0x0A, 0x00, 0x00, 0x00, 0x01, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x05,
+        ];
+        assert_eq!(exceeded(&nested, small), Some((Limit::Depth, 10)));
+
+        // Everything fits
+        assert_eq!(exceeded(&[0x02, 0x00, 0x03, b'a', b'b', b'c'], small), None);
     }
 }
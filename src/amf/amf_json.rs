@@ -0,0 +1,959 @@
+use crate::amf::amf_writer::{AMFWriter, EncodeError};
+use crate::amf::object_info::ObjectInfo;
+use crate::amf::object_properties::{
+    DictionaryProperties, GenericProperties, ObjectProperties, TypeProperties, VectorProperties,
+};
+use crate::amf::object_type::ObjectType;
+use serde_json::{json, Map, Number, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Formatter;
+
+// Keys we add to objects, the $ keeps them apart from the members
+const TYPE: &str = "$type";
+const ID: &str = "$id";
+const REF: &str = "$ref";
+const CLASS: &str = "$class";
+
+// Members whose name starts with $ get another one in front, so they can't be taken for ours
+fn escape(key: &str) -> String {
+    if key.starts_with('$') {
+        format!("${}", key)
+    } else {
+        key.to_string()
+    }
+}
+
+fn unescape(key: &str) -> String {
+    match key.strip_prefix('$') {
+        Some(rest) if rest.starts_with('$') => rest.to_string(),
+        _ => key.to_string(),
+    }
+}
+
+fn type_name(object_type: &ObjectType) -> &'static str {
+    match object_type {
+        ObjectType::Amf0Number(_) => "Amf0Number",
+        ObjectType::Amf0Bool(_) => "Amf0Bool",
+        ObjectType::Amf0String(_) => "Amf0String",
+        ObjectType::Amf0Object(_) => "Amf0Object",
+        ObjectType::Amf0Null => "Amf0Null",
+        ObjectType::Amf0Undefined => "Amf0Undefined",
+        ObjectType::Amf0EcmaArray(_) => "Amf0EcmaArray",
+        ObjectType::Amf0StrictArray(_) => "Amf0StrictArray",
+        ObjectType::Amf0Date(_) => "Amf0Date",
+        ObjectType::Amf0LongString(_) => "Amf0LongString",
+        ObjectType::Amf0Unsupported => "Amf0Unsupported",
+        ObjectType::Amf0XML(_) => "Amf0XML",
+        ObjectType::Amf0TypedObject(_) => "Amf0TypedObject",
+        ObjectType::Amf0Switch(_) => "Amf0Switch",
+        ObjectType::Amf3Undefined => "Amf3Undefined",
+        ObjectType::Amf3Null => "Amf3Null",
+        ObjectType::Amf3False => "Amf3False",
+        ObjectType::Amf3True => "Amf3True",
+        ObjectType::Amf3Integer(_) => "Amf3Integer",
+        ObjectType::Amf3Double(_) => "Amf3Double",
+        ObjectType::Amf3String(_) => "Amf3String",
+        ObjectType::Amf3XMLDocument(_) => "Amf3XMLDocument",
+        ObjectType::Amf3Date(_) => "Amf3Date",
+        ObjectType::Amf3Array(..) => "Amf3Array",
+        ObjectType::Amf3Object(_) => "Amf3Object",
+        ObjectType::Amf3XML(_) => "Amf3XML",
+        ObjectType::Amf3ByteArray(_) => "Amf3ByteArray",
+        ObjectType::Amf3VectorInt(_) => "Amf3VectorInt",
+        ObjectType::Amf3VectorUInt(_) => "Amf3VectorUInt",
+        ObjectType::Amf3VectorDouble(_) => "Amf3VectorDouble",
+        ObjectType::Amf3VectorObject(_) => "Amf3VectorObject",
+        ObjectType::Amf3Dictionary(_) => "Amf3Dictionary",
+    }
+}
+
+// JSON has no NaN or infinities, annotated output spells them out so they survive
+fn number(value: f64, annotate: bool) -> Value {
+    match Number::from_f64(value) {
+        Some(number) => Value::Number(number),
+        None if annotate => Value::String(value.to_string()),
+        None => Value::Null,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+struct Exporter<'a> {
+    objects: &'a HashMap<isize, ObjectInfo<'a>>,
+    annotate: bool,
+    shared: HashSet<isize>, // Containers reached more than once, these get an $id
+    written: HashSet<isize>, // Shared containers already written, the rest become a $ref
+}
+
+impl Exporter<'_> {
+    // Counts how often every container is reached so only the shared ones get an $id
+    fn find_shared(&mut self, object_id: isize, seen: &mut HashSet<isize>) {
+        let Some(info) = self.objects.get(&object_id) else {
+            return;
+        };
+        let children = info.object_type.children();
+        if children.is_empty() && !by_reference(&info.object_type, self.annotate) {
+            return;
+        }
+        if !seen.insert(object_id) {
+            self.shared.insert(object_id);
+            return;
+        }
+        for (_, child) in children {
+            self.find_shared(child, seen);
+        }
+    }
+
+    fn members(&mut self, members: &[(String, isize)]) -> Map<String, Value> {
+        members
+            .iter()
+            .map(|(key, id)| (escape(key), self.value(*id)))
+            .collect()
+    }
+
+    fn values(&mut self, values: &[isize]) -> Vec<Value> {
+        values.iter().map(|id| self.value(*id)).collect()
+    }
+
+    fn value(&mut self, object_id: isize) -> Value {
+        let Some(info) = self.objects.get(&object_id) else {
+            return Value::Null;
+        };
+        if self.shared.contains(&object_id) && !self.written.insert(object_id) {
+            return json!({ REF: object_id });
+        }
+        let annotate = self.annotate;
+        let class = info.object_properties.class_name();
+
+        // What the value is without any annotation, and the extra keys the annotated form needs
+        let mut extra = Map::new();
+        let value = match &info.object_type {
+            ObjectType::Amf0Switch(inner) => return self.value(*inner),
+            ObjectType::Amf0Number(value)
+            | ObjectType::Amf3Double(value)
+            | ObjectType::Amf0Date(value)
+            | ObjectType::Amf3Date(value) => number(*value, annotate),
+            ObjectType::Amf3Integer(value) => json!(value),
+            ObjectType::Amf0Bool(value) => json!(value),
+            ObjectType::Amf3True => json!(true),
+            ObjectType::Amf3False => json!(false),
+            ObjectType::Amf0String(value)
+            | ObjectType::Amf0LongString(value)
+            | ObjectType::Amf0XML(value)
+            | ObjectType::Amf3String(value)
+            | ObjectType::Amf3XML(value)
+            | ObjectType::Amf3XMLDocument(value) => json!(value),
+            ObjectType::Amf0Null
+            | ObjectType::Amf0Undefined
+            | ObjectType::Amf0Unsupported
+            | ObjectType::Amf3Null
+            | ObjectType::Amf3Undefined => Value::Null,
+            ObjectType::Amf3ByteArray(value) => json!(hex(value)),
+            ObjectType::Amf3VectorInt(value) => json!(value),
+            ObjectType::Amf3VectorUInt(value) => json!(value),
+            ObjectType::Amf3VectorDouble(value) => {
+                Value::Array(value.iter().map(|v| number(*v, annotate)).collect())
+            }
+            ObjectType::Amf0Object(members)
+            | ObjectType::Amf0EcmaArray(members)
+            | ObjectType::Amf0TypedObject(members) => Value::Object(self.members(members)),
+            ObjectType::Amf3Object(members) => {
+                if let TypeProperties::Amf3ObjectProperties(prop) = &info.object_properties {
+                    extra.insert(String::from("$sealed"), json!(prop.property_count));
+                    extra.insert(String::from("$dynamic"), json!(prop.dynamic));
+                    if prop.externalisable {
+                        extra.insert(String::from("$externalizable"), json!(true));
+                    }
+                }
+                Value::Object(self.members(members))
+            }
+            ObjectType::Amf0StrictArray(values) | ObjectType::Amf3VectorObject(values) => {
+                Value::Array(self.values(values))
+            }
+            ObjectType::Amf3Array(associative, dense) => {
+                let associative = self.members(associative);
+                let dense = self.values(dense);
+                if annotate {
+                    json!({ "associative": associative, "dense": dense })
+                } else if associative.is_empty() {
+                    Value::Array(dense)
+                } else {
+                    // Like ActionScript does, the dense part becomes index keys
+                    let mut all = associative;
+                    all.extend(
+                        dense
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, v)| (i.to_string(), v)),
+                    );
+                    Value::Object(all)
+                }
+            }
+            ObjectType::Amf3Dictionary(entries) => Value::Array(
+                entries
+                    .iter()
+                    .map(|(key, value)| json!([self.value(*key), self.value(*value)]))
+                    .collect(),
+            ),
+        };
+        match &info.object_properties {
+            TypeProperties::Amf3VectorProperties(prop) => {
+                extra.insert(String::from("$fixed"), json!(prop.is_fixed));
+            }
+            TypeProperties::Amf3DictionaryProperties(prop) => {
+                extra.insert(String::from("$weakKeys"), json!(prop.weak_keys));
+            }
+            TypeProperties::Amf0EcmaArrayProperties(count) => {
+                extra.insert(String::from("$count"), json!(count));
+            }
+            _ => {}
+        }
+
+        let id = self.shared.contains(&object_id).then_some(object_id);
+        if annotate {
+            let mut out = Map::new();
+            out.insert(String::from(TYPE), json!(type_name(&info.object_type)));
+            if let Some(id) = id {
+                out.insert(String::from(ID), json!(id));
+            }
+            if let Some(class) = class {
+                out.insert(String::from(CLASS), json!(class));
+            }
+            out.extend(extra);
+            out.insert(String::from("value"), value);
+            return Value::Object(out);
+        }
+        match value {
+            // Plain objects carry their class and $id next to the members
+            Value::Object(members) if id.is_some() || class.is_some() => {
+                let mut out = Map::new();
+                if let Some(id) = id {
+                    out.insert(String::from(ID), json!(id));
+                }
+                if let Some(class) = class {
+                    out.insert(String::from(CLASS), json!(class));
+                }
+                out.extend(members);
+                Value::Object(out)
+            }
+            // Arrays have nowhere to put them, so they get wrapped
+            Value::Array(items) if id.is_some() || class.is_some() => {
+                let mut out = Map::new();
+                if let Some(id) = id {
+                    out.insert(String::from(ID), json!(id));
+                }
+                if let Some(class) = class {
+                    out.insert(String::from(CLASS), json!(class));
+                }
+                out.insert(String::from("$items"), Value::Array(items));
+                Value::Object(out)
+            }
+            value => value,
+        }
+    }
+}
+
+// Anything that can be sent by reference, even when it holds nothing right now. AMF3 dates,
+// byte arrays and XML are too, but plain JSON has nowhere to put their $id
+fn by_reference(object_type: &ObjectType, annotate: bool) -> bool {
+    match object_type {
+        ObjectType::Amf3Date(_)
+        | ObjectType::Amf3ByteArray(_)
+        | ObjectType::Amf3XML(_)
+        | ObjectType::Amf3XMLDocument(_) => annotate,
+        object_type => matches!(
+            object_type,
+            ObjectType::Amf0Object(_)
+                | ObjectType::Amf0EcmaArray(_)
+                | ObjectType::Amf0StrictArray(_)
+                | ObjectType::Amf0TypedObject(_)
+                | ObjectType::Amf3Array(..)
+                | ObjectType::Amf3Object(_)
+                | ObjectType::Amf3VectorInt(_)
+                | ObjectType::Amf3VectorUInt(_)
+                | ObjectType::Amf3VectorDouble(_)
+                | ObjectType::Amf3VectorObject(_)
+                | ObjectType::Amf3Dictionary(_)
+        ),
+    }
+}
+
+/// Turns decoded values into a JSON array, one element per root. Containers reached more than
+/// once are written the first time with an `$id` and after that as `{"$ref": id}`, class names
+/// go in `$class`. With `annotate` every value becomes `{"$type": "Amf3Integer", "value": 5}`
+/// so the exact AMF types can be rebuilt from it.
+pub fn to_json(objects: &HashMap<isize, ObjectInfo>, roots: &[isize], annotate: bool) -> Value {
+    let mut exporter = Exporter {
+        objects,
+        annotate,
+        shared: HashSet::new(),
+        written: HashSet::new(),
+    };
+    let mut seen = HashSet::new();
+    for root in roots {
+        exporter.find_shared(*root, &mut seen);
+    }
+    Value::Array(roots.iter().map(|root| exporter.value(*root)).collect())
+}
+
+/// Which AMF values are written as, for plain JSON without a `$type` and for serde.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AmfVersion {
+    #[default]
+    Amf0,
+    Amf3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Yaml,
+}
+
+impl ExportFormat {
+    /// YAML for .yaml and .yml files, JSON for anything else.
+    pub fn from_extension(extension: Option<&str>) -> Self {
+        match extension {
+            Some("yaml") | Some("yml") => ExportFormat::Yaml,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+/// to_json, pretty printed in the chosen format.
+pub fn export(
+    objects: &HashMap<isize, ObjectInfo>,
+    roots: &[isize],
+    annotate: bool,
+    format: ExportFormat,
+) -> String {
+    let value = to_json(objects, roots, annotate);
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&value).unwrap() + "\n",
+        ExportFormat::Yaml => serde_yaml::to_string(&value).unwrap(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportError {
+    Parse(String),
+    UnknownType(String),
+    Invalid(String, String), // Type, what was wrong with it
+    UnknownReference(i64),
+    Encode(EncodeError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Parse(e) => write!(f, "Could not parse the document: {}", e),
+            ImportError::UnknownType(name) => write!(f, "Unknown $type {:?}", name),
+            ImportError::Invalid(name, reason) => write!(f, "Invalid {}: {}", name, reason),
+            ImportError::UnknownReference(id) => {
+                write!(f, "$ref {} does not match an earlier $id", id)
+            }
+            ImportError::Encode(e) => write!(f, "Could not encode AMF: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<EncodeError> for ImportError {
+    fn from(e: EncodeError) -> Self {
+        ImportError::Encode(e)
+    }
+}
+
+fn invalid(name: &str, reason: &str) -> ImportError {
+    ImportError::Invalid(name.to_string(), reason.to_string())
+}
+
+// The reverse of Exporter, builds the object map AMFWriter takes
+struct Importer {
+    objects: HashMap<isize, ObjectInfo<'static>>,
+    ids: HashMap<i64, isize>, // $id in the document to our object id
+    next_id: isize,
+}
+
+impl Importer {
+    fn reserve(&mut self) -> isize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn insert(
+        &mut self,
+        object_id: isize,
+        object_type: ObjectType<'static>,
+        object_properties: TypeProperties,
+    ) -> isize {
+        self.objects.insert(
+            object_id,
+            ObjectInfo::detached(object_id, object_type, object_properties),
+        );
+        object_id
+    }
+
+    fn leaf(&mut self, object_type: ObjectType<'static>) -> isize {
+        let object_id = self.reserve();
+        self.insert(object_id, object_type, TypeProperties::AmfNoProperties)
+    }
+
+    // Reserves an id and remembers the $id the document gave it before any members are read, so
+    // members can refer back to it
+    fn declare(&mut self, map: &Map<String, Value>) -> Result<isize, ImportError> {
+        let object_id = self.reserve();
+        if let Some(id) = map.get(ID) {
+            let id = id.as_i64().ok_or_else(|| invalid(ID, "not an integer"))?;
+            self.ids.insert(id, object_id);
+        }
+        Ok(object_id)
+    }
+
+    fn members(
+        &mut self,
+        map: &Map<String, Value>,
+        amf3: bool,
+    ) -> Result<Vec<(String, isize)>, ImportError> {
+        // Plain objects keep their $id and $class next to the members
+        map.iter()
+            .filter(|(key, _)| *key != ID && *key != CLASS)
+            .map(|(key, value)| Ok((unescape(key), self.value(value, amf3)?)))
+            .collect()
+    }
+
+    fn values(&mut self, values: &[Value], amf3: bool) -> Result<Vec<isize>, ImportError> {
+        values.iter().map(|value| self.value(value, amf3)).collect()
+    }
+
+    // amf3 is whether we are inside an AMF3 value, plain values are read as that version
+    fn value(&mut self, value: &Value, amf3: bool) -> Result<isize, ImportError> {
+        match value {
+            Value::Null if amf3 => Ok(self.leaf(ObjectType::Amf3Null)),
+            Value::Null => Ok(self.leaf(ObjectType::Amf0Null)),
+            Value::Bool(true) if amf3 => Ok(self.leaf(ObjectType::Amf3True)),
+            Value::Bool(false) if amf3 => Ok(self.leaf(ObjectType::Amf3False)),
+            Value::Bool(value) => Ok(self.leaf(ObjectType::Amf0Bool(*value))),
+            Value::Number(number) => Ok(match number.as_i64() {
+                // Numbers written without a fraction are integers, like export writes them
+                Some(value) if amf3 && (-0x1000_0000..0x1000_0000).contains(&value) => {
+                    self.leaf(ObjectType::Amf3Integer(value as i32))
+                }
+                _ if amf3 => self.leaf(ObjectType::Amf3Double(number.as_f64().unwrap_or(0.0))),
+                _ => self.leaf(ObjectType::Amf0Number(number.as_f64().unwrap_or(0.0))),
+            }),
+            Value::String(value) if amf3 => {
+                Ok(self.leaf(ObjectType::Amf3String(value.clone().into())))
+            }
+            Value::String(value) => Ok(self.leaf(ObjectType::Amf0String(value.clone().into()))),
+            Value::Array(values) => {
+                let object_id = self.reserve();
+                self.array(object_id, values, None, amf3)
+            }
+            Value::Object(map) => {
+                if let Some(id) = map.get(REF) {
+                    let id = id.as_i64().ok_or_else(|| invalid(REF, "not an integer"))?;
+                    return self
+                        .ids
+                        .get(&id)
+                        .copied()
+                        .ok_or(ImportError::UnknownReference(id));
+                }
+                if let Some(name) = map.get(TYPE) {
+                    let name = name.as_str().ok_or_else(|| invalid(TYPE, "not a string"))?;
+                    return self.annotated(name, map);
+                }
+                let object_id = self.declare(map)?;
+                let class = map.get(CLASS).and_then(Value::as_str);
+                if let Some(items) = map.get("$items") {
+                    let items = items
+                        .as_array()
+                        .ok_or_else(|| invalid("$items", "not an array"))?;
+                    return self.array(object_id, items, class, amf3);
+                }
+                let members = self.members(map, amf3)?;
+                let count = members.len();
+                Ok(match (amf3, class) {
+                    // Classes sent from Flex are almost always sealed, anonymous ones dynamic
+                    (true, Some(class)) => self.insert(
+                        object_id,
+                        ObjectType::Amf3Object(members),
+                        TypeProperties::Amf3ObjectProperties(ObjectProperties::new(
+                            false,
+                            count,
+                            0x00,
+                            false,
+                            false,
+                            class.to_string(),
+                        )),
+                    ),
+                    (true, None) => self.insert(
+                        object_id,
+                        ObjectType::Amf3Object(members),
+                        TypeProperties::Amf3ObjectProperties(ObjectProperties::new(
+                            false,
+                            0,
+                            0x02,
+                            false,
+                            true,
+                            String::new(),
+                        )),
+                    ),
+                    (false, Some(class)) => self.insert(
+                        object_id,
+                        ObjectType::Amf0TypedObject(members),
+                        TypeProperties::Amf0TypedObjectProperties(class.to_string()),
+                    ),
+                    (false, None) => self.insert(
+                        object_id,
+                        ObjectType::Amf0Object(members),
+                        TypeProperties::Amf0ObjectProperties,
+                    ),
+                })
+            }
+        }
+    }
+
+    fn array(
+        &mut self,
+        object_id: isize,
+        values: &[Value],
+        class: Option<&str>,
+        amf3: bool,
+    ) -> Result<isize, ImportError> {
+        let values = self.values(values, amf3)?;
+        Ok(match (amf3, class) {
+            (true, Some(class)) => self.insert(
+                object_id,
+                ObjectType::Amf3VectorObject(values),
+                TypeProperties::Amf3VectorProperties(VectorProperties::new(
+                    false,
+                    class.to_string(),
+                )),
+            ),
+            (true, None) => {
+                let count = values.len() as i32;
+                self.insert(
+                    object_id,
+                    ObjectType::Amf3Array(Vec::new(), values),
+                    TypeProperties::Amf3ArrayProperties(GenericProperties::new(false, count)),
+                )
+            }
+            (false, _) => self.insert(
+                object_id,
+                ObjectType::Amf0StrictArray(values),
+                TypeProperties::AmfNoProperties,
+            ),
+        })
+    }
+
+    fn annotated(&mut self, name: &str, map: &Map<String, Value>) -> Result<isize, ImportError> {
+        let value = map.get("value").unwrap_or(&Value::Null);
+        let flag = |key: &str| map.get(key).and_then(Value::as_bool).unwrap_or(false);
+        let class = map
+            .get(CLASS)
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string();
+        let number = || match value {
+            Value::Number(number) => number.as_f64().ok_or_else(|| invalid(name, "not a number")),
+            // NaN and the infinities are written as strings
+            Value::String(text) => text.parse().map_err(|_| invalid(name, "not a number")),
+            _ => Err(invalid(name, "not a number")),
+        };
+        let string = || {
+            value
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| invalid(name, "not a string"))
+        };
+        let object = || {
+            value
+                .as_object()
+                .ok_or_else(|| invalid(name, "value is not an object"))
+        };
+        let array = || {
+            value
+                .as_array()
+                .ok_or_else(|| invalid(name, "value is not an array"))
+        };
+
+        let leaf = match name {
+            "Amf0Number" => Some(ObjectType::Amf0Number(number()?)),
+            "Amf0Date" => Some(ObjectType::Amf0Date(number()?)),
+            "Amf3Double" => Some(ObjectType::Amf3Double(number()?)),
+            "Amf3Date" => Some(ObjectType::Amf3Date(number()?)),
+            "Amf3Integer" => {
+                let value = value
+                    .as_i64()
+                    .and_then(|value| i32::try_from(value).ok())
+                    .ok_or_else(|| invalid(name, "not a 32 bit integer"))?;
+                Some(ObjectType::Amf3Integer(value))
+            }
+            "Amf0Bool" => Some(ObjectType::Amf0Bool(
+                value.as_bool().ok_or_else(|| invalid(name, "not a bool"))?,
+            )),
+            "Amf3True" => Some(ObjectType::Amf3True),
+            "Amf3False" => Some(ObjectType::Amf3False),
+            "Amf0Null" => Some(ObjectType::Amf0Null),
+            "Amf0Undefined" => Some(ObjectType::Amf0Undefined),
+            "Amf0Unsupported" => Some(ObjectType::Amf0Unsupported),
+            "Amf3Null" => Some(ObjectType::Amf3Null),
+            "Amf3Undefined" => Some(ObjectType::Amf3Undefined),
+            "Amf0String" => Some(ObjectType::Amf0String(string()?.into())),
+            "Amf0LongString" => Some(ObjectType::Amf0LongString(string()?.into())),
+            "Amf0XML" => Some(ObjectType::Amf0XML(string()?.into())),
+            "Amf3String" => Some(ObjectType::Amf3String(string()?.into())),
+            "Amf3XML" => Some(ObjectType::Amf3XML(string()?.into())),
+            "Amf3XMLDocument" => Some(ObjectType::Amf3XMLDocument(string()?.into())),
+            "Amf3ByteArray" => Some(ObjectType::Amf3ByteArray(
+                unhex(&string()?)
+                    .ok_or_else(|| invalid(name, "not a hex string"))?
+                    .into(),
+            )),
+            _ => None,
+        };
+        // Leaves can have an $id too, AMF3 sends dates and byte arrays by reference
+        let object_id = self.declare(map)?;
+        if let Some(object_type) = leaf {
+            return Ok(self.insert(object_id, object_type, TypeProperties::AmfNoProperties));
+        }
+
+        let (object_type, object_properties) = match name {
+            "Amf0Object" | "Amf0EcmaArray" | "Amf0TypedObject" => {
+                let members = self.members(object()?, false)?;
+                match name {
+                    "Amf0Object" => (
+                        ObjectType::Amf0Object(members),
+                        TypeProperties::Amf0ObjectProperties,
+                    ),
+                    "Amf0EcmaArray" => {
+                        // The count that was sent, which needn't match the members
+                        let count = match map.get("$count") {
+                            Some(count) => count
+                                .as_u64()
+                                .and_then(|count| u32::try_from(count).ok())
+                                .ok_or_else(|| invalid(name, "$count is not a count"))?,
+                            None => members.len() as u32,
+                        };
+                        (
+                            ObjectType::Amf0EcmaArray(members),
+                            TypeProperties::Amf0EcmaArrayProperties(count),
+                        )
+                    }
+                    _ => (
+                        ObjectType::Amf0TypedObject(members),
+                        TypeProperties::Amf0TypedObjectProperties(class),
+                    ),
+                }
+            }
+            "Amf0StrictArray" => (
+                ObjectType::Amf0StrictArray(self.values(array()?, false)?),
+                TypeProperties::AmfNoProperties,
+            ),
+            "Amf3Object" => {
+                let members = self.members(object()?, true)?;
+                // Without the flags, a class means sealed and no class means dynamic
+                let sealed = match map.get("$sealed") {
+                    Some(count) => count
+                        .as_u64()
+                        .ok_or_else(|| invalid(name, "$sealed is not a count"))?
+                        as usize,
+                    None if class.is_empty() => 0,
+                    None => members.len(),
+                };
+                let dynamic = map
+                    .get("$dynamic")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(class.is_empty());
+                let externalisable = flag("$externalizable");
+                let encoding = (externalisable as usize) | ((dynamic as usize) << 1);
+                (
+                    ObjectType::Amf3Object(members),
+                    TypeProperties::Amf3ObjectProperties(ObjectProperties::new(
+                        false,
+                        sealed,
+                        encoding,
+                        externalisable,
+                        dynamic,
+                        class,
+                    )),
+                )
+            }
+            "Amf3Array" => {
+                let (associative, dense) = match value {
+                    Value::Array(dense) => (Vec::new(), self.values(dense, true)?),
+                    _ => {
+                        let parts = object()?;
+                        let associative = match parts.get("associative") {
+                            Some(Value::Object(members)) => self.members(members, true)?,
+                            _ => Vec::new(),
+                        };
+                        let dense = match parts.get("dense") {
+                            Some(Value::Array(values)) => self.values(values, true)?,
+                            _ => Vec::new(),
+                        };
+                        (associative, dense)
+                    }
+                };
+                let count = dense.len() as i32;
+                (
+                    ObjectType::Amf3Array(associative, dense),
+                    TypeProperties::Amf3ArrayProperties(GenericProperties::new(false, count)),
+                )
+            }
+            "Amf3VectorInt" | "Amf3VectorUInt" | "Amf3VectorDouble" | "Amf3VectorObject" => {
+                let items = array()?;
+                let object_type = match name {
+                    "Amf3VectorInt" => ObjectType::Amf3VectorInt(
+                        items
+                            .iter()
+                            .map(|item| item.as_i64().and_then(|item| i32::try_from(item).ok()))
+                            .collect::<Option<_>>()
+                            .ok_or_else(|| invalid(name, "items must be 32 bit integers"))?,
+                    ),
+                    "Amf3VectorUInt" => ObjectType::Amf3VectorUInt(
+                        items
+                            .iter()
+                            .map(|item| item.as_u64().and_then(|item| u32::try_from(item).ok()))
+                            .collect::<Option<_>>()
+                            .ok_or_else(|| invalid(name, "items must be 32 bit unsigned"))?,
+                    ),
+                    "Amf3VectorDouble" => ObjectType::Amf3VectorDouble(
+                        items
+                            .iter()
+                            .map(|item| match item {
+                                Value::String(text) => text.parse().ok(),
+                                item => item.as_f64(),
+                            })
+                            .collect::<Option<_>>()
+                            .ok_or_else(|| invalid(name, "items must be numbers"))?,
+                    ),
+                    _ => ObjectType::Amf3VectorObject(self.values(items, true)?),
+                };
+                (
+                    object_type,
+                    TypeProperties::Amf3VectorProperties(VectorProperties::new(
+                        flag("$fixed"),
+                        class,
+                    )),
+                )
+            }
+            "Amf3Dictionary" => {
+                let entries = array()?
+                    .iter()
+                    .map(|entry| match entry.as_array().map(Vec::as_slice) {
+                        Some([key, value]) => {
+                            Ok((self.value(key, true)?, self.value(value, true)?))
+                        }
+                        _ => Err(invalid(name, "entries must be [key, value] pairs")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                (
+                    ObjectType::Amf3Dictionary(entries),
+                    TypeProperties::Amf3DictionaryProperties(DictionaryProperties::new(flag(
+                        "$weakKeys",
+                    ))),
+                )
+            }
+            _ => return Err(ImportError::UnknownType(name.to_string())),
+        };
+        Ok(self.insert(object_id, object_type, object_properties))
+    }
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Builds an object map from JSON written by to_json, or any JSON a person wrote. An array is
+/// a list of roots, anything else is a single root. Values with a `$type` are built as exactly
+/// that type, plain values become the closest type in `version`, with whole numbers as AMF3
+/// integers.
+///
+/// In objects `$id`, `$class`, `$ref`, `$type` and `$items` are ours, not members. A member
+/// whose name starts with `$` is written with one more, `"$$ref"` is a member called `$ref`.
+pub fn from_json(
+    value: &Value,
+    version: AmfVersion,
+) -> Result<(HashMap<isize, ObjectInfo<'static>>, Vec<isize>), ImportError> {
+    let mut importer = Importer {
+        objects: HashMap::new(),
+        ids: HashMap::new(),
+        next_id: 0,
+    };
+    let amf3 = version == AmfVersion::Amf3;
+    let roots = match value {
+        Value::Array(values) => importer.values(values, amf3)?,
+        value => vec![importer.value(value, amf3)?],
+    };
+    Ok((importer.objects, roots))
+}
+
+/// Parses a JSON or YAML document and writes it out as AMF bytes.
+pub fn import(
+    text: &str,
+    format: ExportFormat,
+    version: AmfVersion,
+    is_command: bool,
+) -> Result<Vec<u8>, ImportError> {
+    let value: Value = match format {
+        ExportFormat::Json => {
+            serde_json::from_str(text).map_err(|e| ImportError::Parse(e.to_string()))?
+        }
+        ExportFormat::Yaml => {
+            serde_yaml::from_str(text).map_err(|e| ImportError::Parse(e.to_string()))?
+        }
+    };
+    let (objects, roots) = from_json(&value, version)?;
+    let mut writer = AMFWriter::new(is_command);
+    writer.write_document(&objects, &roots)?;
+    Ok(writer.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_highlight::AMFReader;
+
+    fn export(bytes: &[u8], annotate: bool) -> Value {
+        let mut reader = AMFReader::new(bytes, false);
+        reader.highlight();
+        to_json(&reader.objects, &reader.roots, annotate)
+    }
+
+    // Typed object "Pt" {x: 5 as an AMF3 integer, self: reference to itself}
+    const TYPED: [u8; 23] = [
+        0x10, 0x00, 0x02, b'P', b't', 0x00, 0x01, b'x', 0x11, 0x04, 0x05, 0x00, 0x04, b's', b'e',
+        b'l', b'f', 0x07, 0x00, 0x00, 0x00, 0x00, 0x09,
+    ];
+
+    #[test]
+    fn plain() {
+        assert_eq!(
+            export(&TYPED, false),
+            json!([{"$id": 0, "$class": "Pt", "x": 5, "self": {"$ref": 0}}])
+        );
+    }
+
+    #[test]
+    fn annotated() {
+        assert_eq!(
+            export(&TYPED, true),
+            json!([{
+                "$type": "Amf0TypedObject",
+                "$id": 0,
+                "$class": "Pt",
+                "value": {
+                    "x": {"$type": "Amf3Integer", "value": 5},
+                    "self": {"$ref": 0},
+                },
+            }])
+        );
+    }
+
+    #[test]
+    fn amf3_containers() {
+        // Array with a key and two dense values, then a fixed Vector.<Number>
+        let bytes = [
+            0x11, 0x09, 0x05, 0x03, b'k', 0x02, 0x01, 0x04, 0x01, 0x04, 0x02, 0x11, 0x0F, 0x03,
+            0x01, 0x7F, 0xF8, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(
+            export(&bytes, false),
+            json!([{"k": false, "0": 1, "1": 2}, [null]])
+        );
+        assert_eq!(
+            export(&bytes, true)[1],
+            json!({"$type": "Amf3VectorDouble", "$fixed": true, "value": ["NaN"]})
+        );
+    }
+
+    fn reimport(bytes: &[u8]) -> Vec<u8> {
+        let text = serde_json::to_string(&export(bytes, true)).unwrap();
+        import(&text, ExportFormat::Json, AmfVersion::Amf0, false).unwrap()
+    }
+
+    #[test]
+    fn annotated_round_trip() {
+        assert_eq!(reimport(&TYPED), TYPED);
+        let bytes = [
+            // Date, a reference to it, ByteArray and a Vector.<int>
+            &[0x11, 0x08, 0x01][..],
+            &1_700_000_000_000f64.to_be_bytes(),
+            &[0x11, 0x08, 0x00],
+            &[0x11, 0x0C, 0x05, 0xDE, 0xAD],
+            &[0x11, 0x0D, 0x03, 0x01],
+            &(-1i32).to_be_bytes(),
+            // Sealed Foo {a: 1.5} and a weak keyed dictionary {"k": 1}
+            &[0x11, 0x0A, 0x13, 0x07],
+            b"Foo",
+            &[0x03, b'a', 0x05],
+            &1.5f64.to_be_bytes(),
+            &[0x11, 0x11, 0x03, 0x01, 0x06, 0x03, b'k', 0x04, 0x01],
+        ]
+        .concat();
+        assert_eq!(reimport(&bytes), bytes);
+    }
+
+    #[test]
+    fn plain_json() {
+        let value = json!(["connect", 1, 1.5, {"$class": "a.B", "x": 2}, [true]]);
+        let (objects, roots) = from_json(&value, AmfVersion::Amf3).unwrap();
+        let types: Vec<&ObjectType> = roots.iter().map(|id| &objects[id].object_type).collect();
+        assert_eq!(types[0], &ObjectType::Amf3String("connect".into()));
+        assert_eq!(types[1], &ObjectType::Amf3Integer(1));
+        assert_eq!(types[2], &ObjectType::Amf3Double(1.5));
+        assert_eq!(
+            objects[&roots[3]].object_properties.class_name(),
+            Some("a.B")
+        );
+
+        let (objects, roots) = from_json(&value, AmfVersion::Amf0).unwrap();
+        assert_eq!(objects[&roots[1]].object_type, ObjectType::Amf0Number(1.0));
+        assert!(matches!(
+            objects[&roots[3]].object_type,
+            ObjectType::Amf0TypedObject(_)
+        ));
+
+        assert_eq!(
+            from_json(&json!({"$ref": 3}), AmfVersion::Amf0).unwrap_err(),
+            ImportError::UnknownReference(3)
+        );
+        assert_eq!(
+            from_json(&json!({"$type": "Amf9"}), AmfVersion::Amf0).unwrap_err(),
+            ImportError::UnknownType(String::from("Amf9"))
+        );
+    }
+
+    #[test]
+    fn dollar_members() {
+        // Members called $ref, $$x and $y
+        let value = json!({"$$ref": 1, "$$$x": 2, "$y": 3});
+        let (objects, roots) = from_json(&value, AmfVersion::Amf0).unwrap();
+        let ObjectType::Amf0Object(members) = &objects[&roots[0]].object_type else {
+            panic!("{:?}", objects[&roots[0]].object_type);
+        };
+        let keys: Vec<&str> = members.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["$ref", "$$x", "$y"]);
+
+        let mut writer = AMFWriter::new(false);
+        writer.write_document(&objects, &roots).unwrap();
+        let bytes = writer.into_bytes();
+        assert_eq!(
+            export(&bytes, false)[0],
+            json!({"$$ref": 1.0, "$$$x": 2.0, "$$y": 3.0})
+        );
+        assert_eq!(reimport(&bytes), bytes);
+    }
+}
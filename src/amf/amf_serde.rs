@@ -0,0 +1,1340 @@
+use crate::amf::amf_highlight::AMFReader;
+use crate::amf::amf_json::AmfVersion;
+use crate::amf::amf_writer::{AMFWriter, EncodeError};
+use crate::amf::object_info::ObjectInfo;
+use crate::amf::object_properties::{GenericProperties, ObjectProperties, TypeProperties};
+use crate::amf::object_type::ObjectType;
+use serde::de::value::{BorrowedStrDeserializer, SeqDeserializer};
+use serde::de::{
+    DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::{de, ser, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+
+// References can make the object graph cyclic, which serde has no way of representing
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Message(String),
+    Decode, // The bytes could not be read as AMF
+    Empty,
+    Encode(EncodeError),
+    KeyMustBeString,
+    TooDeep,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Message(message) => write!(f, "{}", message),
+            Error::Decode => write!(f, "Could not decode AMF"),
+            Error::Empty => write!(f, "No value to deserialize"),
+            Error::Encode(e) => write!(f, "Could not encode AMF: {}", e),
+            Error::KeyMustBeString => write!(f, "Map keys must be strings or numbers"),
+            Error::TooDeep => write!(f, "Value is nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(e: EncodeError) -> Self {
+        Error::Encode(e)
+    }
+}
+
+// Structs are typed objects when they have a field by this name, it holds the ActionScript
+// class. #[serde(rename = "$class")] on a String field is all it takes to map one, the same
+// name the JSON export uses.
+const CLASS_FIELD: &str = "$class";
+
+/// Deserializes the first value in an AMF0 buffer, AMF3 values are read through the switch marker.
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8], is_command: bool) -> Result<T, Error> {
+    let mut reader = AMFReader::new(bytes, is_command);
+    reader.highlight();
+    if reader.objects.contains_key(&-1) {
+        return Err(Error::Decode);
+    }
+    let root = *reader.roots.first().ok_or(Error::Empty)?;
+    T::deserialize(Deserializer::new(&reader.objects, root))
+}
+
+/// Serializes a single value as AMF0 or AMF3.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T, version: AmfVersion) -> Result<Vec<u8>, Error> {
+    let mut serializer = Serializer::new(version);
+    serializer.push(value)?;
+    serializer.into_bytes(false)
+}
+
+pub struct Deserializer<'de> {
+    objects: &'de HashMap<isize, ObjectInfo<'de>>,
+    object_id: isize,
+    depth: usize,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn new(objects: &'de HashMap<isize, ObjectInfo<'de>>, object_id: isize) -> Self {
+        Self {
+            objects,
+            object_id,
+            depth: 0,
+        }
+    }
+
+    fn child(&self, object_id: isize) -> Self {
+        Self {
+            objects: self.objects,
+            object_id,
+            depth: self.depth + 1,
+        }
+    }
+
+    fn info(&self) -> Result<&'de ObjectInfo<'de>, Error> {
+        if self.depth > MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        let mut info = self.objects.get(&self.object_id).ok_or(Error::Decode)?;
+        // The switch marker is an encoding detail, the value is what follows it
+        while let ObjectType::Amf0Switch(value) = info.object_type {
+            info = self.objects.get(&value).ok_or(Error::Decode)?;
+        }
+        Ok(info)
+    }
+
+    fn number(&self) -> Result<Option<f64>, Error> {
+        Ok(match self.info()?.object_type {
+            ObjectType::Amf0Number(n) | ObjectType::Amf3Double(n) => Some(n),
+            ObjectType::Amf3Integer(n) => Some(n as f64),
+            _ => None,
+        })
+    }
+
+    fn class_name(&self) -> Result<Option<&'de str>, Error> {
+        Ok(match &self.info()?.object_properties {
+            TypeProperties::Amf0TypedObjectProperties(class_name) => Some(class_name),
+            TypeProperties::Amf3ObjectProperties(prop) if !prop.object_type.is_empty() => {
+                Some(&prop.object_type)
+            }
+            _ => None,
+        })
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let info = self.info()?;
+        match &info.object_type {
+            ObjectType::Amf0Number(n)
+            | ObjectType::Amf0Date(n)
+            | ObjectType::Amf3Double(n)
+            | ObjectType::Amf3Date(n) => visitor.visit_f64(*n),
+            ObjectType::Amf3Integer(n) => visitor.visit_i32(*n),
+            ObjectType::Amf0Bool(b) => visitor.visit_bool(*b),
+            ObjectType::Amf3True => visitor.visit_bool(true),
+            ObjectType::Amf3False => visitor.visit_bool(false),
+            ObjectType::Amf0String(s)
+            | ObjectType::Amf0LongString(s)
+            | ObjectType::Amf0XML(s)
+            | ObjectType::Amf3String(s)
+            | ObjectType::Amf3XML(s)
+            | ObjectType::Amf3XMLDocument(s) => visitor.visit_borrowed_str(s),
+            ObjectType::Amf0Null
+            | ObjectType::Amf0Undefined
+            | ObjectType::Amf0Unsupported
+            | ObjectType::Amf3Null
+            | ObjectType::Amf3Undefined
+            | ObjectType::Amf0Switch(_) => visitor.visit_unit(),
+            ObjectType::Amf0Object(members)
+            | ObjectType::Amf0EcmaArray(members)
+            | ObjectType::Amf0TypedObject(members)
+            | ObjectType::Amf3Object(members) => visitor.visit_map(MemberAccess::new(
+                &self,
+                members
+                    .iter()
+                    .map(|(key, id)| (Cow::Borrowed(key.as_str()), *id))
+                    .collect(),
+            )),
+            ObjectType::Amf3Array(associative, dense) if !associative.is_empty() => {
+                let mut members: Vec<(Cow<'de, str>, isize)> = associative
+                    .iter()
+                    .map(|(key, id)| (Cow::Borrowed(key.as_str()), *id))
+                    .collect();
+                members.extend(
+                    dense
+                        .iter()
+                        .enumerate()
+                        .map(|(i, id)| (Cow::Owned(i.to_string()), *id)),
+                );
+                visitor.visit_map(MemberAccess::new(&self, members))
+            }
+            ObjectType::Amf3Array(_, values)
+            | ObjectType::Amf0StrictArray(values)
+            | ObjectType::Amf3VectorObject(values) => visitor.visit_seq(ValueAccess {
+                de: &self,
+                values: values.iter(),
+            }),
+            ObjectType::Amf3ByteArray(bytes) => visitor.visit_borrowed_bytes(bytes),
+            ObjectType::Amf3VectorInt(items) => {
+                visitor.visit_seq(SeqDeserializer::new(items.iter().copied()))
+            }
+            ObjectType::Amf3VectorUInt(items) => {
+                visitor.visit_seq(SeqDeserializer::new(items.iter().copied()))
+            }
+            ObjectType::Amf3VectorDouble(items) => {
+                visitor.visit_seq(SeqDeserializer::new(items.iter().copied()))
+            }
+            ObjectType::Amf3Dictionary(entries) => visitor.visit_map(DictionaryAccess {
+                de: &self,
+                entries: entries.iter(),
+                value: None,
+            }),
+        }
+    }
+
+    // AMF0 only has doubles, so whole numbers have to be accepted for every integer type
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.number()? {
+            Some(n) if n.fract() == 0.0 => visitor.visit_i64(n as i64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.number()? {
+            Some(n) if n.fract() == 0.0 && n >= 0.0 => visitor.visit_u64(n as u64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.info()?.object_type {
+            ObjectType::Amf0Null
+            | ObjectType::Amf0Undefined
+            | ObjectType::Amf3Null
+            | ObjectType::Amf3Undefined => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let class = if fields.contains(&CLASS_FIELD) {
+            self.class_name()?
+        } else {
+            None
+        };
+        // Only typed objects have a class, and those are always one of these two
+        match (&self.info()?.object_type, class) {
+            (
+                ObjectType::Amf0TypedObject(members) | ObjectType::Amf3Object(members),
+                Some(class),
+            ) => {
+                let members = members
+                    .iter()
+                    .map(|(key, id)| (Cow::Borrowed(key.as_str()), *id))
+                    .collect();
+                let mut access = MemberAccess::new(&self, members);
+                access.class = Some(class);
+                visitor.visit_map(access)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match &self.info()?.object_type {
+            ObjectType::Amf0String(s) | ObjectType::Amf3String(s) => {
+                visitor.visit_enum(BorrowedStrDeserializer::new(s))
+            }
+            ObjectType::Amf0Object(members)
+            | ObjectType::Amf0EcmaArray(members)
+            | ObjectType::Amf3Object(members)
+                if members.len() == 1 =>
+            {
+                visitor.visit_enum(VariantDeserializer {
+                    de: self.child(members[0].1),
+                    variant: &members[0].0,
+                })
+            }
+            _ => Err(de::Error::custom(
+                "expected a string or an object with one member",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct seq tuple tuple_struct
+        map identifier
+    }
+}
+
+// What the key just read leads to
+enum Member<'de> {
+    Object(isize),
+    Class(&'de str),
+}
+
+struct MemberAccess<'de, 'd> {
+    de: &'d Deserializer<'de>,
+    class: Option<&'de str>, // Handed out first as $class, for structs that ask for it
+    members: std::vec::IntoIter<(Cow<'de, str>, isize)>,
+    value: Option<Member<'de>>,
+}
+
+impl<'de, 'd> MemberAccess<'de, 'd> {
+    fn new(de: &'d Deserializer<'de>, members: Vec<(Cow<'de, str>, isize)>) -> Self {
+        Self {
+            de,
+            class: None,
+            members: members.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for MemberAccess<'de, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if let Some(class) = self.class.take() {
+            self.value = Some(Member::Class(class));
+            return seed.deserialize(CLASS_FIELD.into_deserializer()).map(Some);
+        }
+        let (key, value) = match self.members.next() {
+            Some(member) => member,
+            None => return Ok(None),
+        };
+        self.value = Some(Member::Object(value));
+        match key {
+            Cow::Borrowed(key) => seed.deserialize(BorrowedStrDeserializer::new(key)),
+            Cow::Owned(key) => seed.deserialize(key.into_deserializer()),
+        }
+        .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(Member::Object(value)) => seed.deserialize(self.de.child(value)),
+            Some(Member::Class(class)) => seed.deserialize(BorrowedStrDeserializer::new(class)),
+            None => Err(de::Error::custom("value requested before key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.members.len() + self.class.is_some() as usize)
+    }
+}
+
+struct DictionaryAccess<'de, 'd> {
+    de: &'d Deserializer<'de>,
+    entries: std::slice::Iter<'de, (isize, isize)>,
+    value: Option<isize>,
+}
+
+impl<'de> MapAccess<'de> for DictionaryAccess<'de, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let (key, value) = match self.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        self.value = Some(*value);
+        seed.deserialize(self.de.child(*key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(self.de.child(value)),
+            None => Err(de::Error::custom("value requested before key")),
+        }
+    }
+}
+
+struct ValueAccess<'de, 'd> {
+    de: &'d Deserializer<'de>,
+    values: std::slice::Iter<'de, isize>,
+}
+
+impl<'de> SeqAccess<'de> for ValueAccess<'de, '_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.values.next() {
+            Some(value) => seed.deserialize(self.de.child(*value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct VariantDeserializer<'de> {
+    de: Deserializer<'de>,
+    variant: &'de str,
+}
+
+impl<'de> EnumAccess<'de> for VariantDeserializer<'de> {
+    type Error = Error;
+    type Variant = Deserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, self.de))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+// Builds the same object map AMFReader produces and hands it to AMFWriter
+pub struct Serializer {
+    objects: HashMap<isize, ObjectInfo<'static>>,
+    roots: Vec<isize>,
+    version: AmfVersion,
+}
+
+impl Serializer {
+    pub fn new(version: AmfVersion) -> Self {
+        Self {
+            objects: HashMap::new(),
+            roots: Vec::new(),
+            version,
+        }
+    }
+
+    /// Adds another top level value, RTMP commands are several of these in a row.
+    pub fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let object_id = value.serialize(&mut *self)?;
+        self.roots.push(object_id);
+        Ok(())
+    }
+
+    pub fn into_bytes(self, is_command: bool) -> Result<Vec<u8>, Error> {
+        let mut writer = AMFWriter::new(is_command);
+        writer.write_document(&self.objects, &self.roots)?;
+        Ok(writer.into_bytes())
+    }
+
+    fn is_amf3(&self) -> bool {
+        self.version == AmfVersion::Amf3
+    }
+
+    fn insert(
+        &mut self,
+        object_type: ObjectType<'static>,
+        object_properties: TypeProperties,
+    ) -> isize {
+        let object_id = self.objects.len() as isize;
+        self.objects.insert(
+            object_id,
+            ObjectInfo::detached(object_id, object_type, object_properties),
+        );
+        object_id
+    }
+
+    fn insert_number(&mut self, value: f64) -> isize {
+        if self.is_amf3() {
+            self.insert(
+                ObjectType::Amf3Double(value),
+                TypeProperties::AmfNoProperties,
+            )
+        } else {
+            self.insert(
+                ObjectType::Amf0Number(value),
+                TypeProperties::AmfNoProperties,
+            )
+        }
+    }
+
+    fn insert_integer(&mut self, value: i64) -> isize {
+        if self.is_amf3() && (-0x1000_0000..0x1000_0000).contains(&value) {
+            self.insert(
+                ObjectType::Amf3Integer(value as i32),
+                TypeProperties::AmfNoProperties,
+            )
+        } else {
+            self.insert_number(value as f64)
+        }
+    }
+
+    fn insert_members(
+        &mut self,
+        members: Vec<(String, isize)>,
+        class: Option<&str>,
+        sealed: bool,
+    ) -> isize {
+        if self.is_amf3() {
+            let properties = if sealed {
+                ObjectProperties::new(
+                    false,
+                    members.len(),
+                    0x00,
+                    false,
+                    false,
+                    class.unwrap_or("").to_string(),
+                )
+            } else {
+                ObjectProperties::new(false, 0, 0x02, false, true, String::new())
+            };
+            return self.insert(
+                ObjectType::Amf3Object(members),
+                TypeProperties::Amf3ObjectProperties(properties),
+            );
+        }
+        match (sealed, class) {
+            (true, Some(class)) => self.insert(
+                ObjectType::Amf0TypedObject(members),
+                TypeProperties::Amf0TypedObjectProperties(class.to_string()),
+            ),
+            (true, None) => self.insert(
+                ObjectType::Amf0Object(members),
+                TypeProperties::Amf0ObjectProperties,
+            ),
+            (false, _) => {
+                let count = members.len() as u32;
+                self.insert(
+                    ObjectType::Amf0EcmaArray(members),
+                    TypeProperties::Amf0EcmaArrayProperties(count),
+                )
+            }
+        }
+    }
+
+    fn insert_values(&mut self, values: Vec<isize>) -> isize {
+        if self.is_amf3() {
+            let count = values.len() as i32;
+            self.insert(
+                ObjectType::Amf3Array(Vec::new(), values),
+                TypeProperties::Amf3ArrayProperties(GenericProperties::new(false, count)),
+            )
+        } else {
+            self.insert(
+                ObjectType::Amf0StrictArray(values),
+                TypeProperties::AmfNoProperties,
+            )
+        }
+    }
+
+    // Enum variants with data become { variant: data }
+    fn wrap_variant(&mut self, variant: Option<&'static str>, object_id: isize) -> isize {
+        match variant {
+            Some(variant) => {
+                self.insert_members(vec![(variant.to_string(), object_id)], None, true)
+            }
+            None => object_id,
+        }
+    }
+}
+
+impl<'s> ser::Serializer for &'s mut Serializer {
+    type Ok = isize;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'s>;
+    type SerializeTuple = SeqSerializer<'s>;
+    type SerializeTupleStruct = SeqSerializer<'s>;
+    type SerializeTupleVariant = SeqSerializer<'s>;
+    type SerializeMap = MapSerializer<'s>;
+    type SerializeStruct = MapSerializer<'s>;
+    type SerializeStructVariant = MapSerializer<'s>;
+
+    fn serialize_bool(self, v: bool) -> Result<isize, Error> {
+        let object_type = match (self.is_amf3(), v) {
+            (false, v) => ObjectType::Amf0Bool(v),
+            (true, true) => ObjectType::Amf3True,
+            (true, false) => ObjectType::Amf3False,
+        };
+        Ok(self.insert(object_type, TypeProperties::AmfNoProperties))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<isize, Error> {
+        Ok(self.insert_integer(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<isize, Error> {
+        Ok(self.insert_integer(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<isize, Error> {
+        Ok(self.insert_integer(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<isize, Error> {
+        Ok(self.insert_integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<isize, Error> {
+        Ok(self.insert_integer(v as i64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<isize, Error> {
+        Ok(self.insert_integer(v as i64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<isize, Error> {
+        Ok(self.insert_integer(v as i64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<isize, Error> {
+        match i64::try_from(v) {
+            Ok(v) => Ok(self.insert_integer(v)),
+            Err(_) => Ok(self.insert_number(v as f64)),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<isize, Error> {
+        Ok(self.insert_number(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<isize, Error> {
+        Ok(self.insert_number(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<isize, Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<isize, Error> {
+        if self.is_amf3() {
+            return Ok(self.insert(
+                ObjectType::Amf3String(v.to_string().into()),
+                TypeProperties::Amf3StringProperties(GenericProperties::new(false, v.len() as i32)),
+            ));
+        }
+        Ok(self.insert(
+            ObjectType::Amf0String(v.to_string().into()),
+            TypeProperties::Amf0StringProperties,
+        ))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<isize, Error> {
+        if self.is_amf3() {
+            return Ok(self.insert(
+                ObjectType::Amf3ByteArray(v.to_vec().into()),
+                TypeProperties::AmfNoProperties,
+            ));
+        }
+        // AMF0 has nothing better than an array of numbers
+        let values = v.iter().map(|b| self.insert_number(*b as f64)).collect();
+        Ok(self.insert_values(values))
+    }
+
+    fn serialize_none(self) -> Result<isize, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<isize, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<isize, Error> {
+        let object_type = if self.is_amf3() {
+            ObjectType::Amf3Null
+        } else {
+            ObjectType::Amf0Null
+        };
+        Ok(self.insert(object_type, TypeProperties::AmfNoProperties))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<isize, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<isize, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<isize, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<isize, Error> {
+        let object_id = value.serialize(&mut *self)?;
+        Ok(self.wrap_variant(Some(variant), object_id))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'s>, Error> {
+        Ok(SeqSerializer {
+            ser: self,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'s>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'s>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'s>, Error> {
+        let mut seq = self.serialize_seq(Some(len))?;
+        seq.variant = Some(variant);
+        Ok(seq)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer<'s>, Error> {
+        Ok(MapSerializer {
+            ser: self,
+            members: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+            class: None,
+            sealed: false,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer<'s>, Error> {
+        let mut map = self.serialize_map(Some(len))?;
+        map.sealed = true;
+        Ok(map)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer<'s>, Error> {
+        let mut map = self.serialize_map(Some(len))?;
+        map.sealed = true;
+        map.variant = Some(variant);
+        Ok(map)
+    }
+}
+
+pub struct SeqSerializer<'s> {
+    ser: &'s mut Serializer,
+    values: Vec<isize>,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let object_id = value.serialize(&mut *self.ser)?;
+        self.values.push(object_id);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<isize, Error> {
+        let object_id = self.ser.insert_values(self.values);
+        Ok(self.ser.wrap_variant(self.variant, object_id))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+pub struct MapSerializer<'s> {
+    ser: &'s mut Serializer,
+    members: Vec<(String, isize)>,
+    key: Option<String>,
+    class: Option<String>,
+    sealed: bool, // Structs have a fixed set of members, maps are dynamic
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let object_id = value.serialize(&mut *self.ser)?;
+        self.members.push((key.to_string(), object_id));
+        Ok(())
+    }
+
+    // A struct's $class field becomes its class rather than a member
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        if key == CLASS_FIELD {
+            self.class = Some(value.serialize(MapKeySerializer)?);
+            return Ok(());
+        }
+        self.push(key, value)
+    }
+
+    fn finish(self) -> Result<isize, Error> {
+        let class = self.class.as_deref().filter(|class| !class.is_empty());
+        let object_id = self.ser.insert_members(self.members, class, self.sealed);
+        Ok(self.ser.wrap_variant(self.variant, object_id))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <Error as ser::Error>::custom("value serialized before key"))?;
+        self.push(&key, value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer<'_> {
+    type Ok = isize;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<isize, Error> {
+        self.finish()
+    }
+}
+
+// Object keys are always strings in AMF, numbers are written out the way ActionScript would
+struct MapKeySerializer;
+
+impl ser::Serializer for MapKeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<String, Error>;
+    type SerializeTuple = ser::Impossible<String, Error>;
+    type SerializeTupleStruct = ser::Impossible<String, Error>;
+    type SerializeTupleVariant = ser::Impossible<String, Error>;
+    type SerializeMap = ser::Impossible<String, Error>;
+    type SerializeStruct = ser::Impossible<String, Error>;
+    type SerializeStructVariant = ser::Impossible<String, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(Error::KeyMustBeString)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::KeyMustBeString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Player {
+        #[serde(rename = "$class")]
+        class: String,
+        name: String,
+        level: u32,
+        score: f64,
+        online: bool,
+        guild: Option<String>,
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Connect {
+        app: String,
+        #[serde(rename = "objectEncoding")]
+        object_encoding: f64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Joined,
+        Left(String),
+        Moved { x: i32, y: i32 },
+    }
+
+    fn player() -> Player {
+        Player {
+            class: String::from("com.example.Player"),
+            name: String::from("lilith"),
+            level: 42,
+            score: 1.5,
+            online: true,
+            guild: None,
+            tags: vec![String::from("a"), String::from("b")],
+        }
+    }
+
+    #[test]
+    fn struct_round_trip() {
+        for version in [AmfVersion::Amf0, AmfVersion::Amf3] {
+            let bytes = to_bytes(&player(), version).unwrap();
+            let decoded: Player = from_bytes(&bytes, false).unwrap();
+            assert_eq!(decoded, player());
+        }
+    }
+
+    #[test]
+    fn struct_is_typed_object() {
+        let bytes = to_bytes(&player(), AmfVersion::Amf0).unwrap();
+        assert_eq!(bytes[0], 0x10);
+        assert_eq!(&bytes[3..21], b"com.example.Player");
+
+        let bytes = to_bytes(&player(), AmfVersion::Amf3).unwrap();
+        assert_eq!(&bytes[..3], &[0x11, 0x0A, 0x63]);
+        assert_eq!(&bytes[4..22], b"com.example.Player");
+    }
+
+    #[test]
+    fn class_only_from_field() {
+        // A name that looks like a class doesn't make a typed object
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "com.example.Other")]
+        struct Other {
+            name: String,
+        }
+
+        let other = Other {
+            name: String::from("lilith"),
+        };
+        assert_eq!(to_bytes(&other, AmfVersion::Amf0).unwrap()[0], 0x03);
+        for version in [AmfVersion::Amf0, AmfVersion::Amf3] {
+            let bytes = to_bytes(&player(), version).unwrap();
+            let decoded: Other = from_bytes(&bytes, false).unwrap();
+            assert_eq!(decoded, other);
+        }
+
+        // Empty means anonymous, and an untyped object has no class to hand over
+        let anonymous = Player {
+            class: String::new(),
+            ..player()
+        };
+        let bytes = to_bytes(&anonymous, AmfVersion::Amf0).unwrap();
+        assert_eq!(bytes[0], 0x03);
+        assert!(from_bytes::<Player>(&bytes, false).is_err());
+    }
+
+    #[test]
+    fn maps_are_dynamic() {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("duration"), 12.5);
+        map.insert(String::from("width"), 1280.0);
+
+        let bytes = to_bytes(&map, AmfVersion::Amf0).unwrap();
+        assert_eq!(bytes[0], 0x08);
+        let decoded: BTreeMap<String, f64> = from_bytes(&bytes, false).unwrap();
+        assert_eq!(decoded, map);
+
+        let bytes = to_bytes(&map, AmfVersion::Amf3).unwrap();
+        assert_eq!(&bytes[..3], &[0x11, 0x0A, 0x0B]);
+        let decoded: BTreeMap<String, f64> = from_bytes(&bytes, false).unwrap();
+        assert_eq!(decoded, map);
+    }
+
+    #[test]
+    fn enums() {
+        let events = vec![
+            Event::Joined,
+            Event::Left(String::from("lilith")),
+            Event::Moved { x: -1, y: 2 },
+        ];
+        for version in [AmfVersion::Amf0, AmfVersion::Amf3] {
+            let bytes = to_bytes(&events, version).unwrap();
+            let decoded: Vec<Event> = from_bytes(&bytes, false).unwrap();
+            assert_eq!(decoded, events);
+        }
+    }
+
+    // Vec<u8> serializes as a sequence, this is what serde_bytes does
+    struct Bytes(Vec<u8>);
+
+    impl Serialize for Bytes {
+        fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    #[test]
+    fn bytes() {
+        let bytes = to_bytes(&Bytes(vec![1, 2, 3]), AmfVersion::Amf3).unwrap();
+        assert_eq!(bytes, vec![0x11, 0x0C, 0x07, 0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn command_values() {
+        let mut serializer = Serializer::new(AmfVersion::Amf0);
+        serializer.push("connect").unwrap();
+        serializer.push(&1.0).unwrap();
+        serializer
+            .push(&Connect {
+                app: String::from("live"),
+                object_encoding: 3.0,
+            })
+            .unwrap();
+        let bytes = serializer.into_bytes(true).unwrap();
+
+        let mut reader = AMFReader::new(&bytes, true);
+        reader.highlight();
+        assert_eq!(reader.roots.len(), 3);
+        let name: String =
+            de::Deserialize::deserialize(Deserializer::new(&reader.objects, reader.roots[0]))
+                .unwrap();
+        let transaction: u32 =
+            de::Deserialize::deserialize(Deserializer::new(&reader.objects, reader.roots[1]))
+                .unwrap();
+        let command: Connect =
+            de::Deserialize::deserialize(Deserializer::new(&reader.objects, reader.roots[2]))
+                .unwrap();
+        assert_eq!(name, "connect");
+        assert_eq!(transaction, 1);
+        assert_eq!(command.app, "live");
+    }
+
+    #[test]
+    fn cycles_do_not_overflow() {
+        // Dynamic object whose only member points back at itself
+        let bytes = vec![0x11, 0x0A, 0x0B, 0x01, 0x03, b'a', 0x0A, 0x00, 0x01];
+        let decoded: Result<BTreeMap<String, BTreeMap<String, ()>>, Error> =
+            from_bytes(&bytes, false);
+        assert!(decoded.is_err());
+
+        #[derive(Debug, Deserialize)]
+        struct Node {
+            #[allow(dead_code)]
+            a: Box<Node>,
+        }
+        let decoded: Result<Node, Error> = from_bytes(&bytes, false);
+        assert_eq!(decoded.unwrap_err(), Error::TooDeep);
+    }
+}
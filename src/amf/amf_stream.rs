@@ -0,0 +1,316 @@
+use crate::amf::amf_highlight::{AMFReader, ReaderState};
+use crate::amf::decode_limits::{DecodeLimits, Limit};
+use crate::amf::object_info::ObjectInfo;
+use crate::amf::syntax_byte::SyntaxSpan;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+const READ_CHUNK: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    NeedMoreBytes,  // The value so far is fine, it just isn't finished yet
+    Invalid(usize), // Offset of the value that could not be decoded
+    LimitExceeded(Limit, usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::NeedMoreBytes => write!(f, "Need more bytes to finish the value"),
+            DecodeError::Invalid(offset) => write!(f, "Invalid value at offset {}", offset),
+            DecodeError::LimitExceeded(limit, offset) => {
+                write!(f, "{} limit exceeded at offset {}", limit, offset)
+            }
+            DecodeError::Io(e) => write!(f, "Could not read stream: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> Self {
+        DecodeError::Io(e)
+    }
+}
+
+/// Decodes top level AMF0 values from a socket, pipe or growing file as the bytes turn up.
+/// String, object and trait tables are kept between values, so references reach back across
+/// reads the same way they do in a single buffer.
+///
+/// That means nothing read can be let go of, so a stream decodes at most
+/// `DecodeLimits::max_stream_bytes` and then stops with `Limit::StreamBytes`.
+pub struct AMFStream<R: Read> {
+    source: R,
+    buffer: Vec<u8>, // Every byte read so far, spans and string references point into this
+    read_head: usize,
+    state: ReaderState,
+    objects: HashMap<isize, ObjectInfo<'static>>, // Owned, the buffer moves as it grows
+    limits: DecodeLimits,
+    pub roots: Vec<isize>,
+    failed: bool,
+}
+
+impl<R: Read> AMFStream<R> {
+    pub fn new(source: R) -> Self {
+        AMFStream {
+            source,
+            buffer: Vec::new(),
+            read_head: 0,
+            state: ReaderState::default(),
+            objects: HashMap::new(),
+            limits: DecodeLimits::default(),
+            roots: Vec::new(),
+            failed: false,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn objects(&self) -> &HashMap<isize, ObjectInfo<'static>> {
+        &self.objects
+    }
+
+    pub fn spans(&self) -> &[SyntaxSpan] {
+        &self.state.out
+    }
+
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Adds bytes that arrived some other way, e.g. from an async socket.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Reads whatever the source has ready, 0 means it is at its end for now.
+    pub fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            match self.source.read(&mut chunk) {
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Tries to decode the next top level value from the bytes already buffered, without
+    /// touching the source. A half read value is thrown away and tried again next time.
+    pub fn decode_next(&mut self) -> Result<isize, DecodeError> {
+        if self.failed {
+            return Err(DecodeError::Invalid(self.read_head));
+        }
+        // Anything past the cap is never looked at, a value running into it can't finish
+        let cap = self.limits.max_stream_bytes;
+        let at_cap = self.buffer.len() >= cap;
+        if self.read_head >= cap {
+            return self.exceed_stream_bytes();
+        }
+        if self.read_head >= self.buffer.len() {
+            return Err(DecodeError::NeedMoreBytes);
+        }
+        let checkpoint = self.state.checkpoint();
+        let state = std::mem::take(&mut self.state);
+        let buffer = &self.buffer[..self.buffer.len().min(cap)];
+        let mut reader = AMFReader::resume(buffer, self.read_head, state, self.limits);
+        let object_id = reader.read_amf0();
+        let read_head = reader.read_head();
+        let (is_error, truncated) = (reader.is_error(), reader.is_truncated());
+        let exceeded = reader.limit_exceeded();
+        let (state, objects) = reader.into_state();
+        self.state = state;
+
+        if is_error {
+            self.state.rollback(checkpoint);
+            if let Some((limit, offset)) = exceeded {
+                self.failed = true;
+                return Err(DecodeError::LimitExceeded(limit, offset));
+            }
+            if truncated && at_cap {
+                return self.exceed_stream_bytes();
+            }
+            if truncated {
+                return Err(DecodeError::NeedMoreBytes);
+            }
+            self.failed = true;
+            return Err(DecodeError::Invalid(self.read_head));
+        }
+        self.read_head = read_head;
+        self.objects.extend(
+            objects
+                .into_iter()
+                .map(|(id, info)| (id, info.into_owned())),
+        );
+        self.roots.push(object_id);
+        Ok(object_id)
+    }
+
+    fn exceed_stream_bytes(&mut self) -> Result<isize, DecodeError> {
+        self.failed = true;
+        Err(DecodeError::LimitExceeded(
+            Limit::StreamBytes,
+            self.limits.max_stream_bytes,
+        ))
+    }
+
+    /// Decodes the next value, reading from the source until there are enough bytes.
+    /// Returns None when the source runs dry, any half read value stays buffered so this
+    /// can be called again once a growing file or socket has more for us.
+    pub fn next_value(&mut self) -> Result<Option<isize>, DecodeError> {
+        loop {
+            match self.decode_next() {
+                Ok(id) => return Ok(Some(id)),
+                Err(DecodeError::NeedMoreBytes) => {
+                    if self.fill()? == 0 {
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Top level object IDs, look them up in objects()
+impl<R: Read> Iterator for AMFStream<R> {
+    type Item = Result<isize, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        self.next_value().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_writer::AMFWriter;
+    use crate::amf::object_type::ObjectType;
+
+    // Hands out at most one byte per read, like a slow socket
+    struct Trickle(Vec<u8>, usize);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.get(self.1) {
+                Some(b) if !buf.is_empty() => {
+                    buf[0] = *b;
+                    self.1 += 1;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut writer = AMFWriter::new(false);
+        writer.write_amf0_string("connect").unwrap();
+        writer.write_amf0_number(1.0);
+        // Both AMF3 strings share the reference table, the second one is a reference
+        writer.out.push(0x11);
+        writer.write_amf3_string("app").unwrap();
+        writer.out.push(0x11);
+        writer.write_amf3_string("app").unwrap();
+        writer.into_bytes()
+    }
+
+    fn value(stream: &AMFStream<impl Read>, id: isize) -> ObjectType<'static> {
+        match &stream.objects()[&id].object_type {
+            ObjectType::Amf0Switch(inner) => stream.objects()[inner].object_type.clone(),
+            value => value.clone(),
+        }
+    }
+
+    #[test]
+    fn iterates_slow_source() {
+        let mut stream = AMFStream::new(Trickle(sample(), 0));
+        let ids: Vec<isize> = stream.by_ref().map(|id| id.unwrap()).collect();
+        let values: Vec<ObjectType> = ids.iter().map(|id| value(&stream, *id)).collect();
+        assert_eq!(
+            values,
+            vec![
+                ObjectType::Amf0String("connect".into()),
+                ObjectType::Amf0Number(1.0),
+                ObjectType::Amf3String("app".into()),
+                ObjectType::Amf3String("app".into()),
+            ]
+        );
+        assert_eq!(stream.roots, ids);
+    }
+
+    #[test]
+    fn resumes_after_partial_value() {
+        let bytes = sample();
+        let mut stream = AMFStream::new(io::empty());
+        let mut ids = Vec::new();
+        for chunk in bytes.chunks(3) {
+            stream.feed(chunk);
+            loop {
+                match stream.decode_next() {
+                    Ok(id) => ids.push(id),
+                    Err(DecodeError::NeedMoreBytes) => break,
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+        assert_eq!(ids.len(), 4);
+        assert_eq!(value(&stream, ids[3]), ObjectType::Amf3String("app".into()));
+        // Partial attempts must not leave stray objects behind
+        let reachable = stream.objects().len();
+        let mut whole = AMFReader::new(&bytes, false);
+        whole.highlight();
+        assert_eq!(reachable, whole.objects.len());
+    }
+
+    #[test]
+    fn stops_at_stream_bytes() {
+        // Three 4 byte strings, the second one runs past the cap
+        let bytes = [0x02, 0x00, 0x01, b'a'].repeat(3);
+        let limits = DecodeLimits {
+            max_stream_bytes: 6,
+            ..DecodeLimits::default()
+        };
+        let mut stream = AMFStream::new(&bytes[..]).with_limits(limits);
+        assert!(matches!(stream.next(), Some(Ok(_))));
+        assert!(matches!(
+            stream.next(),
+            Some(Err(DecodeError::LimitExceeded(Limit::StreamBytes, 6)))
+        ));
+        assert!(stream.next().is_none());
+
+        // Ending exactly on the cap is fine, the next value is not
+        let limits = DecodeLimits {
+            max_stream_bytes: 8,
+            ..DecodeLimits::default()
+        };
+        let mut stream = AMFStream::new(&bytes[..]).with_limits(limits);
+        assert!(matches!(stream.next(), Some(Ok(_))));
+        assert!(matches!(stream.next(), Some(Ok(_))));
+        assert!(matches!(
+            stream.next(),
+            Some(Err(DecodeError::LimitExceeded(Limit::StreamBytes, 8)))
+        ));
+    }
+
+    #[test]
+    fn invalid_stops_stream() {
+        let mut stream = AMFStream::new(&[0x02, 0x00, 0x01, b'a', 0x02, 0x00, 0x01, 0xFF][..]);
+        assert!(matches!(stream.next(), Some(Ok(_))));
+        assert!(matches!(stream.next(), Some(Err(DecodeError::Invalid(4)))));
+        assert!(stream.next().is_none());
+    }
+}
@@ -0,0 +1,764 @@
+use crate::amf::amf3_object::AmfTraits;
+use crate::amf::object_info::ObjectInfo;
+use crate::amf::object_properties::TypeProperties;
+use crate::amf::object_type::ObjectType;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+
+const AMF3_INTEGER_MIN: i32 = -0x1000_0000;
+const AMF3_INTEGER_MAX: i32 = 0x0FFF_FFFF;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncodeError {
+    MissingObject(isize),
+    NotAmf3(isize), // AMF0 values can't be switched back to once we are in AMF3
+    StringTooLong(usize),
+    TooManyItems(usize),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::MissingObject(id) => write!(f, "Object {} does not exist", id),
+            EncodeError::NotAmf3(id) => write!(f, "Object {} cannot be written as AMF3", id),
+            EncodeError::StringTooLong(len) => write!(f, "String of {} bytes is too long", len),
+            EncodeError::TooManyItems(len) => write!(f, "{} items cannot be encoded", len),
+        }
+    }
+}
+
+// Serialises the same object map AMFReader produces, so anything read can be written back out
+pub struct AMFWriter {
+    pub(crate) out: Vec<u8>,
+    strings: HashMap<String, usize>,
+    traits: Vec<AmfTraits>,
+    amf0_references: HashMap<isize, usize>,
+    amf0_written: usize, // Objects written out in full, the reader's table grows with each one
+    amf3_references: HashMap<isize, usize>,
+}
+
+impl AMFWriter {
+    pub fn new(is_command: bool) -> Self {
+        let mut out = Vec::new();
+        if is_command {
+            // AMFReader skips this, it is the AMF3 command format byte
+            out.push(0x00);
+        }
+        AMFWriter {
+            out,
+            strings: HashMap::new(),
+            traits: Vec::new(),
+            amf0_references: HashMap::new(),
+            amf0_written: 0,
+            amf3_references: HashMap::new(),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.out
+    }
+
+    pub fn write_document(
+        &mut self,
+        objects: &HashMap<isize, ObjectInfo>,
+        roots: &[isize],
+    ) -> Result<(), EncodeError> {
+        for root in roots {
+            self.write_amf0(objects, *root)?;
+        }
+        Ok(())
+    }
+
+    fn get_object<'a>(
+        objects: &'a HashMap<isize, ObjectInfo<'a>>,
+        object_id: isize,
+    ) -> Result<&'a ObjectInfo<'a>, EncodeError> {
+        objects
+            .get(&object_id)
+            .ok_or(EncodeError::MissingObject(object_id))
+    }
+
+    pub fn write_amf0(
+        &mut self,
+        objects: &HashMap<isize, ObjectInfo>,
+        object_id: isize,
+    ) -> Result<(), EncodeError> {
+        let info = Self::get_object(objects, object_id)?;
+        match &info.object_type {
+            ObjectType::Amf0Number(number) => self.write_amf0_number(*number),
+            ObjectType::Amf0Bool(value) => self.write_amf0_bool(*value),
+            ObjectType::Amf0String(value) => self.write_amf0_string(value)?,
+            ObjectType::Amf0Null => self.out.push(0x05),
+            ObjectType::Amf0Undefined => self.out.push(0x06),
+            ObjectType::Amf0Unsupported => self.out.push(0x0D),
+            ObjectType::Amf0Date(date) => {
+                self.out.push(0x0B);
+                self.out.extend_from_slice(&date.to_be_bytes());
+                self.out.extend_from_slice(&[0x00, 0x00]);
+            }
+            ObjectType::Amf0LongString(value) => {
+                self.out.push(0x0C);
+                self.write_u32_utf8(value)?;
+            }
+            ObjectType::Amf0XML(value) => {
+                self.out.push(0x0F);
+                self.write_u32_utf8(value)?;
+            }
+            ObjectType::Amf0Switch(value) => {
+                self.out.push(0x11);
+                self.write_amf3(objects, *value)?;
+            }
+            ObjectType::Amf0Object(members)
+            | ObjectType::Amf0EcmaArray(members)
+            | ObjectType::Amf0TypedObject(members) => {
+                if self.write_amf0_reference(object_id) {
+                    return Ok(());
+                }
+                match (&info.object_type, &info.object_properties) {
+                    (ObjectType::Amf0Object(_), _) => self.out.push(0x03),
+                    (ObjectType::Amf0EcmaArray(_), properties) => {
+                        self.out.push(0x08);
+                        // Whatever count was read, even a wrong one, values built in memory
+                        // have none
+                        match properties {
+                            TypeProperties::Amf0EcmaArrayProperties(count) => {
+                                self.write_u32(*count as usize)?
+                            }
+                            _ => self.write_u32(members.len())?,
+                        }
+                    }
+                    (_, TypeProperties::Amf0TypedObjectProperties(class_name)) => {
+                        self.out.push(0x10);
+                        self.write_amf0_utf8(class_name)?;
+                    }
+                    _ => {
+                        self.out.push(0x10);
+                        self.write_amf0_utf8("")?;
+                    }
+                }
+                for (key, value) in members {
+                    self.write_amf0_utf8(key)?;
+                    self.write_amf0(objects, *value)?;
+                }
+                self.out.extend_from_slice(&[0x00, 0x00, 0x09]);
+            }
+            ObjectType::Amf0StrictArray(values) => {
+                if self.write_amf0_reference(object_id) {
+                    return Ok(());
+                }
+                self.out.push(0x0A);
+                self.write_u32(values.len())?;
+                for value in values {
+                    self.write_amf0(objects, *value)?;
+                }
+            }
+            // Anything else only exists in AMF3, so switch for this one value
+            _ => {
+                self.out.push(0x11);
+                self.write_amf3(objects, object_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_amf0_reference(&mut self, object_id: isize) -> bool {
+        if let Some(index) = self.amf0_references.get(&object_id) {
+            if *index <= u16::MAX as usize {
+                self.out.push(0x07);
+                self.out.extend_from_slice(&(*index as u16).to_be_bytes());
+                return true;
+            }
+        }
+        // Past what a reference can hold it gets written out again, and takes up another
+        // table slot in the reader just like a new object
+        let index = self.amf0_written;
+        self.amf0_written += 1;
+        self.amf0_references.entry(object_id).or_insert(index);
+        false
+    }
+
+    pub fn write_amf0_number(&mut self, number: f64) {
+        self.out.push(0x00);
+        self.out.extend_from_slice(&number.to_be_bytes());
+    }
+
+    pub fn write_amf0_bool(&mut self, value: bool) {
+        self.out.push(0x01);
+        self.out.push(value as u8);
+    }
+
+    pub fn write_amf0_string(&mut self, value: &str) -> Result<(), EncodeError> {
+        if value.len() > u16::MAX as usize {
+            self.out.push(0x0C);
+            return self.write_u32_utf8(value);
+        }
+        self.out.push(0x02);
+        self.write_amf0_utf8(value)
+    }
+
+    fn write_amf0_utf8(&mut self, value: &str) -> Result<(), EncodeError> {
+        if value.len() > u16::MAX as usize {
+            return Err(EncodeError::StringTooLong(value.len()));
+        }
+        self.out
+            .extend_from_slice(&(value.len() as u16).to_be_bytes());
+        self.out.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn write_u32(&mut self, value: usize) -> Result<(), EncodeError> {
+        if value > u32::MAX as usize {
+            return Err(EncodeError::TooManyItems(value));
+        }
+        self.out.extend_from_slice(&(value as u32).to_be_bytes());
+        Ok(())
+    }
+
+    fn write_u32_utf8(&mut self, value: &str) -> Result<(), EncodeError> {
+        if value.len() > u32::MAX as usize {
+            return Err(EncodeError::StringTooLong(value.len()));
+        }
+        self.write_u32(value.len())?;
+        self.out.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    pub fn write_amf3(
+        &mut self,
+        objects: &HashMap<isize, ObjectInfo>,
+        object_id: isize,
+    ) -> Result<(), EncodeError> {
+        let info = Self::get_object(objects, object_id)?;
+        match &info.object_type {
+            ObjectType::Amf3Undefined => self.out.push(0x00),
+            ObjectType::Amf3Null => self.out.push(0x01),
+            ObjectType::Amf3False => self.out.push(0x02),
+            ObjectType::Amf3True => self.out.push(0x03),
+            ObjectType::Amf3Integer(value) => self.write_amf3_integer(*value),
+            ObjectType::Amf3Double(value) => self.write_amf3_double(*value),
+            ObjectType::Amf3String(value) => self.write_amf3_string(value)?,
+            ObjectType::Amf3XMLDocument(value) | ObjectType::Amf3XML(value) => {
+                match info.object_type {
+                    ObjectType::Amf3XMLDocument(_) => self.out.push(0x07),
+                    _ => self.out.push(0x0B),
+                }
+                if self.write_amf3_reference(object_id)? {
+                    return Ok(());
+                }
+                self.write_u29_length(value.len())?;
+                self.out.extend_from_slice(value.as_bytes());
+            }
+            ObjectType::Amf3Date(date) => {
+                self.out.push(0x08);
+                if self.write_amf3_reference(object_id)? {
+                    return Ok(());
+                }
+                self.write_u29(0x01)?;
+                self.out.extend_from_slice(&date.to_be_bytes());
+            }
+            ObjectType::Amf3Array(associative, dense) => {
+                self.out.push(0x09);
+                if self.write_amf3_reference(object_id)? {
+                    return Ok(());
+                }
+                self.write_u29_length(dense.len())?;
+                for (key, value) in associative {
+                    self.write_amf3_utf8(key)?;
+                    self.write_amf3(objects, *value)?;
+                }
+                self.write_amf3_utf8("")?;
+                for value in dense {
+                    self.write_amf3(objects, *value)?;
+                }
+            }
+            ObjectType::Amf3Object(members) => {
+                self.out.push(0x0A);
+                if self.write_amf3_reference(object_id)? {
+                    return Ok(());
+                }
+                self.write_amf3_object(objects, &info.object_properties, members)?;
+            }
+            ObjectType::Amf3ByteArray(bytes) => {
+                self.out.push(0x0C);
+                if self.write_amf3_reference(object_id)? {
+                    return Ok(());
+                }
+                self.write_u29_length(bytes.len())?;
+                self.out.extend_from_slice(bytes);
+            }
+            ObjectType::Amf3VectorInt(items) => {
+                self.out.push(0x0D);
+                if self.write_amf3_vector_header(object_id, &info.object_properties, items.len())? {
+                    return Ok(());
+                }
+                for item in items {
+                    self.out.extend_from_slice(&item.to_be_bytes());
+                }
+            }
+            ObjectType::Amf3VectorUInt(items) => {
+                self.out.push(0x0E);
+                if self.write_amf3_vector_header(object_id, &info.object_properties, items.len())? {
+                    return Ok(());
+                }
+                for item in items {
+                    self.out.extend_from_slice(&item.to_be_bytes());
+                }
+            }
+            ObjectType::Amf3VectorDouble(items) => {
+                self.out.push(0x0F);
+                if self.write_amf3_vector_header(object_id, &info.object_properties, items.len())? {
+                    return Ok(());
+                }
+                for item in items {
+                    self.out.extend_from_slice(&item.to_be_bytes());
+                }
+            }
+            ObjectType::Amf3VectorObject(items) => {
+                self.out.push(0x10);
+                if self.write_amf3_vector_header(object_id, &info.object_properties, items.len())? {
+                    return Ok(());
+                }
+                match &info.object_properties {
+                    TypeProperties::Amf3VectorProperties(prop) => {
+                        self.write_amf3_utf8(&prop.object_type)?
+                    }
+                    _ => self.write_amf3_utf8("")?,
+                }
+                for item in items {
+                    self.write_amf3(objects, *item)?;
+                }
+            }
+            ObjectType::Amf3Dictionary(entries) => {
+                self.out.push(0x11);
+                if self.write_amf3_reference(object_id)? {
+                    return Ok(());
+                }
+                self.write_u29_length(entries.len())?;
+                match &info.object_properties {
+                    TypeProperties::Amf3DictionaryProperties(prop) => {
+                        self.out.push(prop.weak_keys as u8)
+                    }
+                    _ => self.out.push(0x00),
+                }
+                for (key, value) in entries {
+                    self.write_amf3(objects, *key)?;
+                    self.write_amf3(objects, *value)?;
+                }
+            }
+            _ => return Err(EncodeError::NotAmf3(object_id)),
+        }
+        Ok(())
+    }
+
+    fn write_amf3_object(
+        &mut self,
+        objects: &HashMap<isize, ObjectInfo>,
+        properties: &TypeProperties,
+        members: &[(String, isize)],
+    ) -> Result<(), EncodeError> {
+        let traits = match properties {
+            TypeProperties::Amf3ObjectProperties(prop) => AmfTraits::new(
+                prop.encoding as i32,
+                prop.externalisable,
+                prop.dynamic,
+                prop.object_type.clone(),
+                members
+                    .iter()
+                    .take(prop.property_count)
+                    .map(|(key, _)| key.clone())
+                    .collect(),
+            ),
+            // Without any traits the best we can do is an anonymous dynamic object
+            _ => AmfTraits::new(0x02, false, true, String::new(), Vec::new()),
+        };
+
+        match self.traits.iter().position(|t| *t == traits) {
+            Some(index) => self.write_u29(((index as u32) << 2) | 0x01)?,
+            None => {
+                let count = traits.keys.len() as u32;
+                let header = (count << 4)
+                    | ((traits.dynamic as u32) << 3)
+                    | ((traits.externalisable as u32) << 2)
+                    | 0x03;
+                self.write_u29(header)?;
+                self.write_amf3_utf8(&traits.object_type)?;
+                for key in traits.keys.iter() {
+                    self.write_amf3_utf8(key)?;
+                }
+                self.traits.push(traits.clone());
+            }
+        }
+
+        if traits.externalisable {
+            for (_, value) in members {
+                self.write_amf3(objects, *value)?;
+            }
+            return Ok(());
+        }
+        for (_, value) in members.iter().take(traits.keys.len()) {
+            self.write_amf3(objects, *value)?;
+        }
+        if traits.dynamic {
+            for (key, value) in members.iter().skip(traits.keys.len()) {
+                self.write_amf3_utf8(key)?;
+                self.write_amf3(objects, *value)?;
+            }
+            self.write_amf3_utf8("")?;
+        }
+        Ok(())
+    }
+
+    fn write_amf3_vector_header(
+        &mut self,
+        object_id: isize,
+        properties: &TypeProperties,
+        count: usize,
+    ) -> Result<bool, EncodeError> {
+        if self.write_amf3_reference(object_id)? {
+            return Ok(true);
+        }
+        self.write_u29_length(count)?;
+        match properties {
+            TypeProperties::Amf3VectorProperties(prop) => self.out.push(prop.is_fixed as u8),
+            _ => self.out.push(0x00),
+        }
+        Ok(false)
+    }
+
+    // Writes the reference if we have already sent this object, otherwise remembers it
+    fn write_amf3_reference(&mut self, object_id: isize) -> Result<bool, EncodeError> {
+        if let Some(index) = self.amf3_references.get(&object_id) {
+            self.write_u29((*index as u32) << 1)?;
+            return Ok(true);
+        }
+        let index = self.amf3_references.len();
+        self.amf3_references.insert(object_id, index);
+        Ok(false)
+    }
+
+    pub fn write_amf3_integer(&mut self, value: i32) {
+        if !(AMF3_INTEGER_MIN..=AMF3_INTEGER_MAX).contains(&value) {
+            // Out of range for a U29, Flash does the same thing
+            self.write_amf3_double(value as f64);
+            return;
+        }
+        self.out.push(0x04);
+        // Always in range after the check above
+        let _ = self.write_u29(value as u32 & 0x1FFF_FFFF);
+    }
+
+    pub fn write_amf3_double(&mut self, value: f64) {
+        self.out.push(0x05);
+        self.out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_amf3_string(&mut self, value: &str) -> Result<(), EncodeError> {
+        self.out.push(0x06);
+        self.write_amf3_utf8(value)
+    }
+
+    fn write_amf3_utf8(&mut self, value: &str) -> Result<(), EncodeError> {
+        if value.is_empty() {
+            return self.write_u29(0x01);
+        }
+        if let Some(index) = self.strings.get(value) {
+            return self.write_u29((*index as u32) << 1);
+        }
+        self.write_u29_length(value.len())?;
+        self.out.extend_from_slice(value.as_bytes());
+        self.strings.insert(value.to_string(), self.strings.len());
+        Ok(())
+    }
+
+    fn write_u29_length(&mut self, length: usize) -> Result<(), EncodeError> {
+        if length >= 0x1000_0000 {
+            return Err(EncodeError::TooManyItems(length));
+        }
+        self.write_u29(((length as u32) << 1) | 0x01)
+    }
+
+    fn write_u29(&mut self, value: u32) -> Result<(), EncodeError> {
+        match value {
+            0..=0x7F => self.out.push(value as u8),
+            0x80..=0x3FFF => {
+                self.out.push(((value >> 7) | 0x80) as u8);
+                self.out.push((value & 0x7F) as u8);
+            }
+            0x4000..=0x1F_FFFF => {
+                self.out.push(((value >> 14) | 0x80) as u8);
+                self.out.push(((value >> 7) & 0x7F | 0x80) as u8);
+                self.out.push((value & 0x7F) as u8);
+            }
+            0x20_0000..=0x1FFF_FFFF => {
+                self.out.push(((value >> 22) | 0x80) as u8);
+                self.out.push(((value >> 15) & 0x7F | 0x80) as u8);
+                self.out.push(((value >> 8) & 0x7F | 0x80) as u8);
+                self.out.push((value & 0xFF) as u8);
+            }
+            _ => return Err(EncodeError::TooManyItems(value as usize)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_highlight::AMFReader;
+
+    fn sample(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    fn round_trip(sample: &Vec<u8>, is_command: bool) {
+        let mut reader = AMFReader::new(sample, is_command);
+        reader.highlight();
+        assert!(
+            !reader.objects.contains_key(&-1),
+            "{:02X?} did not decode",
+            sample
+        );
+
+        let mut writer = AMFWriter::new(is_command);
+        writer
+            .write_document(&reader.objects, &reader.roots)
+            .unwrap();
+        assert_eq!(&writer.into_bytes(), sample);
+    }
+
+    #[test]
+    fn amf0_primitives() {
+        round_trip(
+            &sample(&[
+                &[0x00],
+                &1.5f64.to_be_bytes(),
+                &[0x01, 0x01, 0x01, 0x00],
+                &[0x02, 0x00, 0x05],
+                b"hello",
+                &[0x02, 0x00, 0x00, 0x05, 0x06, 0x0D],
+                &[0x0B],
+                &1_700_000_000_000f64.to_be_bytes(),
+                &[0x00, 0x00],
+                &[0x0C, 0x00, 0x00, 0x00, 0x03],
+                b"abc",
+                &[0x0F, 0x00, 0x00, 0x00, 0x04],
+                b"<a/>",
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn amf0_objects() {
+        round_trip(
+            &sample(&[
+                &[0x03, 0x00, 0x03],
+                b"app",
+                &[0x02, 0x00, 0x04],
+                b"live",
+                &[0x00, 0x03],
+                b"num",
+                &[0x00],
+                &2.0f64.to_be_bytes(),
+                &[0x00, 0x00, 0x09],
+                &[0x08, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01],
+                b"a",
+                &[0x01, 0x01, 0x00, 0x00, 0x09],
+                &[0x10, 0x00, 0x03],
+                b"Foo",
+                &[0x00, 0x01],
+                b"x",
+                &[0x05, 0x00, 0x00, 0x09],
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn ecma_array_count() {
+        // onMetaData style: says 0, has one member
+        round_trip(
+            &sample(&[
+                &[0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+                b"a",
+                &[0x05, 0x00, 0x00, 0x09],
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn amf0_references() {
+        // The strict array is reference 0 and the object inside it is reference 1
+        round_trip(
+            &sample(&[
+                &[0x0A, 0x00, 0x00, 0x00, 0x02],
+                &[0x03, 0x00, 0x00, 0x09],
+                &[0x07, 0x00, 0x01],
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn amf0_references_past_u16() {
+        // The array is reference 0, so the last of its objects lands on 65537
+        let count = u16::MAX as usize + 2;
+        let mut bytes = vec![0x0A];
+        bytes.extend_from_slice(&(count as u32).to_be_bytes());
+        for _ in 0..count {
+            bytes.extend_from_slice(&[0x03, 0x00, 0x00, 0x09]);
+        }
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let array = reader.roots[0];
+        let ObjectType::Amf0StrictArray(values) = &reader.objects[&array].object_type else {
+            panic!("not a strict array");
+        };
+        let (first, last) = (values[0], values[count - 1]);
+
+        // The last one can't be referenced so goes out again, the first one still can be
+        let mut writer = AMFWriter::new(false);
+        writer
+            .write_document(&reader.objects, &[array, last, first])
+            .unwrap();
+        let written = writer.into_bytes();
+        assert_eq!(&written[..bytes.len()], &bytes[..]);
+        assert_eq!(
+            &written[bytes.len()..],
+            &[0x03, 0x00, 0x00, 0x09, 0x07, 0x00, 0x01]
+        );
+
+        let mut reader = AMFReader::new(&written, false);
+        reader.highlight();
+        assert!(!reader.objects.contains_key(&-1));
+        assert_eq!(reader.roots.len(), 3);
+    }
+
+    #[test]
+    fn command() {
+        round_trip(
+            &sample(&[
+                &[0x00, 0x02, 0x00, 0x07],
+                b"connect",
+                &[0x00],
+                &1.0f64.to_be_bytes(),
+                &[0x03, 0x00, 0x03],
+                b"app",
+                &[0x02, 0x00, 0x04],
+                b"live",
+                &[0x00, 0x00, 0x09, 0x05],
+            ]),
+            true,
+        );
+    }
+
+    #[test]
+    fn amf3_primitives() {
+        round_trip(
+            &sample(&[
+                &[0x11, 0x00, 0x11, 0x01, 0x11, 0x02, 0x11, 0x03],
+                &[0x11, 0x04, 0x7F, 0x11, 0x04, 0x81, 0x00],
+                &[0x11, 0x04, 0xFF, 0xFF, 0xFF, 0xFF],
+                &[0x11, 0x04, 0xC0, 0x80, 0x80, 0x00],
+                &[0x11, 0x05],
+                &f64::NAN.to_be_bytes(),
+                &[0x11, 0x06, 0x07],
+                b"abc",
+                &[0x11, 0x06, 0x01, 0x11, 0x06, 0x00],
+                &[0x11, 0x08, 0x01],
+                &1_700_000_000_000f64.to_be_bytes(),
+                &[0x11, 0x07, 0x09],
+                b"<a/>",
+                &[0x11, 0x0B, 0x09],
+                b"<b/>",
+                &[0x11, 0x0C, 0x07, 0x01, 0x02, 0x03],
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn amf3_arrays_and_objects() {
+        round_trip(
+            &sample(&[
+                // [Foo { a: 1 }, Foo { a: 2 }, <reference to the first Foo>]
+                &[0x11, 0x09, 0x07, 0x01],
+                &[0x0A, 0x13, 0x07],
+                b"Foo",
+                &[0x03],
+                b"a",
+                &[0x04, 0x01],
+                &[0x0A, 0x01, 0x04, 0x02],
+                &[0x0A, 0x02],
+                // { k: "k" } as an associative array, the value is a string reference
+                &[0x11, 0x09, 0x01, 0x03],
+                b"k",
+                &[0x06, 0x00, 0x01],
+                // Anonymous dynamic object
+                &[0x11, 0x0A, 0x0B, 0x01, 0x03],
+                b"x",
+                &[0x04, 0x05, 0x01],
+                &[0x11, 0x0A, 0x07, 0x43],
+                b"flex.messaging.io.ArrayCollection",
+                &[0x09, 0x03, 0x01, 0x04, 0x01],
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn amf3_vectors_and_dictionaries() {
+        round_trip(
+            &sample(&[
+                &[0x11, 0x0D, 0x05, 0x00],
+                &1i32.to_be_bytes(),
+                &(-1i32).to_be_bytes(),
+                &[0x11, 0x0E, 0x03, 0x01],
+                &u32::MAX.to_be_bytes(),
+                &[0x11, 0x0F, 0x03, 0x00],
+                &0.5f64.to_be_bytes(),
+                &[0x11, 0x10, 0x03, 0x00, 0x07],
+                b"Foo",
+                &[0x04, 0x01],
+                &[0x11, 0x11, 0x03, 0x00, 0x06, 0x03],
+                b"k",
+                &[0x04, 0x01],
+            ]),
+            false,
+        );
+    }
+
+    #[test]
+    fn written_values_decode() {
+        let mut writer = AMFWriter::new(false);
+        writer.write_amf0_string("onStatus").unwrap();
+        writer.write_amf0_number(0.0);
+        writer.out.push(0x11);
+        writer.write_amf3_integer(-5);
+        writer.out.push(0x11);
+        writer.write_amf3_integer(i32::MAX);
+
+        let bytes = writer.into_bytes();
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let values: Vec<ObjectType> = reader
+            .roots
+            .iter()
+            .map(|id| match &reader.objects[id].object_type {
+                ObjectType::Amf0Switch(value) => reader.objects[value].object_type.clone(),
+                value => value.clone(),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ObjectType::Amf0String("onStatus".into()),
+                ObjectType::Amf0Number(0.0),
+                ObjectType::Amf3Integer(-5),
+                ObjectType::Amf3Double(i32::MAX as f64),
+            ]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// Caps on what a single buffer may ask the reader to do, so a hostile capture runs into an
+/// error instead of the stack or memory running out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeLimits {
+    pub max_depth: usize,         // Values nested inside values
+    pub max_elements: usize,      // Items in any one array, vector, dictionary or object
+    pub max_string_length: usize, // Bytes in any one string or XML value
+    pub max_objects: usize,       // Objects in the whole document, keys included
+    pub max_stream_bytes: usize,  // Bytes an AMFStream reads before it gives up
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_depth: 256,
+            max_elements: 1 << 20,
+            max_string_length: 64 << 20,
+            max_objects: 1 << 22,
+            max_stream_bytes: 256 << 20,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Depth,
+    Elements,
+    StringLength,
+    Objects,
+    StreamBytes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Depth => write!(f, "Nesting depth"),
+            Limit::Elements => write!(f, "Element count"),
+            Limit::StringLength => write!(f, "String length"),
+            Limit::Objects => write!(f, "Object count"),
+            Limit::StreamBytes => write!(f, "Stream size"),
+        }
+    }
+}
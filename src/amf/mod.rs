@@ -1,6 +1,11 @@
 mod amf3_object;
 pub mod amf_highlight;
-pub(crate) mod object_info;
-pub(crate) mod object_properties;
-pub(crate) mod object_type;
-pub(crate) mod syntax_byte;
+pub mod amf_json;
+pub mod amf_serde;
+pub mod amf_stream;
+pub mod amf_writer;
+pub mod decode_limits;
+pub mod object_info;
+pub mod object_properties;
+pub mod object_type;
+pub mod syntax_byte;
@@ -1,9 +1,36 @@
 use crate::amf::object_properties::TypeProperties;
 use crate::amf::object_type::ObjectType;
+use std::ops::Range;
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct ObjectInfo {
-    pub(crate) object_id: isize,
-    pub(crate) object_type: ObjectType,
-    pub(crate) object_properties: TypeProperties,
+pub struct ObjectInfo<'a> {
+    pub object_id: isize,
+    pub object_type: ObjectType<'a>,
+    pub object_properties: TypeProperties,
+    pub span: Range<usize>, // Bytes of the input this was read from
+}
+
+impl<'a> ObjectInfo<'a> {
+    /// An object built in memory rather than read, it has no bytes to point at yet.
+    pub fn detached(
+        object_id: isize,
+        object_type: ObjectType<'a>,
+        object_properties: TypeProperties,
+    ) -> Self {
+        ObjectInfo {
+            object_id,
+            object_type,
+            object_properties,
+            span: 0..0,
+        }
+    }
+
+    pub fn into_owned(self) -> ObjectInfo<'static> {
+        ObjectInfo {
+            object_id: self.object_id,
+            object_type: self.object_type.into_owned(),
+            object_properties: self.object_properties,
+            span: self.span,
+        }
+    }
 }
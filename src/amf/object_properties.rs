@@ -2,18 +2,35 @@
 pub enum TypeProperties {
     Amf0StringProperties,
     Amf0ObjectProperties,
-    Amf0TypedObjectProperties,
+    Amf0TypedObjectProperties(String), // Class name
+    Amf0EcmaArrayProperties(u32),      // Declared count, encoders often send 0 or a stale one
 
     Amf3StringProperties(GenericProperties),
     Amf3ArrayProperties(GenericProperties),
     Amf3ObjectProperties(ObjectProperties),
+    Amf3VectorProperties(VectorProperties),
+    Amf3DictionaryProperties(DictionaryProperties),
     AmfNoProperties,
 }
 
+impl TypeProperties {
+    /// The ActionScript class a typed object, AMF3 object or object vector was sent with.
+    /// Anonymous objects give None rather than an empty name.
+    pub fn class_name(&self) -> Option<&str> {
+        let name = match self {
+            TypeProperties::Amf0TypedObjectProperties(name) => name,
+            TypeProperties::Amf3ObjectProperties(prop) => &prop.object_type,
+            TypeProperties::Amf3VectorProperties(prop) => &prop.object_type,
+            _ => return None,
+        };
+        Some(name.as_str()).filter(|name| !name.is_empty())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct GenericProperties {
-    pub(crate) is_reference: bool,
-    pub(crate) identifier: i32, // Reference ID when a reference, length when not
+    pub is_reference: bool,
+    pub identifier: i32, // Reference ID when a reference, length when not
 }
 
 impl GenericProperties {
@@ -27,12 +44,12 @@ impl GenericProperties {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ObjectProperties {
-    pub(crate) is_reference: bool,
-    pub(crate) property_count: usize,
-    pub(crate) encoding: usize,
-    pub(crate) externalisable: bool,
-    pub(crate) dynamic: bool,
-    pub(crate) object_type: String,
+    pub is_reference: bool,    // Traits were sent as a reference
+    pub property_count: usize, // Sealed members, dynamic ones follow them
+    pub encoding: usize,
+    pub externalisable: bool,
+    pub dynamic: bool,
+    pub object_type: String,
 }
 
 impl ObjectProperties {
@@ -54,3 +71,29 @@ impl ObjectProperties {
         }
     }
 }
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VectorProperties {
+    pub is_fixed: bool,
+    pub object_type: String, // Only used by Vector.<Object>
+}
+
+impl VectorProperties {
+    pub fn new(is_fixed: bool, object_type: String) -> Self {
+        Self {
+            is_fixed,
+            object_type,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DictionaryProperties {
+    pub weak_keys: bool,
+}
+
+impl DictionaryProperties {
+    pub fn new(weak_keys: bool) -> Self {
+        Self { weak_keys }
+    }
+}
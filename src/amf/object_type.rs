@@ -1,22 +1,25 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
 use std::fmt;
 use std::fmt::Formatter;
 
+// Strings and ByteArrays borrow from the input they were read from, anything built some
+// other way owns its bytes
 #[derive(Clone, Debug, PartialEq)]
-pub enum ObjectType {
+pub enum ObjectType<'a> {
     Amf0Number(f64),
     Amf0Bool(bool),
-    Amf0String(String),
-    Amf0Object,
+    Amf0String(Cow<'a, str>),
+    Amf0Object(Vec<(String, isize)>),
     Amf0Null,
     Amf0Undefined,
-    Amf0EcmaArray,
-    Amf0StrictArray,
-    Amf0Date,
-    Amf0LongString,
-    Amf0XML,
-    Amf0TypedObject,
-    Amf0Switch,
+    Amf0EcmaArray(Vec<(String, isize)>),
+    Amf0StrictArray(Vec<isize>),
+    Amf0Date(f64),
+    Amf0LongString(Cow<'a, str>),
+    Amf0Unsupported,
+    Amf0XML(Cow<'a, str>),
+    Amf0TypedObject(Vec<(String, isize)>),
+    Amf0Switch(isize), // The AMF3 value that follows the marker
 
     Amf3Undefined,
     Amf3Null,
@@ -24,36 +27,37 @@ pub enum ObjectType {
     Amf3True,
     Amf3Integer(i32),
     Amf3Double(f64),
-    Amf3String(String),
-    Amf3XMLDocument,
-    Amf3Date,
-    Amf3Array(Vec<isize>),
-    Amf3Object(HashMap<String, Option<isize>>),
-    Amf3XML,
-    Amf3ByteArray,
-    Amf3VectorInt,
-    Amf3VectorUInt,
-    Amf3VectorDouble,
-    Amf3VectorObject,
-    Amf3Dictionary,
+    Amf3String(Cow<'a, str>),
+    Amf3XMLDocument(Cow<'a, str>),
+    Amf3Date(f64),
+    Amf3Array(Vec<(String, isize)>, Vec<isize>), // Associative part, dense part
+    Amf3Object(Vec<(String, isize)>),            // Sealed members first, then dynamic
+    Amf3XML(Cow<'a, str>),
+    Amf3ByteArray(Cow<'a, [u8]>),
+    Amf3VectorInt(Vec<i32>),
+    Amf3VectorUInt(Vec<u32>),
+    Amf3VectorDouble(Vec<f64>),
+    Amf3VectorObject(Vec<isize>),
+    Amf3Dictionary(Vec<(isize, isize)>),
 }
 
-impl fmt::Display for ObjectType {
+impl fmt::Display for ObjectType<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             ObjectType::Amf0Number(_) => write!(f, "Amf0 Number"),
             ObjectType::Amf0Bool(_) => write!(f, "Amf0 Bool"),
             ObjectType::Amf0String(_) => write!(f, "Amf0 String"),
-            ObjectType::Amf0Object => write!(f, "Amf0 Object"),
+            ObjectType::Amf0Object(_) => write!(f, "Amf0 Object"),
             ObjectType::Amf0Null => write!(f, "Amf0 Null"),
             ObjectType::Amf0Undefined => write!(f, "Amf0 Undefined"),
-            ObjectType::Amf0EcmaArray => write!(f, "Amf0 ECMA Array"),
-            ObjectType::Amf0StrictArray => write!(f, "Amf0 Strict Array"),
-            ObjectType::Amf0Date => write!(f, "Amf0 Date"),
-            ObjectType::Amf0LongString => write!(f, "Amf0 Long String"),
-            ObjectType::Amf0XML => write!(f, "Amf0 XML"),
-            ObjectType::Amf0TypedObject => write!(f, "Amf0 Typed Object"),
-            ObjectType::Amf0Switch => write!(f, "Switch to AMF3"),
+            ObjectType::Amf0EcmaArray(_) => write!(f, "Amf0 ECMA Array"),
+            ObjectType::Amf0StrictArray(_) => write!(f, "Amf0 Strict Array"),
+            ObjectType::Amf0Date(_) => write!(f, "Amf0 Date"),
+            ObjectType::Amf0LongString(_) => write!(f, "Amf0 Long String"),
+            ObjectType::Amf0Unsupported => write!(f, "Amf0 Unsupported"),
+            ObjectType::Amf0XML(_) => write!(f, "Amf0 XML"),
+            ObjectType::Amf0TypedObject(_) => write!(f, "Amf0 Typed Object"),
+            ObjectType::Amf0Switch(_) => write!(f, "Switch to AMF3"),
             ObjectType::Amf3Undefined => write!(f, "Amf3 Undefined"),
             ObjectType::Amf3Null => write!(f, "Amf3 Null"),
             ObjectType::Amf3False => write!(f, "Amf3 False"),
@@ -61,17 +65,89 @@ impl fmt::Display for ObjectType {
             ObjectType::Amf3Integer(_) => write!(f, "Amf3 Integer"),
             ObjectType::Amf3Double(_) => write!(f, "Amf3 Double"),
             ObjectType::Amf3String(_) => write!(f, "Amf3 String"),
-            ObjectType::Amf3XMLDocument => write!(f, "Amf3 XML Document"),
-            ObjectType::Amf3Date => write!(f, "Amf3 Date"),
-            ObjectType::Amf3Array(_) => write!(f, "Amf3 Array"),
+            ObjectType::Amf3XMLDocument(_) => write!(f, "Amf3 XML Document"),
+            ObjectType::Amf3Date(_) => write!(f, "Amf3 Date"),
+            ObjectType::Amf3Array(..) => write!(f, "Amf3 Array"),
             ObjectType::Amf3Object(_) => write!(f, "Amf3 Object"),
-            ObjectType::Amf3XML => write!(f, "Amf3 XML"),
-            ObjectType::Amf3ByteArray => write!(f, "Amf3 Byte Array"),
-            ObjectType::Amf3VectorInt => write!(f, "Amf3 Vector Int"),
-            ObjectType::Amf3VectorUInt => write!(f, "Amf Vector Unsigned Int"),
-            ObjectType::Amf3VectorDouble => write!(f, "Amf3 Vector Double"),
-            ObjectType::Amf3VectorObject => write!(f, "Amf3 Vector Object"),
-            ObjectType::Amf3Dictionary => write!(f, "Amf3 Dictionary"),
+            ObjectType::Amf3XML(_) => write!(f, "Amf3 XML"),
+            ObjectType::Amf3ByteArray(_) => write!(f, "Amf3 Byte Array"),
+            ObjectType::Amf3VectorInt(_) => write!(f, "Amf3 Vector Int"),
+            ObjectType::Amf3VectorUInt(_) => write!(f, "Amf Vector Unsigned Int"),
+            ObjectType::Amf3VectorDouble(_) => write!(f, "Amf3 Vector Double"),
+            ObjectType::Amf3VectorObject(_) => write!(f, "Amf3 Vector Object"),
+            ObjectType::Amf3Dictionary(_) => write!(f, "Amf3 Dictionary"),
+        }
+    }
+}
+
+impl ObjectType<'_> {
+    /// Copies out anything still borrowed from the input, to keep past the input's lifetime.
+    pub fn into_owned(self) -> ObjectType<'static> {
+        let owned = |s: Cow<str>| Cow::Owned(s.into_owned());
+        match self {
+            ObjectType::Amf0Number(number) => ObjectType::Amf0Number(number),
+            ObjectType::Amf0Bool(value) => ObjectType::Amf0Bool(value),
+            ObjectType::Amf0String(s) => ObjectType::Amf0String(owned(s)),
+            ObjectType::Amf0Object(members) => ObjectType::Amf0Object(members),
+            ObjectType::Amf0Null => ObjectType::Amf0Null,
+            ObjectType::Amf0Undefined => ObjectType::Amf0Undefined,
+            ObjectType::Amf0EcmaArray(members) => ObjectType::Amf0EcmaArray(members),
+            ObjectType::Amf0StrictArray(values) => ObjectType::Amf0StrictArray(values),
+            ObjectType::Amf0Date(date) => ObjectType::Amf0Date(date),
+            ObjectType::Amf0LongString(s) => ObjectType::Amf0LongString(owned(s)),
+            ObjectType::Amf0Unsupported => ObjectType::Amf0Unsupported,
+            ObjectType::Amf0XML(s) => ObjectType::Amf0XML(owned(s)),
+            ObjectType::Amf0TypedObject(members) => ObjectType::Amf0TypedObject(members),
+            ObjectType::Amf0Switch(inner) => ObjectType::Amf0Switch(inner),
+            ObjectType::Amf3Undefined => ObjectType::Amf3Undefined,
+            ObjectType::Amf3Null => ObjectType::Amf3Null,
+            ObjectType::Amf3False => ObjectType::Amf3False,
+            ObjectType::Amf3True => ObjectType::Amf3True,
+            ObjectType::Amf3Integer(value) => ObjectType::Amf3Integer(value),
+            ObjectType::Amf3Double(value) => ObjectType::Amf3Double(value),
+            ObjectType::Amf3String(s) => ObjectType::Amf3String(owned(s)),
+            ObjectType::Amf3XMLDocument(s) => ObjectType::Amf3XMLDocument(owned(s)),
+            ObjectType::Amf3Date(date) => ObjectType::Amf3Date(date),
+            ObjectType::Amf3Array(associative, dense) => ObjectType::Amf3Array(associative, dense),
+            ObjectType::Amf3Object(members) => ObjectType::Amf3Object(members),
+            ObjectType::Amf3XML(s) => ObjectType::Amf3XML(owned(s)),
+            ObjectType::Amf3ByteArray(bytes) => {
+                ObjectType::Amf3ByteArray(Cow::Owned(bytes.into_owned()))
+            }
+            ObjectType::Amf3VectorInt(values) => ObjectType::Amf3VectorInt(values),
+            ObjectType::Amf3VectorUInt(values) => ObjectType::Amf3VectorUInt(values),
+            ObjectType::Amf3VectorDouble(values) => ObjectType::Amf3VectorDouble(values),
+            ObjectType::Amf3VectorObject(values) => ObjectType::Amf3VectorObject(values),
+            ObjectType::Amf3Dictionary(entries) => ObjectType::Amf3Dictionary(entries),
+        }
+    }
+
+    /// The values inside a container with a label for each, keys for objects, indices for
+    /// arrays and vectors. Empty for anything that holds its value directly.
+    pub fn children(&self) -> Vec<(String, isize)> {
+        match self {
+            ObjectType::Amf0Object(members)
+            | ObjectType::Amf0EcmaArray(members)
+            | ObjectType::Amf0TypedObject(members)
+            | ObjectType::Amf3Object(members) => members.clone(),
+            ObjectType::Amf3Array(associative, dense) => {
+                let mut children = associative.clone();
+                children.extend(dense.iter().enumerate().map(|(i, id)| (i.to_string(), *id)));
+                children
+            }
+            ObjectType::Amf0StrictArray(values) | ObjectType::Amf3VectorObject(values) => values
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (i.to_string(), *id))
+                .collect(),
+            ObjectType::Amf3Dictionary(entries) => entries
+                .iter()
+                .flat_map(|(key, value)| {
+                    [(String::from("Key"), *key), (String::from("Value"), *value)]
+                })
+                .collect(),
+            ObjectType::Amf0Switch(inner) => vec![(String::from("Value"), *inner)],
+            _ => Vec::new(),
         }
     }
 }
@@ -1,6 +1,14 @@
 #[derive(Clone, Debug)]
 pub struct SyntaxByte {
-    pub value: u8,
+    pub object_id: isize,
+    pub color: String,
+}
+
+// A run of bytes in the input that belong to the same object and share a colour
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxSpan {
+    pub start: usize,
+    pub end: usize,
     pub object_id: isize,
     pub color: String,
 }
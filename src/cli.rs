@@ -0,0 +1,350 @@
+use amf_viewer::amf::amf_highlight::AMFReader;
+use amf_viewer::amf::amf_json::{self, AmfVersion, ExportFormat};
+use amf_viewer::dump::{write_hex, write_tree};
+use amf_viewer::input::Input;
+use amf_viewer::rtmp::command::FormatByte;
+use amf_viewer::rtmp::message::type_name;
+use amf_viewer::rtmp::session::{guess_direction, Session};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    name = "amf-viewer",
+    version,
+    about = "Decode and inspect AMF0/AMF3 payloads"
+)]
+pub struct Cli {
+    // Without one the window opens as usual, when there is one
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print the decoded values as a tree, without opening the window
+    Dump(DumpArgs),
+    /// Write the decoded values out as JSON or YAML
+    Export(ExportArgs),
+    /// Turn JSON or YAML, e.g. from export, back into AMF bytes
+    Encode(EncodeArgs),
+}
+
+#[derive(Args)]
+pub struct DumpArgs {
+    /// File to read, stdin when left out or "-"
+    file: Option<PathBuf>,
+    /// The payload starts with a command's format byte, found on its own when left out
+    #[arg(long)]
+    command: bool,
+    /// The payload doesn't start with a format byte, even if it looks like it does
+    #[arg(long, conflicts_with = "command")]
+    no_command: bool,
+    /// Print a hex dump coloured by object after the tree
+    #[arg(long)]
+    hex: bool,
+    /// The input is an RTMP chunk stream, print each message and the AMF inside it
+    #[arg(long, conflicts_with_all = ["command", "no_command"])]
+    rtmp: bool,
+    /// Capture of the other direction of the same connection, to match requests to responses
+    #[arg(long, requires = "rtmp")]
+    peer: Option<PathBuf>,
+    /// When to colour the output
+    #[arg(long, value_enum, default_value_t = Colour::Auto)]
+    color: Colour,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// File to read, stdin when left out or "-"
+    file: Option<PathBuf>,
+    /// The payload starts with a command's format byte, found on its own when left out
+    #[arg(long)]
+    command: bool,
+    /// The payload doesn't start with a format byte, even if it looks like it does
+    #[arg(long, conflicts_with = "command")]
+    no_command: bool,
+    /// Wrap every value as {"$type": ..., "value": ...} so the AMF types are kept
+    #[arg(long)]
+    types: bool,
+    /// Output format, taken from the output file's extension when not given
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// Where to write, stdout when left out
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct EncodeArgs {
+    /// JSON or YAML file to read, stdin when left out or "-"
+    file: Option<PathBuf>,
+    /// What values without a $type are written as
+    #[arg(long, value_enum, default_value_t = Version::Amf0)]
+    amf: Version,
+    /// Start with a command's format byte
+    #[arg(long)]
+    command: bool,
+    /// Input format, taken from the input file's extension when not given
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// Where to write, stdout when left out
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Version {
+    #[value(name = "0")]
+    Amf0,
+    #[value(name = "3")]
+    Amf3,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Yaml,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Colour {
+    Auto,
+    Always,
+    Never,
+}
+
+fn format_byte(command: bool, no_command: bool) -> FormatByte {
+    match (command, no_command) {
+        (true, _) => FormatByte::Present,
+        (_, true) => FormatByte::Absent,
+        _ => FormatByte::Auto,
+    }
+}
+
+fn read_input(file: &Option<PathBuf>) -> io::Result<Input> {
+    match file {
+        Some(path) if path.as_os_str() != "-" => Input::open(path),
+        _ => {
+            let mut buffer = Vec::new();
+            io::stdin().lock().read_to_end(&mut buffer)?;
+            Ok(Input::from(buffer))
+        }
+    }
+}
+
+fn dump(args: &DumpArgs) -> io::Result<i32> {
+    let input = read_input(&args.file)?;
+    let stdout = io::stdout();
+    let colour = match args.color {
+        Colour::Auto => stdout.is_terminal(),
+        Colour::Always => true,
+        Colour::Never => false,
+    };
+    let mut out = io::BufWriter::new(stdout.lock());
+    if args.rtmp {
+        let peer = match &args.peer {
+            Some(path) => Some(Input::open(path)?),
+            None => None,
+        };
+        return dump_rtmp(&mut out, &input, peer.as_deref(), colour);
+    }
+
+    let is_command = format_byte(args.command, args.no_command).applies(&input);
+    let mut reader = AMFReader::new(&input, is_command);
+    reader.highlight();
+    write_tree(
+        &mut out,
+        &reader.objects,
+        &reader.out,
+        &reader.roots,
+        colour,
+    )?;
+    if args.hex {
+        writeln!(out)?;
+        write_hex(&mut out, &input, &reader.out, colour)?;
+    }
+    out.flush()?;
+
+    if let Some((limit, offset)) = reader.limit_exceeded() {
+        eprintln!("{} limit exceeded at offset {:#X}", limit, offset);
+    }
+    // Still print what we could, but let scripts know it didn't all decode
+    Ok(if reader.objects.contains_key(&-1) {
+        2
+    } else {
+        0
+    })
+}
+
+// Offsets in the trees are into the message payload, not the capture
+fn dump_rtmp<W: Write>(
+    out: &mut W,
+    input: &[u8],
+    peer: Option<&[u8]>,
+    colour: bool,
+) -> io::Result<i32> {
+    let mut code = 0;
+    let direction = guess_direction(input);
+    let mut captures = vec![(direction, input)];
+    if let Some(peer) = peer {
+        captures.push((direction.other(), peer));
+    }
+    let session = Session::new(&captures);
+    for side in &session.sides {
+        let Some(handshake) = &side.handshake else {
+            continue;
+        };
+        writeln!(
+            out,
+            "{:08X}  {} handshake version {} time {} ({}) {}",
+            0,
+            side.direction,
+            handshake.version,
+            handshake.first.time,
+            handshake.kind(),
+            if handshake.is_valid() {
+                "valid"
+            } else {
+                "INVALID"
+            }
+        )?;
+        if !handshake.is_valid() {
+            code = 2;
+        }
+    }
+    for (index, entry) in session.messages.iter().enumerate() {
+        let message = &entry.message;
+        // Sub-messages of an aggregate are indented under it
+        let number = match entry.parent {
+            Some(parent) => format!("  #{} in #{}", index, parent),
+            None => format!("#{}", index),
+        };
+        writeln!(
+            out,
+            "{:08X}  {} {} csid {} stream {} type {} {} time {} ({} bytes)",
+            entry.offset,
+            number,
+            entry.direction,
+            message.csid,
+            message.stream_id,
+            message.type_id,
+            type_name(message.type_id),
+            message.timestamp,
+            message.payload.len()
+        )?;
+        for (name, value) in session.fields(index) {
+            writeln!(out, "{:8}    {}: {}", "", name, value)?;
+        }
+        if let Some(mut reader) = message.amf_reader() {
+            reader.highlight();
+            write_tree(out, &reader.objects, &reader.out, &reader.roots, colour)?;
+            if reader.objects.contains_key(&-1) {
+                code = 2;
+            }
+        }
+    }
+    out.flush()?;
+    // Missing replies are what we're usually after, so they get listed again at the end
+    if peer.is_some() {
+        for transaction in session.unanswered() {
+            let request = &session.messages[transaction.request];
+            if let Some((name, _)) = &request.command {
+                eprintln!(
+                    "amf-viewer: #{} {} (transaction {}) was never answered",
+                    transaction.request, name, transaction.id
+                );
+            }
+        }
+    }
+    for side in &session.sides {
+        if let Some(e) = &side.error {
+            eprintln!("amf-viewer: {}: {}", side.direction, e);
+            code = 2;
+        }
+    }
+    Ok(code)
+}
+
+fn export(args: &ExportArgs) -> io::Result<i32> {
+    let input = read_input(&args.file)?;
+    let is_command = format_byte(args.command, args.no_command).applies(&input);
+    let mut reader = AMFReader::new(&input, is_command);
+    reader.highlight();
+
+    let format = match args.format {
+        Some(Format::Json) => ExportFormat::Json,
+        Some(Format::Yaml) => ExportFormat::Yaml,
+        None => ExportFormat::from_extension(
+            args.output
+                .as_ref()
+                .and_then(|path| path.extension())
+                .and_then(|ext| ext.to_str()),
+        ),
+    };
+    let text = amf_json::export(&reader.objects, &reader.roots, args.types, format);
+    match &args.output {
+        Some(path) => fs::write(path, text)?,
+        None => io::stdout().lock().write_all(text.as_bytes())?,
+    }
+
+    if let Some((limit, offset)) = reader.limit_exceeded() {
+        eprintln!("{} limit exceeded at offset {:#X}", limit, offset);
+    }
+    Ok(if reader.objects.contains_key(&-1) {
+        2
+    } else {
+        0
+    })
+}
+
+fn encode(args: &EncodeArgs) -> io::Result<i32> {
+    let input = read_input(&args.file)?;
+    let format = match args.format {
+        Some(Format::Json) => ExportFormat::Json,
+        Some(Format::Yaml) => ExportFormat::Yaml,
+        None => ExportFormat::from_extension(
+            args.file
+                .as_ref()
+                .and_then(|path| path.extension())
+                .and_then(|ext| ext.to_str()),
+        ),
+    };
+    let version = match args.amf {
+        Version::Amf0 => AmfVersion::Amf0,
+        Version::Amf3 => AmfVersion::Amf3,
+    };
+    let text = String::from_utf8_lossy(&input);
+    let bytes = match amf_json::import(&text, format, version, args.command) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("amf-viewer: {}", e);
+            return Ok(2);
+        }
+    };
+    match &args.output {
+        Some(path) => fs::write(path, bytes)?,
+        None => io::stdout().lock().write_all(&bytes)?,
+    }
+    Ok(0)
+}
+
+/// Runs a subcommand and gives the exit code for it.
+pub fn run(command: Command) -> i32 {
+    let result = match &command {
+        Command::Dump(args) => dump(args),
+        Command::Export(args) => export(args),
+        Command::Encode(args) => encode(args),
+    };
+    match result {
+        Ok(code) => code,
+        // Piped into head or less and they stopped reading, that's fine
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("amf-viewer: {}", e);
+            1
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::amf::amf_highlight::AMF_ERROR;
+use crate::amf::object_info::ObjectInfo;
+use crate::amf::object_type::ObjectType;
+use crate::amf::syntax_byte::SyntaxSpan;
+use crate::hex_view::{hex_row, row_count, spans_in, ROW_BYTES};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const MAX_INLINE_BYTES: usize = 16; // ByteArrays longer than this are cut short in the tree
+
+// Catppuccin Mocha, the same palette the GUI uses in dark mode
+const PALETTE: [(&str, [u8; 3]); 26] = [
+    ("rosewater", [0xf5, 0xe0, 0xdc]),
+    ("flamingo", [0xf2, 0xcd, 0xcd]),
+    ("pink", [0xf5, 0xc2, 0xe7]),
+    ("mauve", [0xcb, 0xa6, 0xf7]),
+    ("red", [0xf3, 0x8b, 0xa8]),
+    ("maroon", [0xeb, 0xa0, 0xac]),
+    ("peach", [0xfa, 0xb3, 0x87]),
+    ("yellow", [0xf9, 0xe2, 0xaf]),
+    ("green", [0xa6, 0xe3, 0xa1]),
+    ("teal", [0x94, 0xe2, 0xd5]),
+    ("sky", [0x89, 0xdc, 0xeb]),
+    ("sapphire", [0x74, 0xc7, 0xec]),
+    ("blue", [0x89, 0xb4, 0xfa]),
+    ("lavender", [0xb4, 0xbe, 0xfe]),
+    ("text", [0xcd, 0xd6, 0xf4]),
+    ("subtext1", [0xba, 0xc2, 0xde]),
+    ("subtext0", [0xa6, 0xad, 0xc8]),
+    ("overlay2", [0x93, 0x99, 0xb2]),
+    ("overlay1", [0x7f, 0x84, 0x9c]),
+    ("overlay0", [0x6c, 0x70, 0x86]),
+    ("surface2", [0x58, 0x5b, 0x70]),
+    ("surface1", [0x45, 0x47, 0x5a]),
+    ("surface0", [0x31, 0x32, 0x44]),
+    ("base", [0x1e, 0x1e, 0x2e]),
+    ("mantle", [0x18, 0x18, 0x25]),
+    ("crust", [0x11, 0x11, 0x1b]),
+];
+
+/// Turns one of the reader's colour classes, e.g. `text-ctp-teal/60`, into a 24-bit ANSI
+/// foreground escape. The opacity is blended over the base colour like the webview does.
+pub fn ansi_colour(class: &str) -> Option<String> {
+    // Bytes the reader gave up on use a plain Tailwind class, closest here is red
+    let colour = match class {
+        AMF_ERROR => "red",
+        _ => class.strip_prefix("text-ctp-")?,
+    };
+    let (name, opacity) = match colour.split_once('/') {
+        Some((name, opacity)) => (name, opacity.parse::<u32>().ok()?.min(100)),
+        None => (colour, 100),
+    };
+    let (_, rgb) = PALETTE.iter().find(|(n, _)| *n == name)?;
+    let base = PALETTE[23].1;
+    let [r, g, b] =
+        [0, 1, 2].map(|i| (rgb[i] as u32 * opacity + base[i] as u32 * (100 - opacity)) / 100);
+    Some(format!("\x1b[38;2;{};{};{}m", r, g, b))
+}
+
+fn paint(text: &str, class: Option<&str>, colour: bool) -> String {
+    match class.and_then(ansi_colour) {
+        Some(escape) if colour => format!("{}{}{}", escape, text, RESET),
+        _ => text.to_string(),
+    }
+}
+
+// What goes after the type for values that aren't containers
+fn leaf_value(object_type: &ObjectType) -> Option<String> {
+    let value = match object_type {
+        ObjectType::Amf0Number(value) | ObjectType::Amf3Double(value) => value.to_string(),
+        ObjectType::Amf0Date(value) | ObjectType::Amf3Date(value) => value.to_string(),
+        ObjectType::Amf0Bool(value) => value.to_string(),
+        ObjectType::Amf3Integer(value) => value.to_string(),
+        ObjectType::Amf0String(value)
+        | ObjectType::Amf0LongString(value)
+        | ObjectType::Amf0XML(value)
+        | ObjectType::Amf3String(value)
+        | ObjectType::Amf3XML(value)
+        | ObjectType::Amf3XMLDocument(value) => format!("{:?}", value),
+        ObjectType::Amf3ByteArray(value) => {
+            let shown: Vec<String> = value
+                .iter()
+                .take(MAX_INLINE_BYTES)
+                .map(|b| format!("{:02X}", b))
+                .collect();
+            let more = if value.len() > MAX_INLINE_BYTES {
+                " .."
+            } else {
+                ""
+            };
+            format!("[{} bytes] {}{}", value.len(), shown.join(" "), more)
+        }
+        ObjectType::Amf3VectorInt(value) => format!("{:?}", value),
+        ObjectType::Amf3VectorUInt(value) => format!("{:?}", value),
+        ObjectType::Amf3VectorDouble(value) => format!("{:?}", value),
+        _ => return None,
+    };
+    Some(value)
+}
+
+struct Tree<'a, W: Write> {
+    out: &'a mut W,
+    objects: &'a HashMap<isize, ObjectInfo<'a>>,
+    spans: &'a [SyntaxSpan],
+    colour: bool,
+    seen: HashSet<isize>, // Containers already written out, later uses are references
+}
+
+impl<W: Write> Tree<'_, W> {
+    fn value(&mut self, object_id: isize, key: Option<&str>, depth: usize) -> io::Result<()> {
+        let Some(info) = self.objects.get(&object_id) else {
+            return writeln!(
+                self.out,
+                "{:8}  {}missing object #{}",
+                "",
+                "  ".repeat(depth),
+                object_id
+            );
+        };
+        let children = info.object_type.children();
+        let class = spans_in(self.spans, info.span.start..info.span.start + 1)
+            .first()
+            .map(|span| span.color.as_str());
+
+        let mut line = info.object_type.to_string();
+        if let Some(name) = info.object_properties.class_name() {
+            line += &format!(" {}", name);
+        }
+        line += &format!(" #{}", object_id);
+        let repeat = !children.is_empty() && !self.seen.insert(object_id);
+        if repeat {
+            line += " (reference, see above)";
+        } else if let Some(value) = leaf_value(&info.object_type) {
+            line += &format!(" = {}", value);
+        }
+
+        let key = key.map(|key| format!("{}: ", key)).unwrap_or_default();
+        let offset = format!("{:08X}", info.span.start);
+        writeln!(
+            self.out,
+            "{}  {}{}{}",
+            paint(&offset, Some("text-ctp-overlay0"), self.colour),
+            "  ".repeat(depth),
+            key,
+            paint(&line, class, self.colour)
+        )?;
+        if repeat {
+            return Ok(());
+        }
+        for (key, child) in children {
+            self.value(child, Some(&key), depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the decoded values as an indented tree, one line per value with the offset it was
+/// read from. Containers that turn up again through a reference are only expanded once.
+pub fn write_tree<W: Write>(
+    out: &mut W,
+    objects: &HashMap<isize, ObjectInfo>,
+    spans: &[SyntaxSpan],
+    roots: &[isize],
+    colour: bool,
+) -> io::Result<()> {
+    let mut tree = Tree {
+        out,
+        objects,
+        spans,
+        colour,
+        seen: HashSet::new(),
+    };
+    for root in roots {
+        tree.value(*root, None, 0)?;
+    }
+    if let Some(error) = objects.get(&-1) {
+        let line = format!("{:08X}  Could not decode any further", error.span.start);
+        writeln!(out, "{}", paint(&line, Some("text-ctp-red"), colour))?;
+    }
+    Ok(())
+}
+
+/// A 16 byte a row hex dump with an ASCII column, each byte in its object's colour.
+pub fn write_hex<W: Write>(
+    out: &mut W,
+    buffer: &[u8],
+    spans: &[SyntaxSpan],
+    colour: bool,
+) -> io::Result<()> {
+    for row in 0..row_count(buffer.len()) {
+        let cells = hex_row(buffer, spans, row);
+        let mut line = format!("{:08X}  ", row * ROW_BYTES);
+        if colour {
+            line = format!("{}{}{}", DIM, line, RESET);
+        }
+        let mut ascii = String::new();
+        for cell in &cells {
+            let class = cell.span.map(|span| span.color.as_str());
+            line += &paint(&format!("{:02X}", cell.value), class, colour);
+            line.push(' ');
+            let c = if cell.value.is_ascii_graphic() || cell.value == b' ' {
+                cell.value as char
+            } else {
+                '.'
+            };
+            ascii += &paint(&c.to_string(), class, colour);
+        }
+        line += &"   ".repeat(ROW_BYTES - cells.len());
+        writeln!(out, "{} |{}|", line, ascii)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_highlight::AMFReader;
+
+    #[test]
+    fn tree() {
+        // "a", then {x: 1.0, y: <reference to the object itself>}
+        let bytes = [
+            0x02, 0x00, 0x01, b'a', 0x03, 0x00, 0x01, b'x', 0x00, 0x3F, 0xF0, 0, 0, 0, 0, 0, 0,
+            0x00, 0x01, b'y', 0x07, 0x00, 0x00, 0x00, 0x00, 0x09,
+        ];
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let mut out = Vec::new();
+        write_tree(&mut out, &reader.objects, &reader.out, &reader.roots, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "00000000  Amf0 String #0 = \"a\"\n\
+             00000004  Amf0 Object #1\n\
+             00000008    x: Amf0 Number #3 = 1\n\
+             00000004    y: Amf0 Object #1 (reference, see above)\n"
+        );
+    }
+
+    #[test]
+    fn hex() {
+        let bytes = [0x02, 0x00, 0x01, b'a'];
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let mut out = Vec::new();
+        write_hex(&mut out, &bytes, &reader.out, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("00000000  02 00 01 61 {} |...a|\n", "   ".repeat(12))
+        );
+
+        let mut out = Vec::new();
+        write_hex(&mut out, &bytes, &reader.out, true).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("\x1b[38;2;"));
+    }
+
+    #[test]
+    fn hex_error() {
+        // A string that says 5 bytes but only has 1
+        let bytes = [0x02, 0x00, 0x05, b'a'];
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let mut out = Vec::new();
+        write_hex(&mut out, &bytes, &reader.out, true).unwrap();
+        let red = ansi_colour("text-ctp-red").unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains(&format!("{}61{}", red, RESET)));
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!(
+            ansi_colour("text-ctp-blue").as_deref(),
+            Some("\x1b[38;2;137;180;250m")
+        );
+        // Half way between red and the background
+        assert_eq!(
+            ansi_colour("text-ctp-red/50").as_deref(),
+            Some("\x1b[38;2;136;84;107m")
+        );
+        assert_eq!(ansi_colour(AMF_ERROR), ansi_colour("text-ctp-red"));
+        assert_eq!(ansi_colour("hex"), None);
+        assert_eq!(ansi_colour("text-ctp-nope"), None);
+    }
+}
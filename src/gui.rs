@@ -0,0 +1,1171 @@
+use amf_viewer::amf::amf_highlight::AMFReader;
+use amf_viewer::amf::amf_json::{self, AmfVersion, ExportFormat};
+use std::collections::HashMap;
+
+use amf_viewer::amf::object_info::ObjectInfo;
+use amf_viewer::amf::object_properties::TypeProperties;
+use amf_viewer::amf::object_type::ObjectType;
+use amf_viewer::amf::syntax_byte::SyntaxSpan;
+use amf_viewer::hex_view::{hex_row, hex_window, HexCell, ROW_BYTES};
+use amf_viewer::input::Input;
+use amf_viewer::rtmp::command::FormatByte;
+use amf_viewer::rtmp::message::type_name;
+use amf_viewer::rtmp::session::{guess_direction, is_capture, Session};
+use dioxus::desktop::tao::dpi::Size;
+use dioxus::desktop::{tao, LogicalSize};
+use dioxus::dioxus_core::SpawnIfAsync;
+use dioxus::logger::tracing;
+use dioxus::prelude::*;
+use native_dialog::{DialogBuilder, MessageLevel};
+use rfd::FileDialog;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const FAVICON: Asset = asset!("/assets/favicon.ico");
+const MAIN_CSS: Asset = asset!("/assets/main.css");
+
+const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100); // How often the decode progress updates
+
+struct OpenedFile {
+    is_open: bool,
+    path: PathBuf,
+    buffer: Arc<Input>, // Read or mapped once when opened, shared with the decode task
+    peer: Option<Arc<Input>>, // The other direction of an RTMP capture
+    format_byte: FormatByte, // Whether the payload starts with a command's format byte
+}
+
+impl OpenedFile {
+    pub fn new() -> Self {
+        OpenedFile {
+            is_open: false,
+            path: PathBuf::new(),
+            buffer: Arc::default(),
+            peer: None,
+            format_byte: FormatByte::Auto,
+        }
+    }
+}
+
+// What's picked in the message list of an RTMP capture
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Selection {
+    Handshake(usize), // Index into the session's sides
+    Message(usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ObjectContext {
+    objects: Signal<HashMap<isize, ObjectInfo<'static>>>,
+    roots: Signal<Vec<isize>>,
+    selected_index: Signal<isize>,
+    has_selected: Signal<bool>,
+    session: Signal<Option<Arc<Session>>>, // Set when the file is an RTMP capture
+    selection: Signal<Option<Selection>>,
+    message_spans: Signal<Vec<SyntaxSpan>>, // Colours for the selected message's payload
+}
+
+impl ObjectContext {
+    pub fn new() -> Self {
+        Self {
+            objects: Signal::new(HashMap::new()),
+            roots: Signal::new(Vec::new()),
+            selected_index: Signal::new(0),
+            has_selected: Signal::new(false),
+            session: Signal::new(None),
+            selection: Signal::new(None),
+            message_spans: Signal::new(Vec::new()),
+        }
+    }
+}
+
+// The UI keeps objects around after the buffer they were read from is gone
+fn owned(objects: HashMap<isize, ObjectInfo>) -> HashMap<isize, ObjectInfo<'static>> {
+    objects
+        .into_iter()
+        .map(|(id, info)| (id, info.into_owned()))
+        .collect()
+}
+
+// Decodes the picked message's payload on its own, which is what the hex pane and inspector
+// show for a capture. Peeks at the session so a resource calling this doesn't rerun on it.
+fn select(mut context: ObjectContext, selection: Selection) {
+    context.selection.set(Some(selection));
+    context.objects.set(HashMap::new());
+    context.roots.set(Vec::new());
+    context.message_spans.set(Vec::new());
+    context.has_selected.set(false);
+    let Selection::Message(index) = selection else {
+        return;
+    };
+    let session = (*context.session.peek()).clone();
+    let Some(entry) = session
+        .as_ref()
+        .and_then(|session| session.messages.get(index))
+    else {
+        return;
+    };
+    let Some(mut reader) = entry.message.amf_reader() else {
+        return;
+    };
+    reader.highlight();
+    let first = reader.roots.first().copied();
+    context.objects.set(owned(reader.objects));
+    context.roots.set(reader.roots);
+    context.message_spans.set(reader.out);
+    if let Some(first) = first {
+        context.selected_index.set(first);
+        context.has_selected.set(true);
+    }
+}
+
+static CURRENT_FILE: GlobalSignal<OpenedFile> = Global::new(|| OpenedFile::new());
+
+pub fn launch() {
+    let window = tao::window::WindowBuilder::new()
+        .with_resizable(true)
+        .with_min_inner_size(Size::Logical(LogicalSize {
+            width: 1280.0,
+            height: 720.0,
+        }))
+        .with_title(format!("AMF Viewer v{}", env!("CARGO_PKG_VERSION")));
+    dioxus::LaunchBuilder::new()
+        .with_cfg(
+            dioxus::desktop::Config::new()
+                .with_window(window)
+                .with_menu(None),
+        )
+        .launch(App);
+}
+
+fn show_error(title: &str, body: String) {
+    DialogBuilder::message()
+        .set_level(MessageLevel::Error)
+        .set_title(title)
+        .set_text(body)
+        .alert()
+        .spawn()
+        .show()
+        .unwrap();
+}
+
+#[component]
+fn App() -> Element {
+    let state = use_context_provider(|| ObjectContext::new());
+
+    rsx! {
+        document::Link { rel: "icon", href: FAVICON }
+        document::Link { rel: "stylesheet", href: MAIN_CSS }
+        document::Link { rel: "stylesheet", href: TAILWIND_CSS }
+        div {
+            class: "ctp-latte dark:ctp-mocha bg-ctp-base min-w-full max-h-screen min-h-screen flex flex-row",
+            LeftBar {}
+            CentreBox {}
+            RightBar {}
+        }
+    }
+}
+
+#[component]
+fn LeftBar() -> Element {
+    let obj_context = use_context::<ObjectContext>();
+    let has_session = obj_context.session.read().is_some();
+    let mut export_types = use_signal(|| false);
+    let mut import_amf3 = use_signal(|| false);
+    let mut import_command = use_signal(|| false);
+
+    rsx! {
+        div {
+            class: "bg-ctp-crust grow-[3] m-3 outline outline-2 outline-ctp-pink p-2 rounded-md flex flex-row",
+            div {
+                div {
+                    class: "h-fit m-2",
+                    button {
+                        class: "bg-ctp-surface0 outline outline-2 outline-ctp-pink text-ctp-text hover:outline-ctp-blue hover:bg-ctp-lavender hover:text-ctp-crust py-2 px-4 rounded",
+                        onclick: move |_| {
+                            let mut handle = CURRENT_FILE.write();
+                            handle.is_open = false;
+                            handle.path = PathBuf::new();
+                            handle.buffer = Arc::default();
+                            handle.peer = None;
+                            let path = FileDialog::new().pick_file();
+                            let path = match path {
+                                Some(path) => path,
+                                None => {
+                                    show_error("Error: Could not open file",
+                                        format!("Could not open file:\n{:?}", path));
+                                    return
+                                }
+                            };
+                            let mut obj_context = use_context::<ObjectContext>();
+                            obj_context.objects.set(HashMap::new());
+                            obj_context.has_selected.set(false);
+                            obj_context.selected_index.set(0);
+                            obj_context.session.set(None);
+                            obj_context.selection.set(None);
+                            obj_context.message_spans.set(Vec::new());
+
+                            let buffer = match Input::open(&path) {
+                                Ok(buffer) => buffer,
+                                Err(e) => {
+                                    show_error("Error: Could not open file",
+                                        format!("Could not open file:\n{:?}", e));
+                                    return
+                                }
+                            };
+                            handle.is_open = true;
+                            handle.path = path;
+                            handle.buffer = Arc::new(buffer);
+                        },
+                        "Open File"
+                    }
+                }
+                if !has_session {
+                    div {
+                        class: "h-fit m-2",
+                        label {
+                            class: "pr-2 text-ctp-text",
+                            "Format byte"
+                        }
+                        select {
+                            class: "bg-ctp-surface0 text-ctp-text rounded p-1",
+                            onchange: move |event| {
+                                let format_byte = match event.value().as_str() {
+                                    "present" => FormatByte::Present,
+                                    "absent" => FormatByte::Absent,
+                                    _ => FormatByte::Auto,
+                                };
+                                let mut obj_context = use_context::<ObjectContext>();
+                                obj_context.selected_index.set(0);
+                                CURRENT_FILE.write().format_byte = format_byte;
+                            },
+                            option { value: "auto", "Detect" }
+                            option { value: "present", "Yes" }
+                            option { value: "absent", "No" }
+                        }
+                    }
+                }
+                if has_session {
+                    div {
+                        class: "h-fit m-2",
+                        button {
+                            class: "bg-ctp-surface0 outline outline-2 outline-ctp-pink text-ctp-text hover:outline-ctp-blue hover:bg-ctp-lavender hover:text-ctp-crust py-2 px-4 rounded",
+                            onclick: move |_| {
+                                let Some(path) = FileDialog::new().pick_file() else {
+                                    return
+                                };
+                                match Input::open(&path) {
+                                    Ok(peer) => CURRENT_FILE.write().peer = Some(Arc::new(peer)),
+                                    Err(e) => show_error("Error: Could not open file",
+                                        format!("Could not open file:\n{:?}", e)),
+                                }
+                            },
+                            "Open Other Side…"
+                        }
+                    }
+                }
+                div {
+                    class: "h-fit m-2",
+                    button {
+                        class: "bg-ctp-surface0 outline outline-2 outline-ctp-pink text-ctp-text hover:outline-ctp-blue hover:bg-ctp-lavender hover:text-ctp-crust py-2 px-4 rounded",
+                        onclick: move |_| {
+                            let obj_context = use_context::<ObjectContext>();
+                            if obj_context.roots.read().is_empty() {
+                                show_error("Error: Nothing to export",
+                                    "Open a file that decodes to at least one value first".to_string());
+                                return
+                            }
+                            let Some(path) = FileDialog::new()
+                                .add_filter("JSON", &["json"])
+                                .add_filter("YAML", &["yaml", "yml"])
+                                .set_file_name("export.json")
+                                .save_file() else {
+                                return
+                            };
+                            let format = ExportFormat::from_extension(
+                                path.extension().and_then(|ext| ext.to_str()));
+                            let text = amf_json::export(&obj_context.objects.read(),
+                                &obj_context.roots.read(), export_types(), format);
+                            if let Err(e) = std::fs::write(&path, text) {
+                                show_error("Error: Could not export",
+                                    format!("Could not write {:?}:\n{:?}", path, e));
+                            }
+                        },
+                        "Export…"
+                    }
+                }
+                div {
+                    class: "h-fit m-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: export_types,
+                        oninput: move |_| export_types.set(!export_types()),
+                    }
+                    label {
+                        class: "pl-2 text-ctp-text",
+                        "Include types"
+                    }
+                }
+                div {
+                    class: "h-fit m-2",
+                    button {
+                        class: "bg-ctp-surface0 outline outline-2 outline-ctp-pink text-ctp-text hover:outline-ctp-blue hover:bg-ctp-lavender hover:text-ctp-crust py-2 px-4 rounded",
+                        onclick: move |_| {
+                            let Some(path) = FileDialog::new()
+                                .add_filter("JSON", &["json"])
+                                .add_filter("YAML", &["yaml", "yml"])
+                                .pick_file() else {
+                                return
+                            };
+                            let text = match std::fs::read_to_string(&path) {
+                                Ok(text) => text,
+                                Err(e) => {
+                                    show_error("Error: Could not open file",
+                                        format!("Could not open file:\n{:?}", e));
+                                    return
+                                }
+                            };
+                            let format = ExportFormat::from_extension(
+                                path.extension().and_then(|ext| ext.to_str()));
+                            let version = if import_amf3() {
+                                AmfVersion::Amf3
+                            } else {
+                                AmfVersion::Amf0
+                            };
+                            let bytes = match amf_json::import(&text, format, version,
+                                import_command()) {
+                                Ok(bytes) => bytes,
+                                Err(e) => {
+                                    show_error("Error: Could not encode", e.to_string());
+                                    return
+                                }
+                            };
+                            let Some(out) = FileDialog::new()
+                                .set_file_name("encoded.amf")
+                                .save_file() else {
+                                return
+                            };
+                            if let Err(e) = std::fs::write(&out, bytes) {
+                                show_error("Error: Could not encode",
+                                    format!("Could not write {:?}:\n{:?}", out, e));
+                            }
+                        },
+                        "Encode JSON…"
+                    }
+                }
+                div {
+                    class: "h-fit m-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: import_amf3,
+                        oninput: move |_| import_amf3.set(!import_amf3()),
+                    }
+                    label {
+                        class: "pl-2 text-ctp-text",
+                        "Plain values as AMF3"
+                    }
+                }
+                div {
+                    class: "h-fit m-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: import_command,
+                        oninput: move |_| import_command.set(!import_command()),
+                    }
+                    label {
+                        class: "pl-2 text-ctp-text",
+                        "Command format byte"
+                    }
+                }
+            }
+            if has_session {
+                MessageList {}
+            }
+        }
+    }
+}
+
+// Time, direction, chunk stream, message stream, type and command name for the list
+fn message_columns(session: &Session, row: Selection) -> [String; 6] {
+    match row {
+        Selection::Handshake(side) => {
+            let side = &session.sides[side];
+            let time = side
+                .handshake
+                .as_ref()
+                .map_or(0, |handshake| handshake.first.time);
+            [
+                time.to_string(),
+                side.direction.to_string(),
+                String::new(),
+                String::new(),
+                String::from("Handshake"),
+                String::new(),
+            ]
+        }
+        Selection::Message(index) => {
+            let entry = &session.messages[index];
+            let message = &entry.message;
+            // Sub-messages of an aggregate are marked as such
+            let nesting = if entry.parent.is_some() { "↳ " } else { "" };
+            let command = entry.command.as_ref().map(|(name, _)| name.clone());
+            [
+                message.timestamp.to_string(),
+                entry.direction.to_string(),
+                message.csid.to_string(),
+                message.stream_id.to_string(),
+                format!(
+                    "{}{} {}",
+                    nesting,
+                    message.type_id,
+                    type_name(message.type_id)
+                ),
+                command.unwrap_or_default(),
+            ]
+        }
+    }
+}
+
+// Type and command names need the room, the numbers don't
+fn column_class(column: usize) -> &'static str {
+    match column {
+        4 | 5 => "message-cell message-cell-wide",
+        _ => "message-cell",
+    }
+}
+
+// Requests with both sides loaded and still no response, what we're usually looking for
+fn is_unanswered(session: &Session, row: Selection) -> bool {
+    let Selection::Message(index) = row else {
+        return false;
+    };
+    session.sides.len() > 1
+        && session.transaction_of(index).is_some_and(|transaction| {
+            transaction.request == index && transaction.response.is_none()
+        })
+}
+
+#[component]
+fn MessageList() -> Element {
+    let obj_context = use_context::<ObjectContext>();
+    let mut type_filter = use_signal(|| None::<u8>);
+    let mut stream_filter = use_signal(|| None::<u32>);
+    let mut scroller = use_signal(|| None::<Rc<MountedData>>);
+    let mut scroll_top = use_signal(|| 0.0);
+    let mut viewport_height = use_signal(|| 600.0);
+
+    let Some(session) = obj_context.session.cloned() else {
+        return rsx! {};
+    };
+    let selection = *obj_context.selection.read();
+
+    let mut types: Vec<u8> = session
+        .messages
+        .iter()
+        .map(|entry| entry.message.type_id)
+        .collect();
+    types.sort();
+    types.dedup();
+    let mut streams: Vec<u32> = session
+        .messages
+        .iter()
+        .map(|entry| entry.message.stream_id)
+        .collect();
+    streams.sort();
+    streams.dedup();
+
+    // Handshakes first, then the messages that get through the filters
+    let rows: Vec<Selection> = session
+        .sides
+        .iter()
+        .enumerate()
+        .filter(|(_, side)| side.handshake.is_some())
+        .map(|(side, _)| Selection::Handshake(side))
+        .chain(
+            session
+                .messages
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| {
+                    type_filter().is_none_or(|type_id| entry.message.type_id == type_id)
+                        && stream_filter().is_none_or(|stream| entry.message.stream_id == stream)
+                })
+                .map(|(index, _)| Selection::Message(index)),
+        )
+        .collect();
+    // Same windowing as the hex pane, one list row standing in for each row of bytes
+    let window = hex_window(rows.len() * ROW_BYTES, scroll_top(), viewport_height());
+    let visible: Vec<(Selection, [String; 6], String)> = window
+        .rows
+        .clone()
+        .map(|i| {
+            let row = rows[i];
+            let class = match (Some(row) == selection, is_unanswered(&session, row)) {
+                (true, _) => "hex-row message-row bg-ctp-surface1 text-ctp-text",
+                (false, true) => "hex-row message-row text-ctp-red",
+                (false, false) => "hex-row message-row text-ctp-text",
+            };
+            (row, message_columns(&session, row), class.to_string())
+        })
+        .collect();
+
+    rsx! {
+        div {
+            class: "message-list m-2",
+            div {
+                class: "flex flex-row",
+                select {
+                    class: "bg-ctp-surface0 text-ctp-text rounded p-1 m-1",
+                    onchange: move |event| type_filter.set(event.value().parse().ok()),
+                    option { value: "", "All types" }
+                    for type_id in types {
+                        option {
+                            value: "{type_id}",
+                            {format!("{} {}", type_id, type_name(type_id))}
+                        }
+                    }
+                }
+                select {
+                    class: "bg-ctp-surface0 text-ctp-text rounded p-1 m-1",
+                    onchange: move |event| stream_filter.set(event.value().parse().ok()),
+                    option { value: "", "All streams" }
+                    for stream in streams {
+                        option {
+                            value: "{stream}",
+                            {format!("Stream {}", stream)}
+                        }
+                    }
+                }
+            }
+            div {
+                class: "hex-row message-row text-ctp-subtext0 font-bold",
+                for (i, title) in ["Time", "Dir", "CSID", "MSID", "Type", "Command"].iter().enumerate() {
+                    span {
+                        class: column_class(i),
+                        "{title}"
+                    }
+                }
+            }
+            div {
+                class: "hex-scroll",
+                onmounted: move |event| async move {
+                    let data = event.data();
+                    if let Ok(rect) = data.get_client_rect().await {
+                        viewport_height.set(rect.height());
+                    }
+                    scroller.set(Some(data));
+                },
+                onscroll: move |_| async move {
+                    let Some(data) = scroller() else { return };
+                    if let Ok(offset) = data.get_scroll_offset().await {
+                        scroll_top.set(offset.y);
+                    }
+                    if let Ok(rect) = data.get_client_rect().await {
+                        viewport_height.set(rect.height());
+                    }
+                },
+                div {
+                    class: "hex-spacer",
+                    style: format!("height: {}px;", window.total_height),
+                    div {
+                        class: "hex-rows",
+                        style: format!("top: {}px;", window.offset),
+                        for (row, columns, class) in visible {
+                            div {
+                                key: "{row:?}",
+                                class: class,
+                                onclick: move |_| select(obj_context, row),
+                                for (i, column) in columns.into_iter().enumerate() {
+                                    span {
+                                        class: column_class(i),
+                                        "{column}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn CentreBox() -> Element {
+    let file = CURRENT_FILE.read();
+    rsx! {
+        div {
+            class: "bg-ctp-crust m-3 grow-[7] max-w-[66%] outline outline-2 outline-ctp-pink p-2 rounded-md scroll-auto overflow-auto",
+            h1 {
+                class: "text-ctp-text",
+            }
+            if file.is_open {
+                FileOpened {}
+            }
+        }
+    }
+}
+
+// Everything one decode of the file gives us, built off the UI thread and kept until another
+// file is opened, so scrolling and clicking never decode again
+struct Document {
+    objects: HashMap<isize, ObjectInfo<'static>>,
+    roots: Vec<isize>,
+    spans: Vec<SyntaxSpan>,
+    limit_message: Option<String>,
+    session: Option<Session>,
+}
+
+fn decode(
+    buffer: &[u8],
+    peer: Option<&[u8]>,
+    format_byte: FormatByte,
+    progress: &AtomicUsize,
+) -> Document {
+    // RTMP captures are split into messages, each is decoded when it's picked from the list
+    if is_capture(buffer) {
+        let direction = guess_direction(buffer);
+        let mut captures = vec![(direction, buffer)];
+        if let Some(peer) = peer {
+            captures.push((direction.other(), peer));
+        }
+        return Document {
+            objects: HashMap::new(),
+            roots: Vec::new(),
+            spans: Vec::new(),
+            limit_message: None,
+            session: Some(Session::new(&captures)),
+        };
+    }
+    // AMF3 command payloads (type 17) start with a format byte that isn't part of the AMF
+    let is_command = format_byte.applies(buffer);
+    let mut reader = AMFReader::new(buffer, is_command).with_progress(progress);
+    reader.highlight();
+    let limit_message = reader.limit_exceeded().map(|(limit, offset)| {
+        format!(
+            "{} limit exceeded at offset {:#X}, the rest was not decoded",
+            limit, offset
+        )
+    });
+    Document {
+        objects: owned(reader.objects),
+        roots: reader.roots,
+        spans: reader.out,
+        limit_message,
+        session: None,
+    }
+}
+
+fn cell_class(cell: &HexCell, selected: isize) -> String {
+    match cell.span {
+        Some(span) if span.object_id == selected => {
+            format!("{} hex-byte outline outline-2", span.color)
+        }
+        Some(span) => format!("{} hex-byte", span.color),
+        None => String::from("text-ctp-overlay0 hex-byte"),
+    }
+}
+
+#[component]
+fn FileOpened() -> Element {
+    let mut obj_context = use_context::<ObjectContext>();
+    let mut scroller = use_signal(|| None::<Rc<MountedData>>);
+    let mut scroll_top = use_signal(|| 0.0);
+    let mut viewport_height = use_signal(|| 800.0);
+    let mut decoded_bytes = use_signal(|| 0usize);
+
+    // Reruns only when the file or its format byte changes
+    let document = use_resource(move || async move {
+        let (buffer, peer, format_byte) = {
+            let file = CURRENT_FILE.read();
+            (file.buffer.clone(), file.peer.clone(), file.format_byte)
+        };
+        obj_context.has_selected.set(false);
+        decoded_bytes.set(0);
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut task = tokio::task::spawn_blocking({
+            let counter = counter.clone();
+            move || {
+                decode(
+                    &buffer,
+                    peer.as_deref().map(|peer| &**peer),
+                    format_byte,
+                    &counter,
+                )
+            }
+        });
+        let mut document = loop {
+            match tokio::time::timeout(PROGRESS_INTERVAL, &mut task).await {
+                Ok(Ok(document)) => break document,
+                Ok(Err(e)) => {
+                    show_error("Error: Could not decode file", format!("{:?}", e));
+                    return None;
+                }
+                Err(_) => decoded_bytes.set(counter.load(Ordering::Relaxed)),
+            }
+        };
+        if let Some(session) = document.session.take() {
+            let first = match session.sides.first() {
+                Some(side) if side.handshake.is_some() => Some(Selection::Handshake(0)),
+                _ if !session.messages.is_empty() => Some(Selection::Message(0)),
+                _ => None,
+            };
+            obj_context.session.set(Some(Arc::new(session)));
+            if let Some(first) = first {
+                select(obj_context, first);
+            }
+            return Some(document);
+        }
+        // An empty file has nothing to select
+        let first = document.roots.first().copied();
+        obj_context
+            .objects
+            .set(std::mem::take(&mut document.objects));
+        obj_context.roots.set(std::mem::take(&mut document.roots));
+        if let Some(first) = first {
+            obj_context.selected_index.set(first);
+            obj_context.has_selected.set(true);
+        }
+        Some(document)
+    });
+
+    let file = CURRENT_FILE.read();
+    let buffer = &file.buffer;
+    let document = document.read();
+    let document = match document.as_ref() {
+        Some(Some(document)) => document,
+        Some(None) => return rsx! {},
+        None => {
+            let percent = decoded_bytes() * 100 / buffer.len().max(1);
+            return rsx! {
+                div {
+                    class: "hex-pane text-ctp-text",
+                    p { "Decoding... {percent}%" }
+                    progress {
+                        class: "decode-progress",
+                        max: 100,
+                        value: percent,
+                    }
+                }
+            };
+        }
+    };
+    let selected = *obj_context.selected_index.read();
+    let session = obj_context.session.cloned();
+    let selection = *obj_context.selection.read();
+    let message_spans = obj_context.message_spans.read();
+    // Captures show the picked message's payload, or a handshake's bytes, not the whole file
+    let (shown, spans): (&[u8], &[SyntaxSpan]) = match (session.as_ref(), selection) {
+        (Some(session), Some(Selection::Message(index))) => (
+            &session.messages[index].message.payload[..],
+            &message_spans[..],
+        ),
+        (Some(session), Some(Selection::Handshake(side))) => {
+            let bytes: &[u8] = match (side, &file.peer) {
+                (0, _) => &buffer[..],
+                (_, Some(peer)) => &peer[..],
+                (_, None) => &[],
+            };
+            let end = session.sides[side]
+                .handshake
+                .as_ref()
+                .map_or(0, |handshake| handshake.end());
+            (&bytes[..end.min(bytes.len())], &document.spans[..0])
+        }
+        _ => (&buffer[..], &document.spans[..]),
+    };
+    let window = hex_window(shown.len(), scroll_top(), viewport_height());
+
+    rsx! {
+        div {
+            class: "hex-pane",
+
+            {document.limit_message.as_ref().map(|message| rsx! {
+                p {
+                    class: "text-ctp-red",
+                    "{message}"
+                }
+            })}
+
+            div {
+                class: "hex hex-row text-ctp-subtext0",
+                span { class: "hex-offset" }
+                for column in 0..ROW_BYTES {
+                    span { class: "hex-byte", "{column:02X}" }
+                }
+            }
+            // Only the rows in view are built, the spacer gives the scrollbar the full height
+            div {
+                class: "hex-scroll",
+                onmounted: move |event| async move {
+                    let data = event.data();
+                    if let Ok(rect) = data.get_client_rect().await {
+                        viewport_height.set(rect.height());
+                    }
+                    scroller.set(Some(data));
+                },
+                onscroll: move |_| async move {
+                    let Some(data) = scroller() else { return };
+                    if let Ok(offset) = data.get_scroll_offset().await {
+                        scroll_top.set(offset.y);
+                    }
+                    if let Ok(rect) = data.get_client_rect().await {
+                        viewport_height.set(rect.height());
+                    }
+                },
+                div {
+                    class: "hex-spacer",
+                    style: format!("height: {}px;", window.total_height),
+                    div {
+                        class: "hex-rows",
+                        style: format!("top: {}px;", window.offset),
+                        for row in window.rows.clone() {
+                            div {
+                                key: "{row}",
+                                class: "hex hex-row",
+                                span {
+                                    class: "text-ctp-subtext0 hex-offset",
+                                    {format!("{:08X}", row * ROW_BYTES)}
+                                }
+                                for cell in hex_row(shown, spans, row) {
+                                    span {
+                                        class: cell_class(&cell, selected),
+                                        onclick: {
+                                            let object_id = cell.span.map(|span| span.object_id);
+                                            move |_| {
+                                                if let Some(object_id) = object_id {
+                                                    tracing::debug!("Selected: {:?}", object_id);
+                                                    obj_context.selected_index.set(object_id);
+                                                }
+                                            }
+                                        },
+                                        {format!("{:02X}", cell.value)}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn object_inspector(obj: Option<ObjectInfo<'static>>) -> Element {
+    let obj = match obj {
+        Some(obj) => obj,
+        None => {
+            return rsx! {
+                "No object selected"
+            }
+        }
+    };
+
+    rsx! {
+        div {
+            class: "flex flex-col",
+            TypeInspectorValue {name: "Object ID", value: obj.object_id}
+            TypeInspectorValue {name: "Object Type", value: obj.object_type.clone()}
+            TypeInspectorProperties {properties: obj.object_properties}
+            type_inspector_contents {obj: obj.object_type, path: vec![obj.object_id]}
+        }
+    }
+}
+
+#[component]
+fn type_inspector_contents(
+    obj: ObjectType<'static>,
+    name: Option<String>,
+    path: Option<Vec<isize>>,
+) -> Element {
+    let name = name.unwrap_or_else(|| String::from("Value"));
+    let path = path.unwrap_or_default();
+    match obj {
+        ObjectType::Amf0Number(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf0Bool(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf0String(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf0Date(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf0LongString(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf0XML(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf3Integer(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf3Double(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf3String(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf3Date(value) => rsx! {TypeInspectorValue {name, value}},
+        ObjectType::Amf3XML(value) | ObjectType::Amf3XMLDocument(value) => {
+            rsx! {TypeInspectorValue {name, value}}
+        }
+        ObjectType::Amf3ByteArray(value) => {
+            rsx! {TypeInspectorValue {name, value: format!("{:02X?}", value) }}
+        }
+        ObjectType::Amf3VectorInt(value) => {
+            rsx! {TypeInspectorValue {name, value: format!("{:?}", value) }}
+        }
+        ObjectType::Amf3VectorUInt(value) => {
+            rsx! {TypeInspectorValue {name, value: format!("{:?}", value) }}
+        }
+        ObjectType::Amf3VectorDouble(value) => {
+            rsx! {TypeInspectorValue {name, value: format!("{:?}", value) }}
+        }
+        ObjectType::Amf0Object(value)
+        | ObjectType::Amf0EcmaArray(value)
+        | ObjectType::Amf0TypedObject(value)
+        | ObjectType::Amf3Object(value) => {
+            rsx! {ObjectInspector {obj: value, path}}
+        }
+        ObjectType::Amf3Array(associative, dense) => {
+            let mut value = associative;
+            value.extend(
+                dense
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, id)| (i.to_string(), id)),
+            );
+            rsx! {ObjectInspector {obj: value, path}}
+        }
+        ObjectType::Amf0StrictArray(value) | ObjectType::Amf3VectorObject(value) => {
+            let value = value
+                .into_iter()
+                .enumerate()
+                .map(|(i, id)| (i.to_string(), id))
+                .collect();
+            rsx! {ObjectInspector {obj: value, path}}
+        }
+        ObjectType::Amf3Dictionary(value) => {
+            let value = value
+                .into_iter()
+                .flat_map(|(key, value)| {
+                    [(String::from("Key"), key), (String::from("Value"), value)]
+                })
+                .collect();
+            rsx! {ObjectInspector {obj: value, path}}
+        }
+        ObjectType::Amf0Switch(value) => {
+            rsx! {ObjectInspector {obj: vec![(name, value)], path}}
+        }
+        ObjectType::Amf3Undefined | ObjectType::Amf0Undefined => rsx! {
+            TypeInspectorValue {name, value: "Undefined"}
+        },
+
+        _ => rsx! {},
+    }
+}
+
+#[component]
+fn ObjectInspector(obj: Vec<(String, isize)>, path: Vec<isize>) -> Element {
+    let obj_context = use_context::<ObjectContext>();
+    let handle = obj_context.objects.read();
+    rsx! {
+        h1 {
+            class: "text-ctp-text font-bold",
+            "Children"
+        }
+        for (key, id) in obj.iter() {
+            // References can point back up the tree, don't follow them forever
+            if path.contains(id) {
+                TypeInspectorValue {name: key.clone(), value: "Circular reference"}
+            } else {
+                type_inspector_contents{obj: {
+                    let x = handle.get(id).unwrap().clone().object_type;
+                    tracing::debug!("Key: {} | Value: {:?}", key, x);
+                    x
+                }, name: key.clone(), path: {
+                    let mut path = path.clone();
+                    path.push(*id);
+                    path
+                }}
+            }
+        }
+    }
+}
+
+#[component]
+fn TypeInspectorProperties(properties: TypeProperties) -> Element {
+    match properties {
+        TypeProperties::Amf3StringProperties(prop) => {
+            if prop.is_reference {
+                rsx! {
+                    TypeInspectorValue {name: "Is Reference?", value: prop.is_reference}
+                    TypeInspectorValue {name: "Identifier", value: prop.identifier}
+                }
+            } else {
+                rsx! {
+                    TypeInspectorValue {name: "Is Reference?", value: prop.is_reference}
+                    TypeInspectorValue {name: "String Length", value: prop.identifier}
+                }
+            }
+        }
+        TypeProperties::Amf0TypedObjectProperties(object_type) => {
+            rsx! {
+                TypeInspectorValue {name: "Object Name", value: object_type}
+            }
+        }
+        TypeProperties::Amf0EcmaArrayProperties(count) => {
+            rsx! {
+                TypeInspectorValue {name: "Declared Count", value: count}
+            }
+        }
+        TypeProperties::Amf3ObjectProperties(prop) => {
+            rsx! {
+                TypeInspectorValue {name: "Object Name", value: prop.object_type}
+                TypeInspectorValue {name: "Is Reference?", value: prop.is_reference}
+                TypeInspectorValue {name: "Property Count", value: prop.property_count}
+                TypeInspectorValue {name: "Encoding", value: prop.encoding}
+                TypeInspectorValue {name: "Externalisable", value: prop.externalisable}
+                TypeInspectorValue {name: "Dynamic", value: prop.dynamic}
+            }
+        }
+        TypeProperties::Amf3VectorProperties(prop) => {
+            rsx! {
+                TypeInspectorValue {name: "Fixed Length", value: prop.is_fixed}
+                if !prop.object_type.is_empty() {
+                    TypeInspectorValue {name: "Object Name", value: prop.object_type}
+                }
+            }
+        }
+        TypeProperties::Amf3DictionaryProperties(prop) => {
+            rsx! {
+                TypeInspectorValue {name: "Weak Keys", value: prop.weak_keys}
+            }
+        }
+        _ => rsx! {},
+    }
+}
+
+#[component]
+fn TypeInspectorValue(name: String, value: String) -> Element {
+    rsx! {
+         span {
+            class: "flex flex-row",
+            p {
+                class: "text-ctp-text font-medium",
+                "{name}: "
+            }
+            p {
+                class: "text-ctp-text pl-2",
+                "{value}"
+            }
+        }
+    }
+}
+
+// Where a message came from and its header, then what's in it
+fn message_header(session: &Session, index: usize) -> Vec<(String, String)> {
+    let entry = &session.messages[index];
+    let message = &entry.message;
+    let mut fields = vec![
+        (String::from("Message"), format!("#{}", index)),
+        (String::from("Direction"), entry.direction.to_string()),
+        (String::from("Offset"), format!("{:#X}", entry.offset)),
+        (String::from("Timestamp"), message.timestamp.to_string()),
+        (String::from("Chunk Stream ID"), message.csid.to_string()),
+        (
+            String::from("Message Stream ID"),
+            message.stream_id.to_string(),
+        ),
+        (
+            String::from("Type"),
+            format!("{} {}", message.type_id, type_name(message.type_id)),
+        ),
+        (String::from("Length"), message.payload.len().to_string()),
+    ];
+    if let Some(parent) = entry.parent {
+        fields.push((String::from("In Aggregate"), format!("#{}", parent)));
+    }
+    fields.extend(session.fields(index));
+    fields
+}
+
+#[component]
+fn RightBar() -> Element {
+    let cont = use_context::<ObjectContext>();
+    let current_index = cont.selected_index.read();
+    // A missing ID shows nothing rather than taking the window down
+    let obj = match *cont.has_selected.read() {
+        true => cont.objects.read().get(&current_index).cloned(),
+        false => None,
+    };
+    let session = cont.session.cloned();
+    let selection = *cont.selection.read();
+    let (message_fields, linked) = match (session.as_ref(), selection) {
+        (Some(session), Some(Selection::Message(index))) => {
+            // The other half of its transaction, if it has one
+            let linked = session.transaction_of(index).and_then(|transaction| {
+                match transaction.request == index {
+                    true => transaction.response,
+                    false => Some(transaction.request),
+                }
+            });
+            (message_header(session, index), linked)
+        }
+        (Some(session), Some(Selection::Handshake(side))) => (session.handshake_fields(side), None),
+        _ => (Vec::new(), None),
+    };
+    rsx! {
+        div {
+            class: "bg-ctp-crust min-w-1/5 grow-[3] max-w-[25%] m-3 outline outline-2 outline-ctp-pink p-2 rounded-md overflow-auto text-ctp-text",
+            if !message_fields.is_empty() {
+                h1 {
+                    class: "text-ctp-text font-bold",
+                    "Message Inspector"
+                }
+                div {
+                    class: "flex flex-col mb-2",
+                    for (name, value) in message_fields {
+                        TypeInspectorValue {name, value}
+                    }
+                }
+            }
+            {linked.map(|index| rsx! {
+                button {
+                    class: "bg-ctp-surface0 outline outline-2 outline-ctp-pink text-ctp-text hover:outline-ctp-blue hover:bg-ctp-lavender hover:text-ctp-crust py-1 px-2 mb-2 rounded",
+                    onclick: move |_| select(cont, Selection::Message(index)),
+                    {format!("Go to #{}", index)}
+                }
+            })}
+            h1 {
+                class: "text-ctp-text font-bold",
+                "Object Inspector"
+            }
+            object_inspector {obj}
+        }
+    }
+}
+
+// /// Echo component that demonstrates fullstack server functions.
+// #[component]
+// fn Echo() -> Element {
+//     let mut response = use_signal(|| String::new());
+//
+//     rsx! {
+//         div {
+//             id: "echo",
+//             h4 { "ServerFn Echo" }
+//             input {
+//                 placeholder: "Type here to echo...",
+//                 oninput:  move |event| async move {
+//                     let data = echo_server(event.value()).await.unwrap();
+//                     response.set(data);
+//                 },
+//             }
+//
+//             if !response().is_empty() {
+//                 p {
+//                     "Server echoed: "
+//                     i { "{response}" }
+//                 }
+//             }
+//         }
+//     }
+// }
+//
+// /// Echo the user input on the server.
+// #[server(EchoServer)]
+// async fn echo_server(input: String) -> Result<String, ServerFnError> {
+//     Ok(input)
+// }
@@ -0,0 +1,139 @@
+use crate::amf::syntax_byte::SyntaxSpan;
+use std::ops::Range;
+
+pub const ROW_BYTES: usize = 16;
+pub const ROW_HEIGHT: f64 = 20.0; // Pixels, the rows are laid out at exactly this height
+const OVERSCAN: usize = 8; // Rows kept either side of the viewport so fast scrolls don't flash
+
+// Webviews stop laying out elements somewhere past 2^24 pixels, past this the scrollbar is
+// scaled instead of being one row per ROW_HEIGHT
+const MAX_HEIGHT: f64 = 8_000_000.0;
+
+/// Which rows of the hex pane to build for a scroll position, and where to put them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexWindow {
+    pub rows: Range<usize>,
+    pub offset: f64, // Top of the first built row, from the top of the scroll area
+    pub total_height: f64, // Height of the scroll area
+}
+
+pub fn row_count(len: usize) -> usize {
+    len.div_ceil(ROW_BYTES)
+}
+
+pub fn hex_window(len: usize, scroll_top: f64, viewport_height: f64) -> HexWindow {
+    let total_rows = row_count(len);
+    let natural = total_rows as f64 * ROW_HEIGHT;
+    let total_height = natural.min(MAX_HEIGHT);
+    let fits = (viewport_height / ROW_HEIGHT).ceil().max(1.0) as usize;
+    let scroll_top = scroll_top.clamp(0.0, (total_height - viewport_height).max(0.0));
+
+    // The row at the top of the viewport, fractional when we scroll a pixel at a time
+    let top = if natural <= MAX_HEIGHT {
+        scroll_top / ROW_HEIGHT
+    } else {
+        let fraction = scroll_top / (total_height - viewport_height).max(1.0);
+        (fraction * total_rows.saturating_sub(fits) as f64).round()
+    };
+    let first = (top as usize).saturating_sub(OVERSCAN).min(total_rows);
+    let last = (top as usize + fits + OVERSCAN).min(total_rows);
+    HexWindow {
+        rows: first..last,
+        offset: (scroll_top - (top - first as f64) * ROW_HEIGHT).max(0.0),
+        total_height,
+    }
+}
+
+/// The spans touching a range of the input, found without walking the ones before it.
+pub fn spans_in(spans: &[SyntaxSpan], range: Range<usize>) -> &[SyntaxSpan] {
+    let start = spans.partition_point(|span| span.end <= range.start);
+    let end = spans.partition_point(|span| span.start < range.end);
+    &spans[start..end.max(start)]
+}
+
+/// One byte of a hex row, span is None for bytes nothing was decoded from (e.g. a command's
+/// leading byte).
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexCell<'a> {
+    pub offset: usize,
+    pub value: u8,
+    pub span: Option<&'a SyntaxSpan>,
+}
+
+pub fn hex_row<'a>(buffer: &[u8], spans: &'a [SyntaxSpan], row: usize) -> Vec<HexCell<'a>> {
+    let start = row * ROW_BYTES;
+    let end = (start + ROW_BYTES).min(buffer.len());
+    let mut spans = spans_in(spans, start..end).iter().peekable();
+    (start..end)
+        .map(|offset| {
+            while spans.peek().is_some_and(|span| span.end <= offset) {
+                spans.next();
+            }
+            let span = spans.peek().copied().filter(|span| span.start <= offset);
+            HexCell {
+                offset,
+                value: buffer[offset],
+                span,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize, object_id: isize) -> SyntaxSpan {
+        SyntaxSpan {
+            start,
+            end,
+            object_id,
+            color: String::new(),
+        }
+    }
+
+    #[test]
+    fn small_files_scroll_a_row_at_a_time() {
+        let window = hex_window(1000, 0.0, 200.0);
+        assert_eq!(window.rows, 0..18);
+        assert_eq!(window.offset, 0.0);
+        assert_eq!(window.total_height, 63.0 * ROW_HEIGHT);
+
+        let window = hex_window(1000, 30.0 * ROW_HEIGHT + 5.0, 200.0);
+        assert_eq!(window.rows, 22..48);
+        assert_eq!(window.offset, 22.0 * ROW_HEIGHT);
+    }
+
+    #[test]
+    fn huge_files_scale_the_scrollbar() {
+        let len = 100 << 20;
+        let start = hex_window(len, 0.0, 800.0);
+        assert_eq!(start.total_height, MAX_HEIGHT);
+        assert_eq!(start.rows.start, 0);
+
+        // The very bottom has to reach the last row
+        let end = hex_window(len, MAX_HEIGHT - 800.0, 800.0);
+        assert_eq!(end.rows.end, row_count(len));
+        assert!(end.offset + (end.rows.len() as f64) * ROW_HEIGHT >= MAX_HEIGHT - 1.0);
+        assert!(end.rows.len() <= 40 + 2 * OVERSCAN);
+    }
+
+    #[test]
+    fn cells_follow_spans() {
+        // Byte 0 isn't covered, like a command's leading byte
+        let spans = vec![span(1, 3, 0), span(3, 20, 1), span(20, 40, 2)];
+        let buffer: Vec<u8> = (0..40).collect();
+        assert_eq!(spans_in(&spans, 16..32), &spans[1..3]);
+        assert_eq!(spans_in(&spans, 40..48), &[] as &[SyntaxSpan]);
+
+        let row = hex_row(&buffer, &spans, 0);
+        assert_eq!(row.len(), ROW_BYTES);
+        assert_eq!(row[0].span, None);
+        assert_eq!(row[2].span.map(|s| s.object_id), Some(0));
+        assert_eq!(row[3].span.map(|s| s.object_id), Some(1));
+
+        let row = hex_row(&buffer, &spans, 2);
+        assert_eq!(row.len(), 8);
+        assert!(row.iter().all(|cell| cell.span.unwrap().object_id == 2));
+    }
+}
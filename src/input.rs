@@ -0,0 +1,84 @@
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+// Smaller files are just read, mapping them saves nothing and costs a syscall or two
+const MAP_THRESHOLD: u64 = 4 << 20;
+
+/// The bytes of an opened file, either read into memory or mapped read-only for big captures.
+/// Derefs to the bytes either way, so the reader and hex view don't care which.
+pub enum Input {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Input {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Input> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < MAP_THRESHOLD {
+            let mut bytes = Vec::with_capacity(len as usize);
+            file.read_to_end(&mut bytes)?;
+            return Ok(Input::Owned(bytes));
+        }
+        // Safety: the map is read-only, if something else truncates the file while we have it
+        // open we get a SIGBUS, same as any other viewer that maps its input
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Input::Mapped(map))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Input::Mapped(_))
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::Owned(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Input {
+    fn from(bytes: Vec<u8>) -> Self {
+        Input::Owned(bytes)
+    }
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Owned(bytes) => bytes,
+            Input::Mapped(map) => map,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn small_files_are_read_big_files_mapped() {
+        let dir = std::env::temp_dir();
+        let small = dir.join(format!("amf-viewer-small-{}", std::process::id()));
+        let big = dir.join(format!("amf-viewer-big-{}", std::process::id()));
+        fs::write(&small, [0x05]).unwrap();
+        let contents: Vec<u8> = (0..MAP_THRESHOLD).map(|i| i as u8).collect();
+        fs::write(&big, &contents).unwrap();
+
+        let input = Input::open(&small).unwrap();
+        assert!(!input.is_mapped());
+        assert_eq!(&input[..], &[0x05]);
+        let input = Input::open(&big).unwrap();
+        assert!(input.is_mapped());
+        assert_eq!(&input[..], &contents[..]);
+
+        fs::remove_file(small).unwrap();
+        fs::remove_file(big).unwrap();
+    }
+}
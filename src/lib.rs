@@ -0,0 +1,5 @@
+pub mod amf;
+pub mod dump;
+pub mod hex_view;
+pub mod input;
+pub mod rtmp;
@@ -0,0 +1,196 @@
+use crate::rtmp::chunk_stream::RtmpMessage;
+use crate::rtmp::message::type_name;
+use std::fmt;
+use std::ops::Range;
+
+const TAG_HEADER_SIZE: usize = 11;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AggregateError {
+    NotAggregate(u8),
+    Truncated(usize), // Offset in the payload of the sub-message that ran past the end
+}
+
+impl fmt::Display for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateError::NotAggregate(type_id) => {
+                write!(f, "Type {} is not an aggregate message", type_id)
+            }
+            AggregateError::Truncated(offset) => {
+                write!(
+                    f,
+                    "Aggregate sub-message at {:#X} runs past the end",
+                    offset
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AggregateError {}
+
+/// One FLV-style tag out of an aggregate message, as a message of its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubMessage {
+    pub message: RtmpMessage,
+    pub span: Range<usize>, // Tag header, data and back pointer in the aggregate's payload
+    pub back_pointer: u32,
+}
+
+impl SubMessage {
+    /// What the back pointer should say, the size of the tag before it.
+    pub fn expected_back_pointer(&self) -> u32 {
+        (TAG_HEADER_SIZE + self.message.payload.len()) as u32
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.back_pointer == self.expected_back_pointer()
+    }
+}
+
+// Where part of the parent's payload came from in the input, it can cross chunks
+fn input_pieces(parent: &RtmpMessage, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut skipped = 0;
+    for piece in &parent.pieces {
+        let start = range.start.max(skipped);
+        let end = range.end.min(skipped + piece.len());
+        if start < end {
+            pieces.push(piece.start + start - skipped..piece.start + end - skipped);
+        }
+        skipped += piece.len();
+    }
+    pieces
+}
+
+fn u24(b: &[u8]) -> u32 {
+    u32::from_be_bytes([0, b[0], b[1], b[2]])
+}
+
+/// Splits an aggregate message into its sub-messages. Their timestamps are moved so the
+/// first lands on the aggregate's own timestamp, the way FFmpeg plays them back.
+pub fn split_aggregate(message: &RtmpMessage) -> Result<Vec<SubMessage>, AggregateError> {
+    if message.type_id != 22 {
+        return Err(AggregateError::NotAggregate(message.type_id));
+    }
+    let payload = &message.payload;
+    let mut subs = Vec::new();
+    let mut offset = 0;
+    let mut first_timestamp = None;
+    while offset < payload.len() {
+        let header = payload
+            .get(offset..offset + TAG_HEADER_SIZE)
+            .ok_or(AggregateError::Truncated(offset))?;
+        let size = u24(&header[1..4]) as usize;
+        // The fourth timestamp byte is the top 8 bits
+        let timestamp = u24(&header[4..7]) | (header[7] as u32) << 24;
+        let data = offset + TAG_HEADER_SIZE..offset + TAG_HEADER_SIZE + size;
+        let end = data.end + 4;
+        let back_pointer = payload
+            .get(data.end..end)
+            .ok_or(AggregateError::Truncated(offset))?;
+        let first = *first_timestamp.get_or_insert(timestamp);
+        subs.push(SubMessage {
+            message: RtmpMessage {
+                csid: message.csid,
+                timestamp: message
+                    .timestamp
+                    .wrapping_add(timestamp.wrapping_sub(first)),
+                type_id: header[0],
+                // The tag's own stream ID is always 0, the aggregate's is the one that counts
+                stream_id: message.stream_id,
+                payload: payload[data.clone()].to_vec(),
+                headers: Vec::new(),
+                pieces: input_pieces(message, data),
+            },
+            span: offset..end,
+            back_pointer: u32::from_be_bytes([
+                back_pointer[0],
+                back_pointer[1],
+                back_pointer[2],
+                back_pointer[3],
+            ]),
+        });
+        offset = end;
+    }
+    Ok(subs)
+}
+
+/// A line per sub-message for the aggregate's own fields, with any back pointer that's off.
+pub fn aggregate_fields(subs: &[SubMessage]) -> Vec<(String, String)> {
+    let mut fields = vec![(String::from("Sub-messages"), subs.len().to_string())];
+    for (i, sub) in subs.iter().enumerate() {
+        let back_pointer = if sub.is_valid() {
+            String::from("ok")
+        } else {
+            format!("WRONG, should be {}", sub.expected_back_pointer())
+        };
+        fields.push((
+            format!("Sub-message {}", i + 1),
+            format!(
+                "type {} {} time {} ({} bytes), back pointer {} {}",
+                sub.message.type_id,
+                type_name(sub.message.type_id),
+                sub.message.timestamp,
+                sub.message.payload.len(),
+                sub.back_pointer,
+                back_pointer
+            ),
+        ));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(type_id: u8, timestamp: u32, data: &[u8], back_pointer: u32) -> Vec<u8> {
+        let mut out = vec![type_id];
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(&timestamp.to_be_bytes()[1..]);
+        out.push((timestamp >> 24) as u8);
+        out.extend_from_slice(&[0, 0, 0]);
+        out.extend_from_slice(data);
+        out.extend_from_slice(&back_pointer.to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn sub_messages() {
+        let mut payload = tag(9, 1000, &[0x17, 0x01, 0, 0, 0], 16);
+        payload.extend(tag(8, 1023, &[0xAF, 0x01, 0x21], 99));
+        // Split over two chunks, the second piece starting at 200 in the input
+        let message = RtmpMessage {
+            csid: 6,
+            timestamp: 40,
+            type_id: 22,
+            stream_id: 1,
+            pieces: vec![100..120, 200..200 + payload.len() - 20],
+            payload,
+            headers: Vec::new(),
+        };
+        let subs = split_aggregate(&message).unwrap();
+        assert_eq!(subs.len(), 2);
+        assert_eq!(subs[0].message.timestamp, 40);
+        assert_eq!(subs[1].message.timestamp, 63);
+        assert_eq!(subs[1].message.stream_id, 1);
+        assert!(subs[0].is_valid());
+        assert!(!subs[1].is_valid());
+        assert_eq!(subs[1].message.pieces.first(), Some(&(211..214)));
+        assert_eq!(
+            aggregate_fields(&subs)[2].1,
+            "type 8 Audio time 63 (3 bytes), back pointer 99 WRONG, should be 14"
+        );
+
+        let truncated = RtmpMessage {
+            payload: message.payload[..30].to_vec(),
+            ..message
+        };
+        assert_eq!(
+            split_aggregate(&truncated),
+            Err(AggregateError::Truncated(20))
+        );
+    }
+}
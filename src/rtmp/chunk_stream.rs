@@ -0,0 +1,467 @@
+use crate::amf::amf_highlight::AMFReader;
+use crate::rtmp::control::{parse_control, ControlMessage};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 128;
+const EXTENDED_TIMESTAMP: u32 = 0xFF_FFFF; // Timestamp field value that means 4 more bytes follow
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChunkError {
+    Truncated(usize), // Offset of the chunk or message that ran past the end
+    UnknownChunkStream(u32, usize), // A chunk that relies on a header we never saw
+    BadChunkSize(u32),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::Truncated(offset) => write!(f, "Truncated chunk at offset {}", offset),
+            ChunkError::UnknownChunkStream(csid, offset) => write!(
+                f,
+                "Chunk stream {} continued at offset {} without a full header",
+                csid, offset
+            ),
+            ChunkError::BadChunkSize(size) => write!(f, "Invalid chunk size {}", size),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// One chunk's header, as it was sent and with the values it inherited filled in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkHeader {
+    pub format: u8,
+    pub csid: u32,
+    pub timestamp: u32, // Absolute, deltas are already added on
+    pub length: u32,
+    pub type_id: u8,
+    pub stream_id: u32,
+    pub extended_timestamp: bool,
+    pub span: Range<usize>, // Bytes of the input the header was read from
+}
+
+/// A whole RTMP message put back together from its chunks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtmpMessage {
+    pub csid: u32,
+    pub timestamp: u32,
+    pub type_id: u8,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+    pub headers: Vec<ChunkHeader>, // One per chunk, the first is where the message starts
+    pub pieces: Vec<Range<usize>>, // Where each part of the payload was in the input
+}
+
+impl RtmpMessage {
+    /// Offset in the input of the payload byte at `index`, for pointing back at the capture.
+    pub fn input_offset(&self, index: usize) -> Option<usize> {
+        let mut skipped = 0;
+        for piece in &self.pieces {
+            if index < skipped + piece.len() {
+                return Some(piece.start + index - skipped);
+            }
+            skipped += piece.len();
+        }
+        None
+    }
+
+    /// Command and data messages carry AMF, AMF3 commands start with a format byte the
+    /// reader has to skip.
+    pub fn amf_reader(&self) -> Option<AMFReader<'_>> {
+        match self.type_id {
+            20 | 18 => Some(AMFReader::new(&self.payload, false)),
+            17 | 15 => Some(AMFReader::new(&self.payload, true)),
+            _ => None,
+        }
+    }
+}
+
+// What a chunk stream remembers for the next chunk that leaves fields out
+#[derive(Default)]
+struct ChunkStreamState {
+    timestamp: u32,
+    delta: u32, // Added on by format 3 chunks that start a new message
+    length: u32,
+    type_id: u8,
+    stream_id: u32,
+    extended_timestamp: bool,
+    seen: bool,
+    partial: Option<RtmpMessage>,
+}
+
+/// Splits an RTMP chunk stream back into messages. Chunk streams interleave, so each one keeps
+/// its own header state and half finished message until the rest of it turns up.
+pub struct ChunkDemuxer<'a> {
+    bytes: &'a [u8],
+    read_head: usize,
+    chunk_size: usize,
+    streams: HashMap<u32, ChunkStreamState>,
+    failed: bool, // The iterator stops after its first error
+}
+
+impl<'a> ChunkDemuxer<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        ChunkDemuxer {
+            bytes,
+            read_head: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            streams: HashMap::new(),
+            failed: false,
+        }
+    }
+
+    /// Starts reading chunks from `offset`, e.g. after the handshake.
+    pub fn starting_at(mut self, offset: usize) -> Self {
+        self.read_head = offset;
+        self
+    }
+
+    pub fn read_head(&self) -> usize {
+        self.read_head
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Changes the size chunks are read with. Set Chunk Size messages in the stream do this
+    /// themselves, this is for a size agreed some other way. Only the top bit is reserved, but
+    /// anything under 1 can't make progress.
+    pub fn set_chunk_size(&mut self, size: u32) -> Result<(), ChunkError> {
+        if size == 0 || size > 0x7FFF_FFFF {
+            return Err(ChunkError::BadChunkSize(size));
+        }
+        self.chunk_size = size as usize;
+        Ok(())
+    }
+
+    fn read(&self, offset: usize, len: usize) -> Result<&'a [u8], ChunkError> {
+        self.bytes
+            .get(offset..offset + len)
+            .ok_or(ChunkError::Truncated(offset))
+    }
+
+    fn read_u24(&self, offset: usize) -> Result<u32, ChunkError> {
+        let b = self.read(offset, 3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]))
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32, ChunkError> {
+        let b = self.read(offset, 4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Reads one chunk, returning the message if this was its last chunk
+    fn read_chunk(&mut self) -> Result<Option<RtmpMessage>, ChunkError> {
+        let start = self.read_head;
+        let first = self.read(start, 1)?[0];
+        let format = first >> 6;
+        // csid 0 and 1 mean the id carries on into one or two more bytes
+        let (csid, mut offset) = match first & 0x3F {
+            0 => (self.read(start + 1, 1)?[0] as u32 + 64, start + 2),
+            1 => {
+                let b = self.read(start + 1, 2)?;
+                (b[1] as u32 * 256 + b[0] as u32 + 64, start + 3)
+            }
+            csid => (csid as u32, start + 1),
+        };
+
+        let mut state = self.streams.remove(&csid).unwrap_or_default();
+        if format != 0 && !state.seen {
+            return Err(ChunkError::UnknownChunkStream(csid, start));
+        }
+        // Put the state back whatever happens below, a truncated chunk can be read again
+        let result = self.read_chunk_body(&mut state, format, csid, start, &mut offset);
+        self.streams.insert(csid, state);
+        result
+    }
+
+    fn read_chunk_body(
+        &mut self,
+        state: &mut ChunkStreamState,
+        format: u8,
+        csid: u32,
+        start: usize,
+        offset: &mut usize,
+    ) -> Result<Option<RtmpMessage>, ChunkError> {
+        let mut timestamp_field = None;
+        let (mut length, mut type_id, mut stream_id) =
+            (state.length, state.type_id, state.stream_id);
+        if format <= 2 {
+            timestamp_field = Some(self.read_u24(*offset)?);
+            *offset += 3;
+        }
+        if format <= 1 {
+            length = self.read_u24(*offset)?;
+            type_id = self.read(*offset + 3, 1)?[0];
+            *offset += 4;
+        }
+        if format == 0 {
+            // The only little endian field in RTMP
+            let b = self.read(*offset, 4)?;
+            stream_id = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            *offset += 4;
+        }
+
+        // Format 3 repeats the extended timestamp when the header it follows had one
+        let extended = match timestamp_field {
+            Some(field) => field == EXTENDED_TIMESTAMP,
+            None => state.extended_timestamp,
+        };
+        let mut extended_value = None;
+        if extended {
+            extended_value = Some(self.read_u32(*offset)?);
+            *offset += 4;
+        }
+        let field = extended_value.or(timestamp_field);
+
+        // Format 1 or 2 in the middle of a message means the rest of it was abandoned
+        let continuing = format == 3 && state.partial.is_some();
+        let timestamp = match (format, field) {
+            _ if continuing => state.timestamp,
+            (0, Some(absolute)) => absolute,
+            (_, Some(delta)) => state.timestamp.wrapping_add(delta),
+            // Like FFmpeg, a format 3 chunk that starts a message adds the last delta again,
+            // after a format 0 header that is its timestamp
+            _ => state.timestamp.wrapping_add(state.delta),
+        };
+
+        let header = ChunkHeader {
+            format,
+            csid,
+            timestamp,
+            length,
+            type_id,
+            stream_id,
+            extended_timestamp: extended,
+            span: start..*offset,
+        };
+
+        let received = match &state.partial {
+            Some(message) if continuing => message.payload.len(),
+            _ => 0,
+        };
+        let take = (length as usize - received).min(self.chunk_size);
+        let body = self.read(*offset, take)?;
+
+        // Everything in the chunk is there, so now it is safe to update the state
+        let mut message = match state.partial.take() {
+            Some(message) if continuing => message,
+            _ => RtmpMessage {
+                csid,
+                timestamp,
+                type_id,
+                stream_id,
+                payload: Vec::with_capacity(length.min(1 << 20) as usize),
+                headers: Vec::new(),
+                pieces: Vec::new(),
+            },
+        };
+        if !continuing {
+            if format != 3 {
+                state.delta = field.unwrap_or(state.delta);
+            }
+            state.timestamp = timestamp;
+        }
+        state.length = length;
+        state.type_id = type_id;
+        state.stream_id = stream_id;
+        state.extended_timestamp = extended;
+        state.seen = true;
+
+        message.payload.extend_from_slice(body);
+        message.pieces.push(*offset..*offset + take);
+        message.headers.push(header);
+        *offset += take;
+        self.read_head = *offset;
+
+        if message.payload.len() == length as usize {
+            return Ok(Some(message));
+        }
+        state.partial = Some(message);
+        Ok(None)
+    }
+
+    // Set Chunk Size and Abort change how the chunks after them are read
+    fn apply_control(&mut self, message: &RtmpMessage) {
+        if !matches!(message.type_id, 1 | 2) {
+            return;
+        }
+        match parse_control(message.type_id, &message.payload) {
+            // A size we can't use is left for the inspector to show, the old one stays
+            Ok(ControlMessage::SetChunkSize(size)) => {
+                let _ = self.set_chunk_size(size);
+            }
+            Ok(ControlMessage::Abort(csid)) => {
+                if let Some(state) = self.streams.get_mut(&csid) {
+                    state.partial = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads chunks until a message is complete. None means the input ended cleanly between
+    /// messages, a chunk or message cut off by the end of the input is a Truncated error.
+    pub fn next_message(&mut self) -> Result<Option<RtmpMessage>, ChunkError> {
+        while self.read_head < self.bytes.len() {
+            if let Some(message) = self.read_chunk()? {
+                self.apply_control(&message);
+                return Ok(Some(message));
+            }
+        }
+        // Whatever is still waiting for chunks will never get them
+        let unfinished = self
+            .streams
+            .values()
+            .filter_map(|state| state.partial.as_ref())
+            .map(|message| message.headers[0].span.start)
+            .min();
+        match unfinished {
+            Some(offset) => {
+                self.streams
+                    .values_mut()
+                    .for_each(|state| state.partial = None);
+                Err(ChunkError::Truncated(offset))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Iterator for ChunkDemuxer<'_> {
+    type Item = Result<RtmpMessage, ChunkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let message = self.next_message();
+        self.failed = message.is_err();
+        message.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(format: u8, csid: u8, fields: &[u8]) -> Vec<u8> {
+        [&[(format << 6) | csid][..], fields].concat()
+    }
+
+    #[test]
+    fn interleaved_chunks() {
+        // A 200 byte message on csid 4 split around a small one on csid 3
+        let big: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let bytes = [
+            header(0, 4, &[0, 0, 10, 0, 0, 200, 9, 1, 0, 0, 0]),
+            big[..128].to_vec(),
+            header(0, 3, &[0, 0, 20, 0, 0, 2, 20, 0, 0, 0, 0]),
+            vec![0xAA, 0xBB],
+            header(3, 4, &[]),
+            big[128..].to_vec(),
+            // Format 2 only sends a delta, format 3 then repeats it for a new message
+            header(2, 3, &[0, 0, 5]),
+            vec![0xCC, 0xDD],
+            header(3, 3, &[]),
+            vec![0xEE, 0xFF],
+        ]
+        .concat();
+
+        let messages: Vec<RtmpMessage> =
+            ChunkDemuxer::new(&bytes).collect::<Result<_, _>>().unwrap();
+        let summary: Vec<(u32, u32, u8, usize)> = messages
+            .iter()
+            .map(|m| (m.csid, m.timestamp, m.type_id, m.payload.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (3, 20, 20, 2),
+                (4, 10, 9, 200),
+                (3, 25, 20, 2),
+                (3, 30, 20, 2)
+            ]
+        );
+        assert_eq!(messages[1].payload, big);
+        assert_eq!(messages[1].stream_id, 1);
+        assert_eq!(messages[1].pieces.len(), 2);
+        assert_eq!(messages[1].input_offset(128), Some(12 + 128 + 12 + 2 + 1));
+    }
+
+    #[test]
+    fn long_basic_headers_and_extended_timestamps() {
+        // csid 64 + 6 in two bytes, csid 64 + 0x0102 in three, both with extended timestamps
+        let bytes = [
+            vec![0x00, 6, 0xFF, 0xFF, 0xFF, 0, 0, 1, 8, 0, 0, 0, 0],
+            0x0100_0000u32.to_be_bytes().to_vec(),
+            vec![0x11],
+            vec![0x01, 0x02, 0x01, 0xFF, 0xFF, 0xFF, 0, 0, 0, 18, 0, 0, 0, 0],
+            5u32.to_be_bytes().to_vec(),
+            // Format 3 after an extended header carries it again
+            vec![0xC0, 6],
+            0x0100_0000u32.to_be_bytes().to_vec(),
+            vec![0x22],
+        ]
+        .concat();
+        let messages: Vec<RtmpMessage> =
+            ChunkDemuxer::new(&bytes).collect::<Result<_, _>>().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!((messages[0].csid, messages[0].timestamp), (70, 0x0100_0000));
+        assert_eq!((messages[1].csid, messages[1].timestamp), (64 + 0x0102, 5));
+        assert_eq!(messages[1].payload, Vec::<u8>::new());
+        assert!(messages[2].headers[0].extended_timestamp);
+        assert_eq!(messages[2].timestamp, 0x0200_0000);
+        assert_eq!(messages[2].payload, vec![0x22]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut demuxer = ChunkDemuxer::new(&[0x43, 0, 0, 1]);
+        assert_eq!(
+            demuxer.next(),
+            Some(Err(ChunkError::UnknownChunkStream(3, 0)))
+        );
+        assert_eq!(demuxer.next(), None);
+
+        // Says 10 bytes but the capture stops after 2
+        let bytes = [
+            header(0, 3, &[0, 0, 0, 0, 0, 10, 20, 0, 0, 0, 0]),
+            vec![1, 2],
+        ]
+        .concat();
+        assert_eq!(
+            ChunkDemuxer::new(&bytes).next_message(),
+            Err(ChunkError::Truncated(12))
+        );
+
+        let mut demuxer = ChunkDemuxer::new(&bytes);
+        assert_eq!(demuxer.set_chunk_size(0), Err(ChunkError::BadChunkSize(0)));
+        demuxer.set_chunk_size(2).unwrap();
+        assert_eq!(demuxer.next_message(), Err(ChunkError::Truncated(0)));
+    }
+
+    #[test]
+    fn control_messages_change_reading() {
+        // Chunk size goes up to 4, then a 6 byte message is abandoned half way
+        let bytes = [
+            header(0, 2, &[0, 0, 0, 0, 0, 4, 1, 0, 0, 0, 0]),
+            vec![0, 0, 0, 4],
+            header(0, 3, &[0, 0, 0, 0, 0, 6, 20, 0, 0, 0, 0]),
+            vec![1, 2, 3, 4],
+            header(0, 2, &[0, 0, 0, 0, 0, 4, 2, 0, 0, 0, 0]),
+            vec![0, 0, 0, 3],
+        ]
+        .concat();
+        let mut demuxer = ChunkDemuxer::new(&bytes);
+        assert_eq!(demuxer.next_message().unwrap().unwrap().type_id, 1);
+        assert_eq!(demuxer.chunk_size(), 4);
+        assert_eq!(demuxer.next_message().unwrap().unwrap().type_id, 2);
+        // Nothing is left waiting, so the end is clean
+        assert_eq!(demuxer.next_message(), Ok(None));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::amf::amf_highlight::AMFReader;
+use crate::amf::object_info::ObjectInfo;
+use crate::amf::object_type::ObjectType;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandKind {
+    Connect,
+    CreateStream,
+    Play,
+    Publish,
+    DeleteStream,
+    Result,
+    Error,
+    OnStatus,
+    Call, // Any other name, a method on the server or client
+}
+
+impl CommandKind {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "connect" => CommandKind::Connect,
+            "createStream" => CommandKind::CreateStream,
+            "play" => CommandKind::Play,
+            "publish" => CommandKind::Publish,
+            "deleteStream" => CommandKind::DeleteStream,
+            "_result" => CommandKind::Result,
+            "_error" => CommandKind::Error,
+            "onStatus" => CommandKind::OnStatus,
+            _ => CommandKind::Call,
+        }
+    }
+
+    // What the arguments after the command object are called, the rest are just numbered
+    fn argument_names(&self) -> &'static [&'static str] {
+        match self {
+            CommandKind::Connect => &["Optional User Arguments"],
+            CommandKind::Play => &["Stream Name", "Start", "Duration", "Reset"],
+            CommandKind::Publish => &["Publishing Name", "Publishing Type"],
+            CommandKind::DeleteStream => &["Stream ID"],
+            CommandKind::Result => &["Response"],
+            CommandKind::Error => &["Error Information"],
+            CommandKind::OnStatus => &["Info Object"],
+            CommandKind::CreateStream | CommandKind::Call => &[],
+        }
+    }
+}
+
+impl fmt::Display for CommandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandKind::Connect => write!(f, "Connect"),
+            CommandKind::CreateStream => write!(f, "Create Stream"),
+            CommandKind::Play => write!(f, "Play"),
+            CommandKind::Publish => write!(f, "Publish"),
+            CommandKind::DeleteStream => write!(f, "Delete Stream"),
+            CommandKind::Result => write!(f, "Result"),
+            CommandKind::Error => write!(f, "Error"),
+            CommandKind::OnStatus => write!(f, "Status"),
+            CommandKind::Call => write!(f, "Call"),
+        }
+    }
+}
+
+/// A command message's values: name, transaction ID, command object and the arguments.
+/// The values are object IDs in the map it was read from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub kind: CommandKind,
+    pub transaction_id: f64,
+    pub command_object: Option<isize>,
+    pub arguments: Vec<isize>,
+}
+
+/// Looks through the switch to AMF3 marker at the value after it.
+pub fn resolve<'a>(
+    objects: &'a HashMap<isize, ObjectInfo<'a>>,
+    object_id: isize,
+) -> Option<&'a ObjectInfo<'a>> {
+    let mut info = objects.get(&object_id)?;
+    while let ObjectType::Amf0Switch(value) = info.object_type {
+        info = objects.get(&value)?;
+    }
+    Some(info)
+}
+
+pub(crate) fn as_string<'a>(info: &'a ObjectInfo) -> Option<&'a str> {
+    match &info.object_type {
+        ObjectType::Amf0String(value)
+        | ObjectType::Amf0LongString(value)
+        | ObjectType::Amf3String(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub(crate) fn as_number(info: &ObjectInfo) -> Option<f64> {
+    match info.object_type {
+        ObjectType::Amf0Number(value) | ObjectType::Amf3Double(value) => Some(value),
+        ObjectType::Amf3Integer(value) => Some(value as f64),
+        _ => None,
+    }
+}
+
+/// The named members of an object, ECMA array or AMF3 object, None for anything else.
+pub fn members<'a>(
+    objects: &'a HashMap<isize, ObjectInfo>,
+    object_id: isize,
+) -> Option<&'a Vec<(String, isize)>> {
+    match &resolve(objects, object_id)?.object_type {
+        ObjectType::Amf0Object(members)
+        | ObjectType::Amf0EcmaArray(members)
+        | ObjectType::Amf0TypedObject(members)
+        | ObjectType::Amf3Object(members)
+        | ObjectType::Amf3Array(members, _) => Some(members),
+        _ => None,
+    }
+}
+
+/// A member of an object, ECMA array or AMF3 object by name.
+pub fn member<'a>(
+    objects: &'a HashMap<isize, ObjectInfo<'a>>,
+    object_id: isize,
+    name: &str,
+) -> Option<&'a ObjectInfo<'a>> {
+    let (_, id) = members(objects, object_id)?
+        .iter()
+        .find(|(key, _)| key == name)?;
+    resolve(objects, *id)
+}
+
+/// One line for a value, primitives as themselves and containers by type and size.
+pub fn summary(objects: &HashMap<isize, ObjectInfo>, object_id: isize) -> String {
+    let Some(info) = resolve(objects, object_id) else {
+        return String::from("missing");
+    };
+    if let Some(value) = as_string(info) {
+        return format!("{:?}", value);
+    }
+    if let Some(value) = as_number(info) {
+        return value.to_string();
+    }
+    match &info.object_type {
+        ObjectType::Amf0Bool(value) => value.to_string(),
+        ObjectType::Amf3True => String::from("true"),
+        ObjectType::Amf3False => String::from("false"),
+        ObjectType::Amf0Null | ObjectType::Amf3Null => String::from("null"),
+        ObjectType::Amf0Undefined | ObjectType::Amf3Undefined => String::from("undefined"),
+        object_type => {
+            let count = object_type.children().len();
+            format!("{} #{} ({} values)", object_type, info.object_id, count)
+        }
+    }
+}
+
+/// Reads the command out of a decoded command message, None when it doesn't start with a
+/// name and a transaction ID.
+pub fn parse_command(objects: &HashMap<isize, ObjectInfo>, roots: &[isize]) -> Option<Command> {
+    let name = as_string(resolve(objects, *roots.first()?)?)?.to_string();
+    let transaction_id = as_number(resolve(objects, *roots.get(1)?)?)?;
+    // Usually null when there is nothing to say, but it's still there
+    let command_object = roots.get(2).copied().filter(|id| {
+        !matches!(
+            resolve(objects, *id).map(|info| &info.object_type),
+            Some(ObjectType::Amf0Null) | Some(ObjectType::Amf3Null)
+        )
+    });
+    Some(Command {
+        kind: CommandKind::from_name(&name),
+        name,
+        transaction_id,
+        command_object,
+        arguments: roots.iter().skip(3).copied().collect(),
+    })
+}
+
+/// Whether a bare AMF payload starts with the format byte of an AMF3 command message, i.e.
+/// a 0 that would otherwise be read as a number, then a command name and transaction ID.
+/// Only those two values are read, the rest of the payload is left for the real decode.
+pub fn has_format_byte(buffer: &[u8]) -> bool {
+    if !buffer.starts_with(&[0x00, 0x02]) {
+        return false;
+    }
+    let mut reader = AMFReader::new(buffer, true);
+    let name = reader.read_amf0();
+    let transaction_id = reader.read_amf0();
+    if reader.is_error() {
+        return false;
+    }
+    let is_name = matches!(
+        reader.objects.get(&name).map(|info| &info.object_type),
+        Some(ObjectType::Amf0String(name)) if !name.is_empty()
+    );
+    let is_number = matches!(
+        reader
+            .objects
+            .get(&transaction_id)
+            .map(|info| &info.object_type),
+        Some(ObjectType::Amf0Number(_))
+    );
+    is_name && is_number
+}
+
+/// Whether to skip a command's format byte at the start of a bare payload. Auto goes by
+/// `has_format_byte`, which an AMF0 number starting with 0x02 can fool, the others overrule it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FormatByte {
+    #[default]
+    Auto,
+    Present,
+    Absent,
+}
+
+impl FormatByte {
+    pub fn applies(self, buffer: &[u8]) -> bool {
+        match self {
+            FormatByte::Auto => has_format_byte(buffer),
+            FormatByte::Present => true,
+            FormatByte::Absent => false,
+        }
+    }
+}
+
+impl Command {
+    pub fn fields(&self, objects: &HashMap<isize, ObjectInfo>) -> Vec<(String, String)> {
+        let mut fields = vec![
+            (
+                String::from("Command"),
+                format!("{} ({})", self.name, self.kind),
+            ),
+            (
+                String::from("Transaction ID"),
+                self.transaction_id.to_string(),
+            ),
+            (
+                String::from("Command Object"),
+                match self.command_object {
+                    Some(id) => summary(objects, id),
+                    None => String::from("null"),
+                },
+            ),
+        ];
+        let names = self.kind.argument_names();
+        for (i, argument) in self.arguments.iter().enumerate() {
+            let name = match names.get(i) {
+                Some(name) => name.to_string(),
+                None => format!("Argument {}", i + 1),
+            };
+            fields.push((name, summary(objects, *argument)));
+        }
+        // Status and error info objects say what happened in these two
+        let info = match self.kind {
+            CommandKind::OnStatus | CommandKind::Error | CommandKind::Result => {
+                self.arguments.first()
+            }
+            _ => None,
+        };
+        if let Some(info) = info {
+            for (key, label) in [
+                ("level", "Level"),
+                ("code", "Code"),
+                ("description", "Description"),
+            ] {
+                if let Some(value) = member(objects, *info, key).and_then(as_string) {
+                    fields.push((label.to_string(), value.to_string()));
+                }
+            }
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_writer::AMFWriter;
+
+    #[test]
+    fn on_status() {
+        let mut writer = AMFWriter::new(true);
+        writer.write_amf0_string("onStatus").unwrap();
+        writer.write_amf0_number(0.0);
+        writer.out.push(0x05);
+        writer.out.extend_from_slice(&[0x03, 0x00, 0x04]);
+        writer.out.extend_from_slice(b"code");
+        writer.write_amf0_string("NetStream.Play.Start").unwrap();
+        writer.out.extend_from_slice(&[0x00, 0x00, 0x09]);
+        let bytes = writer.into_bytes();
+        assert!(has_format_byte(&bytes));
+        assert!(!has_format_byte(&bytes[1..]));
+        assert!(!FormatByte::Absent.applies(&bytes));
+        assert!(FormatByte::Present.applies(&bytes[1..]));
+        // A number whose first byte is 0x02, the "name" after it is empty
+        let mut number = vec![0x00];
+        number.extend_from_slice(&f64::from_bits(0x0200_0000_0000_0000).to_be_bytes());
+        assert!(!has_format_byte(&number));
+
+        let mut reader = AMFReader::new(&bytes, true);
+        reader.highlight();
+        let command = parse_command(&reader.objects, &reader.roots).unwrap();
+        assert_eq!(command.kind, CommandKind::OnStatus);
+        assert_eq!(command.command_object, None);
+        let fields = command.fields(&reader.objects);
+        assert_eq!(fields[0].1, "onStatus (Status)");
+        assert_eq!(fields[3].0, "Info Object");
+        assert_eq!(
+            fields.last().unwrap(),
+            &(String::from("Code"), String::from("NetStream.Play.Start"))
+        );
+    }
+
+    #[test]
+    fn play_arguments() {
+        let mut writer = AMFWriter::new(false);
+        writer.write_amf0_string("play").unwrap();
+        writer.write_amf0_number(4.0);
+        writer.out.push(0x05);
+        writer.write_amf0_string("stream").unwrap();
+        writer.write_amf0_number(-2.0);
+        let bytes = writer.into_bytes();
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let fields = parse_command(&reader.objects, &reader.roots)
+            .unwrap()
+            .fields(&reader.objects);
+        assert_eq!(
+            fields[1..],
+            [
+                (String::from("Transaction ID"), String::from("4")),
+                (String::from("Command Object"), String::from("null")),
+                (String::from("Stream Name"), String::from("\"stream\"")),
+                (String::from("Start"), String::from("-2")),
+            ]
+        );
+    }
+}
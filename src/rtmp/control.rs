@@ -0,0 +1,201 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlError {
+    NotControl(u8),
+    TooShort(u8, usize), // Type ID, bytes there were
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::NotControl(type_id) => {
+                write!(f, "Type {} is not a protocol control message", type_id)
+            }
+            ControlError::TooShort(type_id, len) => {
+                write!(f, "Type {} message is too short at {} bytes", type_id, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitType {
+    Hard,
+    Soft,
+    Dynamic, // Hard if the last one was hard, otherwise ignored
+    Unknown(u8),
+}
+
+impl fmt::Display for LimitType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitType::Hard => write!(f, "Hard (0)"),
+            LimitType::Soft => write!(f, "Soft (1)"),
+            LimitType::Dynamic => write!(f, "Dynamic (2)"),
+            LimitType::Unknown(value) => write!(f, "Unknown ({})", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UserControlEvent {
+    StreamBegin(u32),
+    StreamEof(u32),
+    StreamDry(u32),
+    SetBufferLength(u32, u32), // Stream ID, milliseconds
+    StreamIsRecorded(u32),
+    PingRequest(u32), // Server's timestamp, sent back in the response
+    PingResponse(u32),
+    Unknown(u16, Vec<u8>),
+}
+
+impl UserControlEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UserControlEvent::StreamBegin(_) => "Stream Begin",
+            UserControlEvent::StreamEof(_) => "Stream EOF",
+            UserControlEvent::StreamDry(_) => "Stream Dry",
+            UserControlEvent::SetBufferLength(..) => "Set Buffer Length",
+            UserControlEvent::StreamIsRecorded(_) => "Stream Is Recorded",
+            UserControlEvent::PingRequest(_) => "Ping Request",
+            UserControlEvent::PingResponse(_) => "Ping Response",
+            UserControlEvent::Unknown(..) => "Unknown",
+        }
+    }
+}
+
+/// Message types 1 to 6, which are about the connection rather than any stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlMessage {
+    SetChunkSize(u32),
+    Abort(u32), // Chunk stream whose partial message should be dropped
+    Acknowledgement(u32),
+    UserControl(u16, UserControlEvent),
+    WindowAckSize(u32),
+    SetPeerBandwidth(u32, LimitType),
+}
+
+fn u32_at(payload: &[u8], offset: usize) -> Option<u32> {
+    let b = payload.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+pub fn parse_control(type_id: u8, payload: &[u8]) -> Result<ControlMessage, ControlError> {
+    let too_short = ControlError::TooShort(type_id, payload.len());
+    let first = u32_at(payload, 0);
+    let message = match type_id {
+        // The top bit is reserved, it is always 0
+        1 => ControlMessage::SetChunkSize(first.ok_or(too_short)? & 0x7FFF_FFFF),
+        2 => ControlMessage::Abort(first.ok_or(too_short)?),
+        3 => ControlMessage::Acknowledgement(first.ok_or(too_short)?),
+        4 => {
+            let event = match payload.get(..2) {
+                Some(b) => u16::from_be_bytes([b[0], b[1]]),
+                None => return Err(too_short),
+            };
+            let data = |offset| u32_at(payload, offset).ok_or(too_short.clone());
+            let parsed = match event {
+                0 => UserControlEvent::StreamBegin(data(2)?),
+                1 => UserControlEvent::StreamEof(data(2)?),
+                2 => UserControlEvent::StreamDry(data(2)?),
+                3 => UserControlEvent::SetBufferLength(data(2)?, data(6)?),
+                4 => UserControlEvent::StreamIsRecorded(data(2)?),
+                6 => UserControlEvent::PingRequest(data(2)?),
+                7 => UserControlEvent::PingResponse(data(2)?),
+                _ => UserControlEvent::Unknown(event, payload[2..].to_vec()),
+            };
+            ControlMessage::UserControl(event, parsed)
+        }
+        5 => ControlMessage::WindowAckSize(first.ok_or(too_short)?),
+        6 => {
+            let size = first.ok_or(too_short.clone())?;
+            let limit = match payload.get(4).ok_or(too_short)? {
+                0 => LimitType::Hard,
+                1 => LimitType::Soft,
+                2 => LimitType::Dynamic,
+                value => LimitType::Unknown(*value),
+            };
+            ControlMessage::SetPeerBandwidth(size, limit)
+        }
+        _ => return Err(ControlError::NotControl(type_id)),
+    };
+    Ok(message)
+}
+
+impl ControlMessage {
+    /// Name and value of everything in the message, in the order it was sent.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let field = |name: &str, value: String| (name.to_string(), value);
+        match self {
+            ControlMessage::SetChunkSize(size) => vec![field("Chunk Size", size.to_string())],
+            ControlMessage::Abort(csid) => vec![field("Chunk Stream ID", csid.to_string())],
+            ControlMessage::Acknowledgement(sequence) => {
+                vec![field("Sequence Number", sequence.to_string())]
+            }
+            ControlMessage::WindowAckSize(size) => vec![field("Window Size", size.to_string())],
+            ControlMessage::SetPeerBandwidth(size, limit) => vec![
+                field("Window Size", size.to_string()),
+                field("Limit Type", limit.to_string()),
+            ],
+            ControlMessage::UserControl(id, event) => {
+                let mut fields = vec![field("Event", format!("{} ({})", event.name(), id))];
+                match event {
+                    UserControlEvent::StreamBegin(stream)
+                    | UserControlEvent::StreamEof(stream)
+                    | UserControlEvent::StreamDry(stream)
+                    | UserControlEvent::StreamIsRecorded(stream) => {
+                        fields.push(field("Stream ID", stream.to_string()))
+                    }
+                    UserControlEvent::SetBufferLength(stream, length) => {
+                        fields.push(field("Stream ID", stream.to_string()));
+                        fields.push(field("Buffer Length", format!("{} ms", length)));
+                    }
+                    UserControlEvent::PingRequest(time) | UserControlEvent::PingResponse(time) => {
+                        fields.push(field("Timestamp", time.to_string()))
+                    }
+                    UserControlEvent::Unknown(_, data) => {
+                        fields.push(field("Data", format!("{:02X?}", data)))
+                    }
+                }
+                fields
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_messages() {
+        assert_eq!(
+            parse_control(1, &[0x80, 0, 0x10, 0]),
+            Ok(ControlMessage::SetChunkSize(4096))
+        );
+        assert_eq!(
+            parse_control(6, &[0, 0x26, 0x25, 0xA0, 2]),
+            Ok(ControlMessage::SetPeerBandwidth(
+                2_500_000,
+                LimitType::Dynamic
+            ))
+        );
+        let buffer = parse_control(4, &[0, 3, 0, 0, 0, 1, 0, 0, 0x0B, 0xB8]).unwrap();
+        assert_eq!(
+            buffer.fields(),
+            vec![
+                (String::from("Event"), String::from("Set Buffer Length (3)")),
+                (String::from("Stream ID"), String::from("1")),
+                (String::from("Buffer Length"), String::from("3000 ms")),
+            ]
+        );
+        assert_eq!(
+            parse_control(4, &[0, 6, 0, 0]),
+            Err(ControlError::TooShort(4, 4))
+        );
+        assert_eq!(parse_control(20, &[]), Err(ControlError::NotControl(20)));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::amf::object_info::ObjectInfo;
+use crate::rtmp::command::{as_number, as_string, members, resolve, summary};
+use crate::rtmp::message::{sound_format_name, video_codec_name};
+use std::collections::HashMap;
+
+/// A data message's values: the handler it's for, then whatever it carries. With
+/// @setDataFrame the handler the data is stored under comes next, usually onMetaData.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataMessage {
+    pub handler: String,
+    pub frame: Option<String>,
+    pub values: Vec<isize>,
+}
+
+// Metadata we know how to show better, in the order the inspector lists it
+const METADATA: [(&str, &str); 11] = [
+    ("duration", "Duration"),
+    ("width", "Width"),
+    ("height", "Height"),
+    ("videocodecid", "Video Codec"),
+    ("audiocodecid", "Audio Codec"),
+    ("framerate", "Frame Rate"),
+    ("videoframerate", "Frame Rate"),
+    ("videodatarate", "Video Data Rate"),
+    ("audiodatarate", "Audio Data Rate"),
+    ("audiosamplerate", "Audio Sample Rate"),
+    ("audiosamplesize", "Audio Sample Size"),
+];
+
+/// Reads the handler name and values out of a decoded data message, None when it doesn't
+/// start with a name.
+pub fn parse_data(objects: &HashMap<isize, ObjectInfo>, roots: &[isize]) -> Option<DataMessage> {
+    let handler = as_string(resolve(objects, *roots.first()?)?)?.to_string();
+    let mut values = &roots[1..];
+    let mut frame = None;
+    if handler == "@setDataFrame" {
+        if let Some(name) = values
+            .first()
+            .and_then(|id| resolve(objects, *id))
+            .and_then(as_string)
+        {
+            frame = Some(name.to_string());
+            values = &values[1..];
+        }
+    }
+    Some(DataMessage {
+        handler,
+        frame,
+        values: values.to_vec(),
+    })
+}
+
+fn metadata_value(objects: &HashMap<isize, ObjectInfo>, key: &str, id: isize) -> String {
+    let Some(number) = resolve(objects, id).and_then(as_number) else {
+        // Some encoders send codec IDs as strings like "avc1", so leave anything else alone
+        return summary(objects, id);
+    };
+    match key {
+        "duration" => format!("{} s", number),
+        "width" | "height" => format!("{} px", number),
+        "videocodecid" => format!("{} ({})", number, video_codec_name(number as u8)),
+        "audiocodecid" => format!("{} ({})", number, sound_format_name(number as u8)),
+        "framerate" | "videoframerate" => format!("{} fps", number),
+        "videodatarate" | "audiodatarate" => format!("{} kbps", number),
+        "audiosamplerate" => format!("{} Hz", number),
+        "audiosamplesize" => format!("{} bits", number),
+        _ => number.to_string(),
+    }
+}
+
+fn metadata_fields(
+    objects: &HashMap<isize, ObjectInfo>,
+    object_id: isize,
+    fields: &mut Vec<(String, String)>,
+) {
+    let Some(members) = members(objects, object_id) else {
+        fields.push((String::from("Metadata"), summary(objects, object_id)));
+        return;
+    };
+    for (key, label) in METADATA {
+        if let Some((_, id)) = members.iter().find(|(name, _)| name == key) {
+            fields.push((label.to_string(), metadata_value(objects, key, *id)));
+        }
+    }
+    // Then everything else as a plain table, in the order it was sent
+    for (key, id) in members {
+        if !METADATA.iter().any(|(name, _)| name == key) {
+            fields.push((key.clone(), summary(objects, *id)));
+        }
+    }
+}
+
+impl DataMessage {
+    pub fn fields(&self, objects: &HashMap<isize, ObjectInfo>) -> Vec<(String, String)> {
+        let mut fields = vec![(String::from("Handler"), self.handler.clone())];
+        if let Some(frame) = &self.frame {
+            fields.push((String::from("Data Frame"), frame.clone()));
+        }
+        let name = self.frame.as_ref().unwrap_or(&self.handler);
+        match name.as_str() {
+            "onMetaData" if !self.values.is_empty() => {
+                metadata_fields(objects, self.values[0], &mut fields)
+            }
+            // Whether the client may read the raw audio and video, e.g. with BitmapData.draw
+            "|RtmpSampleAccess" => {
+                for (i, label) in ["Audio Access", "Video Access"].iter().enumerate() {
+                    if let Some(id) = self.values.get(i) {
+                        fields.push((label.to_string(), summary(objects, *id)));
+                    }
+                }
+            }
+            _ => {
+                for (i, id) in self.values.iter().enumerate() {
+                    fields.push((format!("Argument {}", i + 1), summary(objects, *id)));
+                }
+            }
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_highlight::AMFReader;
+    use crate::amf::amf_writer::AMFWriter;
+
+    #[test]
+    fn set_data_frame() {
+        let mut writer = AMFWriter::new(false);
+        writer.write_amf0_string("@setDataFrame").unwrap();
+        writer.write_amf0_string("onMetaData").unwrap();
+        writer.out.extend_from_slice(&[0x08, 0, 0, 0, 3]);
+        for (key, value) in [
+            ("encoder", None),
+            ("width", Some(1280.0)),
+            ("videocodecid", Some(7.0)),
+        ] {
+            writer
+                .out
+                .extend_from_slice(&(key.len() as u16).to_be_bytes());
+            writer.out.extend_from_slice(key.as_bytes());
+            match value {
+                Some(value) => writer.write_amf0_number(value),
+                None => writer.write_amf0_string("obs-output").unwrap(),
+            }
+        }
+        writer.out.extend_from_slice(&[0x00, 0x00, 0x09]);
+        let bytes = writer.into_bytes();
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        let data = parse_data(&reader.objects, &reader.roots).unwrap();
+        assert_eq!(data.frame.as_deref(), Some("onMetaData"));
+        let field = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(
+            data.fields(&reader.objects),
+            [
+                field("Handler", "@setDataFrame"),
+                field("Data Frame", "onMetaData"),
+                field("Width", "1280 px"),
+                field("Video Codec", "7 (AVC)"),
+                field("encoder", "\"obs-output\""),
+            ]
+        );
+    }
+}
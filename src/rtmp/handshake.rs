@@ -0,0 +1,366 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::ops::Range;
+
+pub const PACKET_SIZE: usize = 1536; // C1, C2, S1 and S2
+pub const HANDSHAKE_SIZE: usize = 1 + 2 * PACKET_SIZE; // What one side sends before any chunks
+const DIGEST_SIZE: usize = 32;
+const BLOCK_SIZE: usize = 764; // The key and digest blocks of a complex handshake
+
+// Shared by both keys, the part after the readable text
+const KEY_TAIL: [u8; 32] = [
+    0xF0, 0xEE, 0xC2, 0x4A, 0x80, 0x68, 0xBE, 0xE8, 0x2E, 0x00, 0xD0, 0xD1, 0x02, 0x9E, 0x7E, 0x57,
+    0x6E, 0xEC, 0x5D, 0x2D, 0x29, 0x80, 0x6F, 0xAB, 0x93, 0xB8, 0xE6, 0x36, 0xCF, 0xEB, 0x31, 0xAE,
+];
+const PLAYER_KEY: &[u8] = b"Genuine Adobe Flash Player 001";
+const SERVER_KEY: &[u8] = b"Genuine Adobe Flash Media Server 001";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandshakeError {
+    Truncated(usize), // Bytes there were, HANDSHAKE_SIZE are needed
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Truncated(len) => write!(
+                f,
+                "Handshake needs {} bytes but there are only {}",
+                HANDSHAKE_SIZE, len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Client => write!(f, "Client"),
+            Side::Server => write!(f, "Server"),
+        }
+    }
+}
+
+/// Where the digest is in a complex handshake. Named like SRS does, schema 0 has the key block
+/// first and the digest block second, schema 1 the other way round.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Schema {
+    Schema0,
+    Schema1,
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schema::Schema0 => write!(f, "Schema 0"),
+            Schema::Schema1 => write!(f, "Schema 1"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Digest {
+    pub schema: Schema,
+    pub side: Side,         // Whose key it was made with
+    pub span: Range<usize>, // Absolute, like the packet spans
+    pub value: [u8; DIGEST_SIZE],
+}
+
+/// C1/S1 or C2/S2.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandshakePacket {
+    pub span: Range<usize>,
+    pub time: u32,
+    pub field: u32, // Zero for a simple handshake, the player or server version in a complex one
+    pub bytes: Vec<u8>,
+}
+
+impl HandshakePacket {
+    fn new(bytes: &[u8], start: usize) -> Self {
+        let bytes = bytes[start..start + PACKET_SIZE].to_vec();
+        HandshakePacket {
+            span: start..start + PACKET_SIZE,
+            time: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            field: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            bytes,
+        }
+    }
+
+    /// The random block, or for C2/S2 the echo of the peer's.
+    pub fn random(&self) -> &[u8] {
+        &self.bytes[8..]
+    }
+}
+
+/// One side's half of the handshake: the version byte and the two packets after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handshake {
+    pub version: u8, // 3 for plain RTMP, 6 and up are encrypted
+    pub first: HandshakePacket,
+    pub second: HandshakePacket,
+    pub digest: Option<Digest>, // Found and verified in the first packet
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn full_key(side: Side) -> Vec<u8> {
+    let text = match side {
+        Side::Client => PLAYER_KEY,
+        Side::Server => SERVER_KEY,
+    };
+    [text, &KEY_TAIL[..]].concat()
+}
+
+// Start of the digest in a packet, from the four offset bytes at the start of its block
+fn digest_offset(packet: &[u8], schema: Schema) -> usize {
+    let block = match schema {
+        Schema::Schema0 => 8 + BLOCK_SIZE,
+        Schema::Schema1 => 8,
+    };
+    let sum: usize = packet[block..block + 4].iter().map(|b| *b as usize).sum();
+    block + 4 + sum % (BLOCK_SIZE - DIGEST_SIZE - 4)
+}
+
+/// The digest a C1 or S1 should carry for a schema, over everything but the digest itself.
+/// Only the readable part of the key is used here.
+pub fn packet_digest(packet: &[u8], schema: Schema, side: Side) -> [u8; DIGEST_SIZE] {
+    let offset = digest_offset(packet, schema);
+    let key = match side {
+        Side::Client => PLAYER_KEY,
+        Side::Server => SERVER_KEY,
+    };
+    hmac(
+        key,
+        &[
+            &packet[..offset],
+            &packet[offset + DIGEST_SIZE..PACKET_SIZE],
+        ],
+    )
+}
+
+// Tries every schema and key, a match also tells us which side sent it
+fn find_digest(packet: &HandshakePacket) -> Option<Digest> {
+    for schema in [Schema::Schema0, Schema::Schema1] {
+        let offset = digest_offset(&packet.bytes, schema);
+        for side in [Side::Client, Side::Server] {
+            if packet_digest(&packet.bytes, schema, side)[..]
+                == packet.bytes[offset..offset + DIGEST_SIZE]
+            {
+                let start = packet.span.start + offset;
+                let mut value = [0; DIGEST_SIZE];
+                value.copy_from_slice(&packet.bytes[offset..offset + DIGEST_SIZE]);
+                return Some(Digest {
+                    schema,
+                    side,
+                    span: start..start + DIGEST_SIZE,
+                    value,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// The last 32 bytes of a C2 or S2 in a complex handshake, keyed on the peer's C1/S1 digest.
+pub fn reply_digest(reply: &[u8], peer_digest: &[u8], side: Side) -> [u8; DIGEST_SIZE] {
+    let key = hmac(&full_key(side), &[peer_digest]);
+    hmac(&key, &[&reply[..PACKET_SIZE - DIGEST_SIZE]])
+}
+
+/// Whether the bytes look like the start of a handshake rather than a chunk. A version byte of
+/// 3 is also a format 0 header on chunk stream 3, so a simple handshake also needs the zero
+/// field to be zero and a complex one a digest that checks out.
+pub fn is_handshake(bytes: &[u8]) -> bool {
+    if bytes.len() < 1 + PACKET_SIZE || !matches!(bytes[0], 3 | 6 | 8 | 9) {
+        return false;
+    }
+    let first = HandshakePacket::new(bytes, 1);
+    first.field == 0 || find_digest(&first).is_some()
+}
+
+pub fn parse_handshake(bytes: &[u8]) -> Result<Handshake, HandshakeError> {
+    if bytes.len() < HANDSHAKE_SIZE {
+        return Err(HandshakeError::Truncated(bytes.len()));
+    }
+    let first = HandshakePacket::new(bytes, 1);
+    let second = HandshakePacket::new(bytes, 1 + PACKET_SIZE);
+    // A simple handshake has no digest to find, so don't go looking
+    let digest = if first.field != 0 {
+        find_digest(&first)
+    } else {
+        None
+    };
+    Ok(Handshake {
+        version: bytes[0],
+        first,
+        second,
+        digest,
+    })
+}
+
+impl Handshake {
+    /// Where the chunk stream starts.
+    pub fn end(&self) -> usize {
+        self.second.span.end
+    }
+
+    pub fn is_complex(&self) -> bool {
+        self.first.field != 0
+    }
+
+    /// Which side sent this, only known when there was a digest to go by.
+    pub fn side(&self) -> Option<Side> {
+        self.digest.as_ref().map(|digest| digest.side)
+    }
+
+    /// Plain RTMP, and for a complex handshake a digest made with one of the known keys.
+    pub fn is_valid(&self) -> bool {
+        self.version == 3 && (!self.is_complex() || self.digest.is_some())
+    }
+
+    /// Simple or complex, and for complex which digest matched.
+    pub fn kind(&self) -> String {
+        match &self.digest {
+            Some(digest) => format!(
+                "complex, {} digest from the {} at {:#X}",
+                digest.schema,
+                digest.side.to_string().to_lowercase(),
+                digest.span.start
+            ),
+            None if self.is_complex() => String::from("complex, no digest matched"),
+            None => String::from("simple"),
+        }
+    }
+
+    /// Name and value of each part, in the order they were sent.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let field = |name: &str, value: String| (name.to_string(), value);
+        let version = self.first.field.to_be_bytes();
+        vec![
+            field("Version", self.version.to_string()),
+            field("Kind", self.kind()),
+            field("Time", self.first.time.to_string()),
+            match self.is_complex() {
+                true => field(
+                    "Peer Version",
+                    format!(
+                        "{}.{}.{}.{}",
+                        version[0], version[1], version[2], version[3]
+                    ),
+                ),
+                false => field("Zero", self.first.field.to_string()),
+            },
+            field("Echo Time", self.second.time.to_string()),
+            field("Valid", self.is_valid().to_string()),
+        ]
+    }
+
+    /// Checks our C2/S2 against the C1/S1 the other side sent. Simple handshakes echo it back
+    /// as it was, complex ones sign it with a key made from the peer's digest.
+    pub fn answers(&self, peer: &Handshake) -> bool {
+        match (&peer.digest, self.side()) {
+            (Some(peer_digest), side) => {
+                let side = side.unwrap_or(match peer_digest.side {
+                    Side::Client => Side::Server,
+                    Side::Server => Side::Client,
+                });
+                reply_digest(&self.second.bytes, &peer_digest.value, side)[..]
+                    == self.second.bytes[PACKET_SIZE - DIGEST_SIZE..]
+            }
+            (None, _) => {
+                self.second.time == peer.first.time && self.second.random() == peer.first.random()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A complex C1 signed with the player key, then a C2
+    fn complex(schema: Schema, side: Side) -> Vec<u8> {
+        let mut packet: Vec<u8> = (0..PACKET_SIZE).map(|i| (i * 7) as u8).collect();
+        packet[4..8].copy_from_slice(&[0x80, 0x00, 0x07, 0x02]);
+        let offset = digest_offset(&packet, schema);
+        let digest = packet_digest(&packet, schema, side);
+        packet[offset..offset + DIGEST_SIZE].copy_from_slice(&digest);
+        [&[3][..], &packet, &[0; PACKET_SIZE]].concat()
+    }
+
+    #[test]
+    fn simple() {
+        let mut bytes = vec![3, 0, 0, 0, 9, 0, 0, 0, 0];
+        bytes.resize(HANDSHAKE_SIZE + 4, 0xAB);
+        assert!(is_handshake(&bytes));
+        let handshake = parse_handshake(&bytes).unwrap();
+        assert_eq!(handshake.first.time, 9);
+        assert!(!handshake.is_complex());
+        assert!(handshake.is_valid());
+        assert_eq!(handshake.end(), HANDSHAKE_SIZE);
+
+        // A connect on chunk stream 3 isn't a handshake, the type byte lands in the zero field
+        let mut chunk = vec![0x03, 0, 0, 0, 0, 0, 0xC8, 0x14, 0, 0, 0, 0];
+        chunk.resize(HANDSHAKE_SIZE, 0);
+        assert!(!is_handshake(&chunk));
+        assert_eq!(
+            parse_handshake(&bytes[..100]),
+            Err(HandshakeError::Truncated(100))
+        );
+    }
+
+    #[test]
+    fn complex_digests() {
+        for schema in [Schema::Schema0, Schema::Schema1] {
+            let bytes = complex(schema, Side::Client);
+            let handshake = parse_handshake(&bytes).unwrap();
+            let digest = handshake.digest.as_ref().unwrap();
+            assert_eq!((digest.schema, digest.side), (schema, Side::Client));
+            assert!(handshake.is_valid());
+        }
+
+        let mut bytes = complex(Schema::Schema1, Side::Server);
+        assert_eq!(parse_handshake(&bytes).unwrap().side(), Some(Side::Server));
+        // Any changed byte outside the digest breaks it
+        bytes[1000] ^= 1;
+        let handshake = parse_handshake(&bytes).unwrap();
+        assert_eq!(handshake.digest, None);
+        assert!(!handshake.is_valid());
+    }
+
+    #[test]
+    fn replies() {
+        let client = parse_handshake(&complex(Schema::Schema0, Side::Client)).unwrap();
+        let mut bytes = complex(Schema::Schema1, Side::Server);
+        let digest = {
+            let peer = client.digest.as_ref().unwrap();
+            reply_digest(&bytes[1 + PACKET_SIZE..], &peer.value, Side::Server)
+        };
+        bytes[HANDSHAKE_SIZE - DIGEST_SIZE..].copy_from_slice(&digest);
+        let server = parse_handshake(&bytes).unwrap();
+        assert!(server.answers(&client));
+        assert!(!client.answers(&server));
+
+        // Simple handshakes just echo the other side's C1/S1
+        let mut peer = vec![3, 0, 0, 0, 1, 0, 0, 0, 0];
+        peer.resize(HANDSHAKE_SIZE, 5);
+        let peer = parse_handshake(&peer).unwrap();
+        let echo = [&[3][..], &peer.first.bytes, &peer.first.bytes].concat();
+        assert!(parse_handshake(&echo).unwrap().answers(&peer));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::rtmp::message::{sound_format_name, video_codec_name};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MediaError {
+    NotMedia(u8),
+    TooShort(u8, usize), // Type ID, bytes there were
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaError::NotMedia(type_id) => {
+                write!(f, "Type {} is not an audio or video message", type_id)
+            }
+            MediaError::TooShort(type_id, len) => {
+                write!(f, "Type {} message is too short at {} bytes", type_id, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MediaError {}
+
+/// What an AAC decoder needs before the first frame, sent once in the sequence header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioSpecificConfig {
+    pub object_type: u8,
+    pub sample_rate: u32,
+    pub channels: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioHeader {
+    pub sound_format: u8,
+    pub sound_rate: u8, // Index into 5.5, 11, 22 and 44 kHz
+    pub sixteen_bit: bool,
+    pub stereo: bool,
+    pub aac_packet_type: Option<u8>,
+    pub config: Option<AudioSpecificConfig>,
+}
+
+/// The SPS and PPS an H.264 decoder needs before the first frame, sent once in the
+/// sequence header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AvcDecoderConfig {
+    pub version: u8,
+    pub profile: u8,
+    pub compatibility: u8,
+    pub level: u8,
+    pub nal_length_size: u8,
+    pub sps: Vec<Vec<u8>>,
+    pub pps: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoHeader {
+    pub frame_type: u8,
+    pub codec_id: u8,
+    pub avc_packet_type: Option<u8>,
+    pub composition_time: Option<i32>, // Milliseconds to add for the presentation time
+    pub config: Option<AvcDecoderConfig>,
+}
+
+/// The FLV tag header at the start of audio (8) and video (9) messages, the media after it
+/// is left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MediaHeader {
+    Audio(AudioHeader),
+    Video(VideoHeader),
+}
+
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+// MSB first, like everything in the MPEG-4 configs
+struct Bits<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.bytes.get(self.position / 8)?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+fn audio_specific_config(bytes: &[u8]) -> Option<AudioSpecificConfig> {
+    let mut bits = Bits { bytes, position: 0 };
+    let mut object_type = bits.read(5)?;
+    if object_type == 31 {
+        object_type = 32 + bits.read(6)?;
+    }
+    let index = bits.read(4)?;
+    let sample_rate = match index {
+        15 => bits.read(24)?,
+        _ => *AAC_SAMPLE_RATES.get(index as usize)?,
+    };
+    Some(AudioSpecificConfig {
+        object_type: object_type as u8,
+        sample_rate,
+        channels: bits.read(4)? as u8,
+    })
+}
+
+// Parameter sets are each a 16-bit length and that many bytes
+fn parameter_sets(bytes: &[u8], offset: &mut usize, count: usize) -> Option<Vec<Vec<u8>>> {
+    let mut sets = Vec::new();
+    for _ in 0..count {
+        let len = bytes.get(*offset..*offset + 2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        sets.push(bytes.get(*offset + 2..*offset + 2 + len)?.to_vec());
+        *offset += 2 + len;
+    }
+    Some(sets)
+}
+
+fn avc_decoder_config(bytes: &[u8]) -> Option<AvcDecoderConfig> {
+    let header = bytes.get(..6)?;
+    let mut offset = 6;
+    let sps = parameter_sets(bytes, &mut offset, (header[5] & 0x1F) as usize)?;
+    let pps_count = *bytes.get(offset)? as usize;
+    offset += 1;
+    let pps = parameter_sets(bytes, &mut offset, pps_count)?;
+    Some(AvcDecoderConfig {
+        version: header[0],
+        profile: header[1],
+        compatibility: header[2],
+        level: header[3],
+        nal_length_size: (header[4] & 0x03) + 1,
+        sps,
+        pps,
+    })
+}
+
+pub fn parse_media(type_id: u8, payload: &[u8]) -> Result<MediaHeader, MediaError> {
+    let too_short = MediaError::TooShort(type_id, payload.len());
+    let first = *payload.first().ok_or(too_short.clone())?;
+    match type_id {
+        8 => {
+            let sound_format = first >> 4;
+            let mut header = AudioHeader {
+                sound_format,
+                sound_rate: (first >> 2) & 0x03,
+                sixteen_bit: first & 0x02 != 0,
+                stereo: first & 0x01 != 0,
+                aac_packet_type: None,
+                config: None,
+            };
+            if sound_format == 10 {
+                let packet_type = *payload.get(1).ok_or(too_short)?;
+                header.aac_packet_type = Some(packet_type);
+                if packet_type == 0 {
+                    header.config = audio_specific_config(&payload[2..]);
+                }
+            }
+            Ok(MediaHeader::Audio(header))
+        }
+        9 => {
+            let codec_id = first & 0x0F;
+            let mut header = VideoHeader {
+                frame_type: first >> 4,
+                codec_id,
+                avc_packet_type: None,
+                composition_time: None,
+                config: None,
+            };
+            if codec_id == 7 {
+                let b = payload.get(1..5).ok_or(too_short)?;
+                header.avc_packet_type = Some(b[0]);
+                // Signed 24 bits
+                header.composition_time = Some(i32::from_be_bytes([b[1], b[2], b[3], 0]) >> 8);
+                if b[0] == 0 {
+                    header.config = avc_decoder_config(&payload[5..]);
+                }
+            }
+            Ok(MediaHeader::Video(header))
+        }
+        _ => Err(MediaError::NotMedia(type_id)),
+    }
+}
+
+fn aac_object_name(object_type: u8) -> &'static str {
+    match object_type {
+        1 => "AAC Main",
+        2 => "AAC LC",
+        3 => "AAC SSR",
+        4 => "AAC LTP",
+        5 => "SBR (HE-AAC)",
+        29 => "PS (HE-AAC v2)",
+        _ => "Unknown",
+    }
+}
+
+fn avc_profile_name(profile: u8) -> &'static str {
+    match profile {
+        66 => "Baseline",
+        77 => "Main",
+        88 => "Extended",
+        100 => "High",
+        110 => "High 10",
+        122 => "High 4:2:2",
+        244 => "High 4:4:4",
+        _ => "Unknown",
+    }
+}
+
+fn frame_type_name(frame_type: u8) -> &'static str {
+    match frame_type {
+        1 => "Key Frame",
+        2 => "Inter Frame",
+        3 => "Disposable Inter Frame",
+        4 => "Generated Key Frame",
+        5 => "Video Info/Command Frame",
+        _ => "Unknown",
+    }
+}
+
+fn packet_type_name(packet_type: u8) -> &'static str {
+    match packet_type {
+        0 => "Sequence Header",
+        1 => "Raw",
+        2 => "End of Sequence",
+        _ => "Unknown",
+    }
+}
+
+impl MediaHeader {
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let field = |name: &str, value: String| (name.to_string(), value);
+        let mut fields = Vec::new();
+        match self {
+            MediaHeader::Audio(audio) => {
+                fields.push(field(
+                    "Sound Format",
+                    format!(
+                        "{} ({})",
+                        sound_format_name(audio.sound_format),
+                        audio.sound_format
+                    ),
+                ));
+                let rate = ["5.5 kHz", "11 kHz", "22 kHz", "44 kHz"][audio.sound_rate as usize];
+                fields.push(field("Sound Rate", rate.to_string()));
+                let size = if audio.sixteen_bit { "16 bit" } else { "8 bit" };
+                fields.push(field("Sound Size", size.to_string()));
+                let channels = if audio.stereo { "Stereo" } else { "Mono" };
+                fields.push(field("Sound Type", channels.to_string()));
+                if let Some(packet_type) = audio.aac_packet_type {
+                    fields.push(field(
+                        "AAC Packet Type",
+                        format!("{} ({})", packet_type_name(packet_type), packet_type),
+                    ));
+                }
+                // AAC always says 44 kHz stereo above, these are the real ones
+                if let Some(config) = &audio.config {
+                    fields.push(field(
+                        "Audio Object Type",
+                        format!(
+                            "{} ({})",
+                            aac_object_name(config.object_type),
+                            config.object_type
+                        ),
+                    ));
+                    fields.push(field("Sample Rate", format!("{} Hz", config.sample_rate)));
+                    fields.push(field("Channels", config.channels.to_string()));
+                } else if audio.aac_packet_type == Some(0) {
+                    fields.push(field(
+                        "AudioSpecificConfig",
+                        String::from("Too short to read"),
+                    ));
+                }
+            }
+            MediaHeader::Video(video) => {
+                fields.push(field(
+                    "Frame Type",
+                    format!(
+                        "{} ({})",
+                        frame_type_name(video.frame_type),
+                        video.frame_type
+                    ),
+                ));
+                fields.push(field(
+                    "Codec ID",
+                    format!("{} ({})", video_codec_name(video.codec_id), video.codec_id),
+                ));
+                if let Some(packet_type) = video.avc_packet_type {
+                    let name = match packet_type {
+                        1 => "NALU",
+                        _ => packet_type_name(packet_type),
+                    };
+                    fields.push(field(
+                        "AVC Packet Type",
+                        format!("{} ({})", name, packet_type),
+                    ));
+                }
+                if let Some(time) = video.composition_time {
+                    fields.push(field("Composition Time", format!("{} ms", time)));
+                }
+                if let Some(config) = &video.config {
+                    fields.push(field("Configuration Version", config.version.to_string()));
+                    fields.push(field(
+                        "Profile",
+                        format!("{} ({})", avc_profile_name(config.profile), config.profile),
+                    ));
+                    fields.push(field(
+                        "Profile Compatibility",
+                        format!("{:#04X}", config.compatibility),
+                    ));
+                    fields.push(field(
+                        "Level",
+                        format!("{}.{}", config.level / 10, config.level % 10),
+                    ));
+                    fields.push(field(
+                        "NAL Length Size",
+                        format!("{} bytes", config.nal_length_size),
+                    ));
+                    for sps in &config.sps {
+                        fields.push(field("SPS", format!("{:02X?}", sps)));
+                    }
+                    for pps in &config.pps {
+                        fields.push(field("PPS", format!("{:02X?}", pps)));
+                    }
+                } else if video.avc_packet_type == Some(0) {
+                    fields.push(field(
+                        "AVCDecoderConfigurationRecord",
+                        String::from("Too short to read"),
+                    ));
+                }
+            }
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aac_sequence_header() {
+        // AAC LC, 44.1 kHz, stereo
+        let MediaHeader::Audio(audio) = parse_media(8, &[0xAF, 0x00, 0x12, 0x10]).unwrap() else {
+            panic!("not audio");
+        };
+        assert_eq!(audio.sound_format, 10);
+        assert_eq!(
+            audio.config,
+            Some(AudioSpecificConfig {
+                object_type: 2,
+                sample_rate: 44100,
+                channels: 2,
+            })
+        );
+        assert_eq!(parse_media(8, &[0xAF]), Err(MediaError::TooShort(8, 1)));
+    }
+
+    #[test]
+    fn avc_sequence_header() {
+        let payload = [
+            0x17, 0x00, 0x00, 0x00, 0x00, // Key frame, AVC, sequence header
+            0x01, 0x64, 0x00, 0x1F, 0xFF, 0xE1, // High, level 3.1, 4 byte lengths, one SPS
+            0x00, 0x04, 0x67, 0x64, 0x00, 0x1F, // SPS
+            0x01, 0x00, 0x02, 0x68, 0xEE, // One PPS
+        ];
+        let header = parse_media(9, &payload).unwrap();
+        let MediaHeader::Video(video) = &header else {
+            panic!("not video");
+        };
+        let config = video.config.as_ref().unwrap();
+        assert_eq!(config.nal_length_size, 4);
+        assert_eq!(config.sps, [vec![0x67, 0x64, 0x00, 0x1F]]);
+        assert_eq!(config.pps, [vec![0x68, 0xEE]]);
+        let fields = header.fields();
+        assert_eq!(fields[0].1, "Key Frame (1)");
+        assert_eq!(fields[5].1, "High (100)");
+        assert_eq!(fields[7].1, "3.1");
+
+        // Negative composition times are allowed
+        let MediaHeader::Video(frame) = parse_media(9, &[0x27, 0x01, 0xFF, 0xFF, 0xD8]).unwrap()
+        else {
+            panic!("not video");
+        };
+        assert_eq!(frame.composition_time, Some(-40));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::rtmp::aggregate::{aggregate_fields, split_aggregate};
+use crate::rtmp::chunk_stream::RtmpMessage;
+use crate::rtmp::command::parse_command;
+use crate::rtmp::control::parse_control;
+use crate::rtmp::data::parse_data;
+use crate::rtmp::media::parse_media;
+use crate::rtmp::shared_object::parse_shared_object;
+
+/// What the spec calls each message type ID.
+pub fn type_name(type_id: u8) -> &'static str {
+    match type_id {
+        1 => "Set Chunk Size",
+        2 => "Abort",
+        3 => "Acknowledgement",
+        4 => "User Control",
+        5 => "Window Acknowledgement Size",
+        6 => "Set Peer Bandwidth",
+        8 => "Audio",
+        9 => "Video",
+        15 => "Data (AMF3)",
+        16 => "Shared Object (AMF3)",
+        17 => "Command (AMF3)",
+        18 => "Data (AMF0)",
+        19 => "Shared Object (AMF0)",
+        20 => "Command (AMF0)",
+        22 => "Aggregate",
+        _ => "Unknown",
+    }
+}
+
+/// FLV's names for the SoundFormat IDs, also used for audiocodecid in metadata.
+pub fn sound_format_name(id: u8) -> &'static str {
+    match id {
+        0 => "Linear PCM, platform endian",
+        1 => "ADPCM",
+        2 => "MP3",
+        3 => "Linear PCM, little endian",
+        4 => "Nellymoser 16 kHz mono",
+        5 => "Nellymoser 8 kHz mono",
+        6 => "Nellymoser",
+        7 => "G.711 A-law",
+        8 => "G.711 mu-law",
+        10 => "AAC",
+        11 => "Speex",
+        14 => "MP3 8 kHz",
+        15 => "Device-specific",
+        _ => "Unknown",
+    }
+}
+
+/// FLV's names for the video CodecID values, also used for videocodecid in metadata.
+pub fn video_codec_name(id: u8) -> &'static str {
+    match id {
+        1 => "JPEG",
+        2 => "Sorenson H.263",
+        3 => "Screen Video",
+        4 => "On2 VP6",
+        5 => "On2 VP6 with alpha",
+        6 => "Screen Video 2",
+        7 => "AVC",
+        _ => "Unknown",
+    }
+}
+
+/// The named fields the inspector shows for a message, empty for types we only show as AMF.
+pub fn message_fields(message: &RtmpMessage) -> Vec<(String, String)> {
+    match message.type_id {
+        1..=6 => match parse_control(message.type_id, &message.payload) {
+            Ok(control) => control.fields(),
+            Err(e) => vec![(String::from("Error"), e.to_string())],
+        },
+        17 | 20 => {
+            let Some(mut reader) = message.amf_reader() else {
+                return Vec::new();
+            };
+            reader.highlight();
+            match parse_command(&reader.objects, &reader.roots) {
+                Some(command) => command.fields(&reader.objects),
+                None => vec![(
+                    String::from("Error"),
+                    String::from("Does not start with a command name and transaction ID"),
+                )],
+            }
+        }
+        15 | 18 => {
+            let Some(mut reader) = message.amf_reader() else {
+                return Vec::new();
+            };
+            reader.highlight();
+            match parse_data(&reader.objects, &reader.roots) {
+                Some(data) => data.fields(&reader.objects),
+                None => vec![(
+                    String::from("Error"),
+                    String::from("Does not start with a handler name"),
+                )],
+            }
+        }
+        8 | 9 => match parse_media(message.type_id, &message.payload) {
+            Ok(header) => header.fields(),
+            Err(e) => vec![(String::from("Error"), e.to_string())],
+        },
+        16 | 19 => match parse_shared_object(message.type_id, &message.payload) {
+            Ok(shared_object) => shared_object.fields(),
+            Err(e) => vec![(String::from("Error"), e.to_string())],
+        },
+        22 => match split_aggregate(message) {
+            Ok(subs) => aggregate_fields(&subs),
+            Err(e) => vec![(String::from("Error"), e.to_string())],
+        },
+        _ => Vec::new(),
+    }
+}
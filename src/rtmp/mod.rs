@@ -0,0 +1,10 @@
+pub mod aggregate;
+pub mod chunk_stream;
+pub mod command;
+pub mod control;
+pub mod data;
+pub mod handshake;
+pub mod media;
+pub mod message;
+pub mod session;
+pub mod shared_object;
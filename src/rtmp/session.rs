@@ -0,0 +1,518 @@
+use crate::rtmp::aggregate::split_aggregate;
+use crate::rtmp::chunk_stream::{ChunkDemuxer, ChunkError, RtmpMessage};
+use crate::rtmp::command::{parse_command, CommandKind};
+use crate::rtmp::handshake::{is_handshake, parse_handshake, Handshake, Side};
+use crate::rtmp::message::{message_fields, type_name};
+use crate::rtmp::shared_object::{parse_shared_object, SlotHistory};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    pub fn other(self) -> Self {
+        match self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ClientToServer => write!(f, "C→S"),
+            Direction::ServerToClient => write!(f, "S→C"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionMessage {
+    pub direction: Direction,
+    pub message: RtmpMessage,
+    pub command: Option<(String, f64)>, // Name and transaction ID, for command messages
+    pub parent: Option<usize>,          // The aggregate message this came out of
+    pub offset: usize,                  // Where it starts in its side's capture
+}
+
+/// What reading one direction's capture gave, apart from its messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionSide {
+    pub direction: Direction,
+    pub handshake: Option<Handshake>,
+    pub error: Option<ChunkError>, // Why reading stopped early, if it did
+}
+
+/// A request and the _result or _error that answered it, both as indexes into the messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub id: f64,
+    pub request: usize,
+    pub response: Option<usize>,
+}
+
+/// Every message of a connection, from one or both directions. Each side stays in capture
+/// order, the sides are interleaved by timestamp.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub sides: Vec<SessionSide>,
+    pub messages: Vec<SessionMessage>,
+    pub transactions: Vec<Transaction>,
+    pub slots: SlotHistory, // Shared object slots as they were at the end
+    by_message: HashMap<usize, usize>, // Message index to its transaction, requests and responses
+    slot_changes: HashMap<usize, Vec<(String, String)>>,
+}
+
+fn command_of(message: &RtmpMessage) -> Option<(String, f64)> {
+    if !matches!(message.type_id, 17 | 20) {
+        return None;
+    }
+    let mut reader = message.amf_reader()?;
+    reader.highlight();
+    let command = parse_command(&reader.objects, &reader.roots)?;
+    Some((command.name, command.transaction_id))
+}
+
+/// Which way a capture went, from the handshake digest or else the first command in it.
+/// Anything we can't tell is taken to be the client.
+pub fn guess_direction(bytes: &[u8]) -> Direction {
+    let mut start = 0;
+    if is_handshake(bytes) {
+        if let Ok(handshake) = parse_handshake(bytes) {
+            match handshake.side() {
+                Some(Side::Client) => return Direction::ClientToServer,
+                Some(Side::Server) => return Direction::ServerToClient,
+                None => start = handshake.end(),
+            }
+        }
+    }
+    for message in ChunkDemuxer::new(bytes)
+        .starting_at(start)
+        .map_while(Result::ok)
+    {
+        if let Some((name, _)) = command_of(&message) {
+            return match CommandKind::from_name(&name) {
+                CommandKind::Result | CommandKind::Error | CommandKind::OnStatus => {
+                    Direction::ServerToClient
+                }
+                _ => Direction::ClientToServer,
+            };
+        }
+    }
+    Direction::ClientToServer
+}
+
+// Known type, and whatever is inside reads without errors
+fn is_sensible(message: &RtmpMessage) -> bool {
+    if type_name(message.type_id) == "Unknown" {
+        return false;
+    }
+    if message_fields(message)
+        .iter()
+        .any(|(name, _)| name == "Error")
+    {
+        return false;
+    }
+    match message.amf_reader() {
+        Some(mut reader) => {
+            reader.highlight();
+            !reader.objects.contains_key(&-1)
+        }
+        None => true,
+    }
+}
+
+/// Whether a file looks like an RTMP capture rather than bare AMF: it starts with a handshake,
+/// or its first messages all split out of the chunks cleanly and make sense on their own. A
+/// bare AMF payload can pass for a chunk header, but not for much more than that.
+pub fn is_capture(bytes: &[u8]) -> bool {
+    if is_handshake(bytes) {
+        return true;
+    }
+    let mut count = 0;
+    for message in ChunkDemuxer::new(bytes).take(16) {
+        match message {
+            Ok(message) if is_sensible(&message) => count += 1,
+            // Captures often stop partway through a message
+            Err(ChunkError::Truncated(_)) if count > 0 => break,
+            _ => return false,
+        }
+    }
+    count > 0
+}
+
+fn read_side(direction: Direction, bytes: &[u8]) -> (SessionSide, Vec<SessionMessage>) {
+    let mut side = SessionSide {
+        direction,
+        handshake: None,
+        error: None,
+    };
+    let mut start = 0;
+    if is_handshake(bytes) {
+        if let Ok(handshake) = parse_handshake(bytes) {
+            start = handshake.end();
+            side.handshake = Some(handshake);
+        }
+    }
+    let mut messages = Vec::new();
+    for message in ChunkDemuxer::new(bytes).starting_at(start) {
+        match message {
+            Ok(message) => messages.push(SessionMessage {
+                direction,
+                command: command_of(&message),
+                parent: None,
+                offset: message.headers[0].span.start,
+                message,
+            }),
+            Err(e) => {
+                side.error = Some(e);
+                break;
+            }
+        }
+    }
+    (side, messages)
+}
+
+// Takes whichever side's next message has the lowest timestamp, ties going to the side
+// loaded first. Only the heads are compared, so each side comes out in the order it went in.
+fn merge(mut sides: Vec<VecDeque<SessionMessage>>) -> Vec<SessionMessage> {
+    let mut messages = Vec::with_capacity(sides.iter().map(VecDeque::len).sum());
+    while let Some(side) = sides
+        .iter_mut()
+        .filter(|side| !side.is_empty())
+        .min_by_key(|side| side[0].message.timestamp)
+    {
+        messages.extend(side.pop_front());
+    }
+    messages
+}
+
+impl Session {
+    /// Reads each direction's capture and puts the messages together. The two sides stamp
+    /// messages with their own clocks, which both start near 0 at the handshake, so the order
+    /// between them is only as good as that. Within a side nothing is reordered, timestamps
+    /// are per chunk stream and plenty of servers send everything but media at 0.
+    pub fn new(captures: &[(Direction, &[u8])]) -> Self {
+        let mut sides = Vec::new();
+        let mut queues = Vec::new();
+        for (direction, bytes) in captures {
+            let (side, side_messages) = read_side(*direction, bytes);
+            sides.push(side);
+            queues.push(VecDeque::from(side_messages));
+        }
+        let messages = merge(queues);
+
+        // Aggregates are followed by what was in them, so the list stays in capture order
+        let mut expanded = Vec::with_capacity(messages.len());
+        for message in messages {
+            let parent = expanded.len();
+            let subs = split_aggregate(&message.message).unwrap_or_default();
+            let (direction, offset) = (message.direction, message.offset);
+            let payload_offset = |start| message.message.input_offset(start).unwrap_or(offset);
+            let subs: Vec<SessionMessage> = subs
+                .into_iter()
+                .map(|sub| SessionMessage {
+                    direction,
+                    command: command_of(&sub.message),
+                    parent: Some(parent),
+                    offset: payload_offset(sub.span.start),
+                    message: sub.message,
+                })
+                .collect();
+            expanded.push(message);
+            expanded.extend(subs);
+        }
+        let messages = expanded;
+
+        let mut session = Session {
+            sides,
+            messages,
+            transactions: Vec::new(),
+            slots: SlotHistory::default(),
+            by_message: HashMap::new(),
+            slot_changes: HashMap::new(),
+        };
+        session.correlate();
+        session.track_slots();
+        session
+    }
+
+    // The nth response with a transaction ID answers the nth request with it from the other
+    // direction, either side can make requests. Requests are all gathered first, so it
+    // doesn't matter how the two sides' clocks interleaved them.
+    fn correlate(&mut self) {
+        let mut waiting: HashMap<(Direction, u64), VecDeque<usize>> = HashMap::new();
+        for (index, message) in self.messages.iter().enumerate() {
+            let Some((name, id)) = &message.command else {
+                continue;
+            };
+            match CommandKind::from_name(name) {
+                // Transaction 0 means no response is expected, e.g. play and onStatus
+                CommandKind::Result | CommandKind::Error | CommandKind::OnStatus => {}
+                _ if *id == 0.0 => {}
+                _ => {
+                    let transaction = self.transactions.len();
+                    self.transactions.push(Transaction {
+                        id: *id,
+                        request: index,
+                        response: None,
+                    });
+                    self.by_message.insert(index, transaction);
+                    waiting
+                        .entry((message.direction, id.to_bits()))
+                        .or_default()
+                        .push_back(transaction);
+                }
+            }
+        }
+        for (index, message) in self.messages.iter().enumerate() {
+            let Some((name, id)) = &message.command else {
+                continue;
+            };
+            if !matches!(
+                CommandKind::from_name(name),
+                CommandKind::Result | CommandKind::Error
+            ) {
+                continue;
+            }
+            let key = (message.direction.other(), id.to_bits());
+            if let Some(transaction) = waiting.get_mut(&key).and_then(VecDeque::pop_front) {
+                self.transactions[transaction].response = Some(index);
+                self.by_message.insert(index, transaction);
+            }
+        }
+    }
+
+    // Replays shared object messages in order so each one can say what it changed
+    fn track_slots(&mut self) {
+        for (index, message) in self.messages.iter().enumerate() {
+            let message = &message.message;
+            if !matches!(message.type_id, 16 | 19) {
+                continue;
+            }
+            if let Ok(shared_object) = parse_shared_object(message.type_id, &message.payload) {
+                let changes = self.slots.apply(&shared_object);
+                if !changes.is_empty() {
+                    self.slot_changes.insert(index, changes);
+                }
+            }
+        }
+    }
+
+    /// The transaction a message is the request or response of.
+    pub fn transaction_of(&self, index: usize) -> Option<&Transaction> {
+        self.transactions.get(*self.by_message.get(&index)?)
+    }
+
+    /// Milliseconds between a request and its response by their timestamps. None when the
+    /// response is stamped earlier, e.g. a server that sends every _result at 0.
+    pub fn round_trip(&self, transaction: &Transaction) -> Option<i64> {
+        let response = self.messages.get(transaction.response?)?;
+        let request = self.messages.get(transaction.request)?;
+        let round_trip = response.message.timestamp as i64 - request.message.timestamp as i64;
+        (round_trip >= 0).then_some(round_trip)
+    }
+
+    // "#n name" of the other end of a transaction, with the round trip when it means anything
+    fn link(&self, transaction: &Transaction, index: usize) -> String {
+        match self.round_trip(transaction) {
+            Some(round_trip) => format!(
+                "#{} {}, round trip {} ms",
+                index,
+                self.command_name(index),
+                round_trip
+            ),
+            None => format!("#{} {}", index, self.command_name(index)),
+        }
+    }
+
+    /// Requests that no response turned up for. Only meaningful with both directions loaded.
+    pub fn unanswered(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions
+            .iter()
+            .filter(|transaction| transaction.response.is_none())
+    }
+
+    fn command_name(&self, index: usize) -> &str {
+        match &self.messages[index].command {
+            Some((name, _)) => name,
+            None => "",
+        }
+    }
+
+    /// A side's handshake, and whether its C2/S2 answers the other side's C1/S1 when both
+    /// are loaded.
+    pub fn handshake_fields(&self, side: usize) -> Vec<(String, String)> {
+        let Some(handshake) = self
+            .sides
+            .get(side)
+            .and_then(|side| side.handshake.as_ref())
+        else {
+            return Vec::new();
+        };
+        let mut fields = handshake.fields();
+        let peer = self
+            .sides
+            .iter()
+            .enumerate()
+            .find(|(i, _)| *i != side)
+            .and_then(|(_, peer)| peer.handshake.as_ref());
+        if let Some(peer) = peer {
+            fields.push((
+                String::from("Answers Peer"),
+                handshake.answers(peer).to_string(),
+            ));
+        }
+        fields
+    }
+
+    /// The message's own fields, then what it did to shared object slots and what it answers
+    /// or what answered it.
+    pub fn fields(&self, index: usize) -> Vec<(String, String)> {
+        let mut fields = message_fields(&self.messages[index].message);
+        if let Some(changes) = self.slot_changes.get(&index) {
+            fields.extend(changes.iter().cloned());
+        }
+        let Some(transaction) = self.transaction_of(index) else {
+            return fields;
+        };
+        if transaction.request == index {
+            let value = match transaction.response {
+                Some(response) => self.link(transaction, response),
+                None if self.sides.len() < 2 => String::from("Not seen, only one side is loaded"),
+                None => String::from("None, the request was never answered"),
+            };
+            fields.push((String::from("Answered By"), value));
+        } else {
+            fields.push((
+                String::from("In Reply To"),
+                self.link(transaction, transaction.request),
+            ));
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::amf_writer::AMFWriter;
+
+    // Each command as a whole type 20 message on chunk stream 3
+    fn commands(commands: &[(&str, f64, u32)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, id, time) in commands {
+            let mut writer = AMFWriter::new(false);
+            writer.write_amf0_string(name).unwrap();
+            writer.write_amf0_number(*id);
+            writer.out.push(0x05);
+            let payload = writer.into_bytes();
+            out.push(0x03);
+            out.extend_from_slice(&time.to_be_bytes()[1..]);
+            out.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+            out.extend_from_slice(&[20, 0, 0, 0, 0]);
+            out.extend_from_slice(&payload);
+        }
+        out
+    }
+
+    #[test]
+    fn requests_and_responses() {
+        let client = commands(&[
+            ("connect", 1.0, 0),
+            ("createStream", 2.0, 40),
+            ("getUser", 3.0, 50),
+            ("play", 0.0, 60),
+        ]);
+        let server = commands(&[("_result", 1.0, 25), ("_result", 2.0, 45)]);
+        assert_eq!(guess_direction(&client), Direction::ClientToServer);
+        assert_eq!(guess_direction(&server), Direction::ServerToClient);
+        assert!(is_capture(&client));
+        // A bare command payload is AMF, even though it splits into a chunk or two
+        assert!(!is_capture(&client[12..]));
+
+        let session = Session::new(&[
+            (Direction::ClientToServer, &client),
+            (Direction::ServerToClient, &server),
+        ]);
+        let order: Vec<&str> = (0..session.messages.len())
+            .map(|i| session.command_name(i))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "connect",
+                "_result",
+                "createStream",
+                "_result",
+                "getUser",
+                "play"
+            ]
+        );
+        // play has transaction 0, so only getUser is left waiting
+        assert_eq!(session.transactions.len(), 3);
+        let unanswered: Vec<usize> = session.unanswered().map(|t| t.request).collect();
+        assert_eq!(unanswered, [4]);
+
+        let connect = session.transaction_of(1).unwrap();
+        assert_eq!(connect.request, 0);
+        assert_eq!(session.round_trip(connect), Some(25));
+        assert_eq!(
+            session.fields(0).last().unwrap().1,
+            "#1 _result, round trip 25 ms"
+        );
+        assert_eq!(
+            session.fields(4).last().unwrap().1,
+            "None, the request was never answered"
+        );
+    }
+
+    #[test]
+    fn responses_stamped_at_zero() {
+        // Like nginx-rtmp, which sends every _result with timestamp 0
+        let client = commands(&[
+            ("connect", 1.0, 10),
+            ("createStream", 2.0, 40),
+            ("deleteStream", 3.0, 90),
+        ]);
+        let server = commands(&[("_result", 1.0, 0), ("_result", 2.0, 0)]);
+        let session = Session::new(&[
+            (Direction::ClientToServer, &client),
+            (Direction::ServerToClient, &server),
+        ]);
+        let order: Vec<&str> = (0..session.messages.len())
+            .map(|i| session.command_name(i))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "_result",
+                "_result",
+                "connect",
+                "createStream",
+                "deleteStream"
+            ]
+        );
+        let answered: Vec<(usize, Option<usize>)> = session
+            .transactions
+            .iter()
+            .map(|t| (t.request, t.response))
+            .collect();
+        assert_eq!(answered, [(2, Some(0)), (3, Some(1)), (4, None)]);
+        assert_eq!(session.round_trip(&session.transactions[0]), None);
+        assert_eq!(session.fields(2).last().unwrap().1, "#0 _result");
+
+        // One side on its own is never reordered, even when its timestamps go backwards
+        let session = Session::new(&[(
+            Direction::ClientToServer,
+            &commands(&[("connect", 1.0, 50), ("getUser", 2.0, 0)]),
+        )]);
+        assert_eq!(session.command_name(0), "connect");
+        assert_eq!(session.messages[1].message.timestamp, 0);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::amf::amf_highlight::AMFReader;
+use crate::rtmp::command::summary;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SharedObjectError {
+    NotSharedObject(u8),
+    Truncated(usize), // Offset in the payload where more was needed
+    BadValue(usize),  // Offset of an AMF value that didn't decode
+}
+
+impl fmt::Display for SharedObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SharedObjectError::NotSharedObject(type_id) => {
+                write!(f, "Type {} is not a shared object message", type_id)
+            }
+            SharedObjectError::Truncated(offset) => {
+                write!(f, "Shared object message ends early at {:#X}", offset)
+            }
+            SharedObjectError::BadValue(offset) => {
+                write!(f, "Shared object value at {:#X} doesn't decode", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SharedObjectError {}
+
+/// A slot named in a change, with its new value as AMF bytes and summarised on one line.
+/// Values can refer back to ones in earlier slots, so the summary is made while the whole
+/// event is read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slot {
+    pub name: String,
+    pub value: Vec<u8>,
+    pub summary: String,
+}
+
+/// One event in a shared object message. Values are kept as their AMF bytes, `decode`
+/// reads them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SharedObjectEvent {
+    Use,
+    Release,
+    RequestChange(Vec<Slot>),
+    Change(Vec<Slot>),
+    Success(String),        // Slot whose requested change went through
+    SendMessage(Vec<u8>),   // Handler name then its arguments, all AMF
+    Status(String, String), // Message, level
+    Clear,
+    Remove(String),
+    RequestRemove(String),
+    UseSuccess,
+    Unknown(u8, Vec<u8>),
+}
+
+impl SharedObjectEvent {
+    pub fn type_id(&self) -> u8 {
+        match self {
+            SharedObjectEvent::Use => 1,
+            SharedObjectEvent::Release => 2,
+            SharedObjectEvent::RequestChange(_) => 3,
+            SharedObjectEvent::Change(_) => 4,
+            SharedObjectEvent::Success(_) => 5,
+            SharedObjectEvent::SendMessage(_) => 6,
+            SharedObjectEvent::Status(..) => 7,
+            SharedObjectEvent::Clear => 8,
+            SharedObjectEvent::Remove(_) => 9,
+            SharedObjectEvent::RequestRemove(_) => 10,
+            SharedObjectEvent::UseSuccess => 11,
+            SharedObjectEvent::Unknown(type_id, _) => *type_id,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SharedObjectEvent::Use => "Use",
+            SharedObjectEvent::Release => "Release",
+            SharedObjectEvent::RequestChange(_) => "Request Change",
+            SharedObjectEvent::Change(_) => "Change",
+            SharedObjectEvent::Success(_) => "Success",
+            SharedObjectEvent::SendMessage(_) => "Send Message",
+            SharedObjectEvent::Status(..) => "Status",
+            SharedObjectEvent::Clear => "Clear",
+            SharedObjectEvent::Remove(_) => "Remove",
+            SharedObjectEvent::RequestRemove(_) => "Request Remove",
+            SharedObjectEvent::UseSuccess => "Use Success",
+            SharedObjectEvent::Unknown(..) => "Unknown",
+        }
+    }
+}
+
+/// Message types 19 and 16. Type 16 has one more byte in front, always 0, and its values
+/// switch to AMF3 with the 0x11 marker as they need to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedObjectMessage {
+    pub name: String,
+    pub version: u32,
+    pub persistent: bool,
+    pub events: Vec<SharedObjectEvent>,
+}
+
+// Reads through the payload, offsets stay relative to all of it for the errors
+struct Cursor<'a> {
+    payload: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SharedObjectError> {
+        let bytes = self
+            .payload
+            .get(self.offset..self.offset + len)
+            .ok_or(SharedObjectError::Truncated(self.offset))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, SharedObjectError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Names are AMF0 strings without the marker
+    fn string(&mut self) -> Result<String, SharedObjectError> {
+        let b = self.take(2)?;
+        let len = u16::from_be_bytes([b[0], b[1]]) as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.payload.len()
+    }
+}
+
+// Names and values take turns, one reader goes through all of them so the reference tables
+// carry over from slot to slot. The cursor only gets the names, the reader skips past them.
+fn slots(data: &mut Cursor) -> Result<Vec<Slot>, SharedObjectError> {
+    let mut reader = AMFReader::new(data.payload, false);
+    let mut slots = Vec::new();
+    while !data.is_empty() {
+        let name = data.string()?;
+        let start = data.offset;
+        reader.read_bytes(start - reader.read_head());
+        let object_id = reader.read_amf0();
+        if reader.is_error() {
+            return Err(SharedObjectError::BadValue(start));
+        }
+        let value = data.take(reader.read_head() - start)?.to_vec();
+        slots.push(Slot {
+            name,
+            value,
+            summary: summary(&reader.objects, object_id),
+        });
+    }
+    Ok(slots)
+}
+
+pub fn parse_shared_object(
+    type_id: u8,
+    payload: &[u8],
+) -> Result<SharedObjectMessage, SharedObjectError> {
+    let mut cursor = Cursor { payload, offset: 0 };
+    match type_id {
+        19 => {}
+        16 => {
+            cursor.take(1)?;
+        }
+        _ => return Err(SharedObjectError::NotSharedObject(type_id)),
+    }
+    let name = cursor.string()?;
+    let version = cursor.u32()?;
+    // 8 bytes of flags, only the first word means anything
+    let persistent = cursor.u32()? == 2;
+    cursor.take(4)?;
+
+    let mut events = Vec::new();
+    while !cursor.is_empty() {
+        let event_type = cursor.take(1)?[0];
+        let len = cursor.u32()? as usize;
+        let start = cursor.offset;
+        cursor.take(len)?;
+        let mut data = Cursor {
+            payload: &payload[..start + len],
+            offset: start,
+        };
+        let event = match event_type {
+            1 => SharedObjectEvent::Use,
+            2 => SharedObjectEvent::Release,
+            3 => SharedObjectEvent::RequestChange(slots(&mut data)?),
+            4 => SharedObjectEvent::Change(slots(&mut data)?),
+            5 => SharedObjectEvent::Success(data.string()?),
+            6 => SharedObjectEvent::SendMessage(data.take(len)?.to_vec()),
+            7 => SharedObjectEvent::Status(data.string()?, data.string()?),
+            8 => SharedObjectEvent::Clear,
+            9 => SharedObjectEvent::Remove(data.string()?),
+            10 => SharedObjectEvent::RequestRemove(data.string()?),
+            11 => SharedObjectEvent::UseSuccess,
+            _ => SharedObjectEvent::Unknown(event_type, data.take(len)?.to_vec()),
+        };
+        events.push(event);
+    }
+    Ok(SharedObjectMessage {
+        name,
+        version,
+        persistent,
+        events,
+    })
+}
+
+/// Every value in some AMF bytes, summarised on one line each.
+pub fn decode(bytes: &[u8]) -> Vec<String> {
+    let mut reader = AMFReader::new(bytes, false);
+    reader.highlight();
+    reader
+        .roots
+        .iter()
+        .map(|id| summary(&reader.objects, *id))
+        .collect()
+}
+
+impl SharedObjectMessage {
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let field = |name: &str, value: String| (name.to_string(), value);
+        let mut fields = vec![
+            field("Shared Object", self.name.clone()),
+            field("Version", self.version.to_string()),
+            field("Persistent", self.persistent.to_string()),
+        ];
+        for event in &self.events {
+            let event_name = format!("{} ({})", event.name(), event.type_id());
+            let details = match event {
+                SharedObjectEvent::RequestChange(slots) | SharedObjectEvent::Change(slots) => slots
+                    .iter()
+                    .map(|slot| format!("{} = {}", slot.name, slot.summary))
+                    .collect(),
+                SharedObjectEvent::Success(name)
+                | SharedObjectEvent::Remove(name)
+                | SharedObjectEvent::RequestRemove(name) => vec![name.clone()],
+                SharedObjectEvent::SendMessage(values) => vec![decode(values).join(", ")],
+                SharedObjectEvent::Status(message, level) => {
+                    vec![format!("{} ({})", message, level)]
+                }
+                SharedObjectEvent::Unknown(_, data) => vec![format!("{:02X?}", data)],
+                _ => Vec::new(),
+            };
+            if details.is_empty() {
+                fields.push(field("Event", event_name.clone()));
+            }
+            // One line per changed slot, so big changes stay readable
+            for detail in details {
+                fields.push(field("Event", format!("{}: {}", event_name, detail)));
+            }
+        }
+        fields
+    }
+}
+
+/// What each shared object's slots hold, built up one message at a time. Only the server's
+/// Change, Remove and Clear count, requests can still be turned down.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlotHistory {
+    slots: HashMap<String, HashMap<String, String>>,
+}
+
+impl SlotHistory {
+    /// Applies a message and says what it did to each slot, as "old → new".
+    pub fn apply(&mut self, message: &SharedObjectMessage) -> Vec<(String, String)> {
+        let slots = self.slots.entry(message.name.clone()).or_default();
+        let mut changes = Vec::new();
+        let was = |value: Option<String>| value.unwrap_or_else(|| String::from("unset"));
+        for event in &message.events {
+            match event {
+                SharedObjectEvent::Change(changed) => {
+                    for slot in changed {
+                        let old = slots.insert(slot.name.clone(), slot.summary.clone());
+                        changes.push((
+                            format!("Slot {}", slot.name),
+                            format!("{} → {}", was(old), slot.summary),
+                        ));
+                    }
+                }
+                SharedObjectEvent::Remove(name) => {
+                    if let Some(old) = slots.remove(name) {
+                        changes.push((format!("Slot {}", name), format!("{} → removed", old)));
+                    }
+                }
+                SharedObjectEvent::Clear => {
+                    let mut cleared: Vec<_> = slots.drain().collect();
+                    cleared.sort();
+                    for (name, old) in cleared {
+                        changes.push((format!("Slot {}", name), format!("{} → cleared", old)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    /// A shared object's slots as they stand now, sorted by name.
+    pub fn slots(&self, name: &str) -> Vec<(String, String)> {
+        let mut slots: Vec<_> = self
+            .slots
+            .get(name)
+            .map(|slots| slots.clone().into_iter().collect())
+            .unwrap_or_default();
+        slots.sort();
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: u8, data: &[u8]) -> Vec<u8> {
+        let mut out = vec![event_type];
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn message(events: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![0, 5];
+        out.extend_from_slice(b"chat1");
+        out.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 0]);
+        for event in events {
+            out.extend_from_slice(event);
+        }
+        out
+    }
+
+    #[test]
+    fn events_and_slots() {
+        // topic = "hi", count = 2
+        let mut change = vec![0, 5];
+        change.extend_from_slice(b"topic");
+        change.extend_from_slice(&[0x02, 0, 2, b'h', b'i', 0, 5]);
+        change.extend_from_slice(b"count");
+        change.push(0x00);
+        change.extend_from_slice(&2.0f64.to_be_bytes());
+        let first = message(&[event(11, &[]), event(8, &[]), event(4, &change)]);
+        let parsed = parse_shared_object(19, &first).unwrap();
+        assert_eq!(parsed.name, "chat1");
+        assert_eq!(parsed.version, 3);
+        assert!(parsed.persistent);
+        assert_eq!(parsed.events.len(), 3);
+        assert_eq!(parsed.fields()[4].1, "Clear (8)");
+        assert_eq!(parsed.fields()[5].1, "Change (4): topic = \"hi\"");
+
+        let mut history = SlotHistory::default();
+        assert_eq!(history.apply(&parsed).len(), 2);
+        let second = message(&[event(9, &[0, 5, b't', b'o', b'p', b'i', b'c'])]);
+        let changes = history.apply(&parse_shared_object(19, &second).unwrap());
+        assert_eq!(
+            changes,
+            [(String::from("Slot topic"), String::from("\"hi\" → removed"))]
+        );
+        assert_eq!(
+            history.slots("chat1"),
+            [(String::from("count"), String::from("2"))]
+        );
+
+        // The second slot is a reference to the object in the first
+        let mut shared = vec![0, 1, b'a', 0x03, 0, 1, b'k', 0x02, 0, 1, b'v', 0, 0, 0x09];
+        shared.extend_from_slice(&[0, 1, b'b', 0x07, 0, 0]);
+        let parsed = parse_shared_object(19, &message(&[event(4, &shared)])).unwrap();
+        let SharedObjectEvent::Change(slots) = &parsed.events[0] else {
+            panic!("{:?}", parsed.events);
+        };
+        assert_eq!(slots[1].value, [0x07, 0, 0]);
+        assert_eq!(slots[1].summary, slots[0].summary);
+        assert!(slots[0].summary.ends_with("(1 values)"));
+
+        let mut flex = vec![0];
+        flex.extend_from_slice(&second);
+        assert!(parse_shared_object(16, &flex).is_ok());
+        assert_eq!(
+            parse_shared_object(19, &first[..first.len() - 3]),
+            Err(SharedObjectError::Truncated(34))
+        );
+    }
+}
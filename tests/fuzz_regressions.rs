@@ -0,0 +1,28 @@
+use amf_viewer::amf::amf_highlight::AMFReader;
+use std::fs;
+use std::path::Path;
+
+// Inputs that once crashed or broke the spans, copied from fuzz/artifacts into fuzz/regressions
+#[test]
+fn fuzz_regressions() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+    let mut cases = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = fs::read(&path).unwrap();
+        for is_command in [false, true] {
+            let mut reader = AMFReader::new(&data, is_command);
+            reader.highlight();
+
+            let mut head = if is_command { 1.min(data.len()) } else { 0 };
+            for span in &reader.out {
+                assert_eq!(span.start, head, "{:?} gap or overlap", path);
+                assert!(span.end > span.start, "{:?} empty span", path);
+                head = span.end;
+            }
+            assert_eq!(head, data.len(), "{:?} not fully coloured", path);
+        }
+        cases += 1;
+    }
+    assert!(cases > 0);
+}
@@ -0,0 +1,111 @@
+use amf_viewer::amf::amf_highlight::AMFReader;
+use amf_viewer::amf::amf_writer::AMFWriter;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Everything the viewer shows for a payload: roots, every object with its span, and the colours
+fn snapshot(data: &[u8], is_command: bool) -> String {
+    let mut reader = AMFReader::new(data, is_command);
+    reader.highlight();
+
+    let mut out = String::new();
+    writeln!(out, "roots: {:?}", reader.roots).unwrap();
+    if let Some((limit, offset)) = reader.limit_exceeded() {
+        writeln!(out, "limit: {} at {}", limit, offset).unwrap();
+    }
+    writeln!(out, "objects:").unwrap();
+    let mut ids: Vec<isize> = reader.objects.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let info = &reader.objects[&id];
+        writeln!(
+            out,
+            "  {} {:?} {:?} {:?}",
+            id, info.span, info.object_type, info.object_properties
+        )
+        .unwrap();
+    }
+    writeln!(out, "spans:").unwrap();
+    for span in &reader.out {
+        writeln!(
+            out,
+            "  {:?} {} {}",
+            span.start..span.end,
+            span.object_id,
+            span.color
+        )
+        .unwrap();
+    }
+    out
+}
+
+// Every tests/golden/*.amf, sorted
+fn samples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "amf"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    paths
+}
+
+fn name(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+// Each tests/golden/*.amf is decoded and checked against the .snap beside it, files starting
+// with "command_" are read as commands. Run with UPDATE_GOLDEN=1 to rewrite the snapshots,
+// then read the diff to make sure the change was meant.
+#[test]
+fn golden() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = Vec::new();
+    for path in samples() {
+        let data = fs::read(&path).unwrap();
+        let name = name(&path);
+        let actual = snapshot(&data, name.starts_with("command_"));
+        let snap = path.with_extension("snap");
+        if update {
+            fs::write(&snap, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&snap).unwrap_or_default();
+        if actual != expected {
+            eprintln!("--- {}\nexpected:\n{}actual:\n{}", name, expected, actual);
+            failed.push(name);
+        }
+    }
+    assert!(failed.is_empty(), "snapshots differ: {:?}", failed);
+}
+
+// Every sample that decodes in full writes back out as the same bytes
+#[test]
+fn writer_round_trip() {
+    let mut failed = Vec::new();
+    for path in samples() {
+        let data = fs::read(&path).unwrap();
+        let name = name(&path);
+        let is_command = name.starts_with("command_");
+        let mut reader = AMFReader::new(&data, is_command);
+        reader.highlight();
+        if reader.objects.contains_key(&-1) {
+            continue;
+        }
+        let mut writer = AMFWriter::new(is_command);
+        let written = writer
+            .write_document(&reader.objects, &reader.roots)
+            .map(|_| writer.into_bytes());
+        if written.as_ref() != Ok(&data) {
+            eprintln!(
+                "--- {}\nread:    {:02X?}\nwritten: {:02X?}",
+                name, data, written
+            );
+            failed.push(name);
+        }
+    }
+    assert!(failed.is_empty(), "written bytes differ: {:?}", failed);
+}
@@ -0,0 +1,483 @@
+use amf_viewer::amf::amf_highlight::AMFReader;
+use amf_viewer::amf::amf_writer::AMFWriter;
+use amf_viewer::amf::object_info::ObjectInfo;
+use amf_viewer::amf::object_properties::{
+    DictionaryProperties, ObjectProperties, TypeProperties, VectorProperties,
+};
+use amf_viewer::amf::object_type::ObjectType;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Clone, Debug)]
+enum Amf0Value {
+    Number(f64),
+    Bool(bool),
+    String(String),
+    Null,
+    Undefined,
+    Unsupported,
+    Date(f64),
+    LongString(String),
+    Xml(String),
+    Object(Vec<(String, Amf0Value)>),
+    EcmaArray(Vec<(String, Amf0Value)>),
+    TypedObject(String, Vec<(String, Amf0Value)>),
+    StrictArray(Vec<Amf0Value>),
+    Reference(usize), // Any object, ECMA or strict array already built
+    Amf3(Amf3Value),
+}
+
+#[derive(Clone, Debug)]
+enum Amf3Value {
+    Undefined,
+    Null,
+    Bool(bool),
+    Integer(i32),
+    Double(f64),
+    String(String),
+    XmlDocument(String),
+    Xml(String),
+    Date(f64),
+    ByteArray(Vec<u8>),
+    Array(Vec<(String, Amf3Value)>, Vec<Amf3Value>),
+    Object {
+        class: String,
+        sealed: Vec<(String, Amf3Value)>,
+        dynamic: Option<Vec<(String, Amf3Value)>>,
+    },
+    ArrayCollection(Box<Amf3Value>),
+    VectorInt(Vec<i32>, bool),
+    VectorUInt(Vec<u32>, bool),
+    VectorDouble(Vec<f64>, bool),
+    VectorObject(String, Vec<Amf3Value>, bool),
+    Dictionary(Vec<(Amf3Value, Amf3Value)>, bool),
+    Reference(usize), // Any referencable AMF3 value already built
+}
+
+fn key() -> impl Strategy<Value = String> {
+    "[a-zA-Z_][a-zA-Z0-9_é]{0,8}"
+}
+
+fn text() -> impl Strategy<Value = String> {
+    "\\PC{0,12}"
+}
+
+fn class_name() -> impl Strategy<Value = String> {
+    "((com\\.example\\.)?[A-Z][a-z]{0,6})?"
+}
+
+fn amf3_value() -> impl Strategy<Value = Amf3Value> {
+    let leaf = prop_oneof![
+        Just(Amf3Value::Undefined),
+        Just(Amf3Value::Null),
+        any::<bool>().prop_map(Amf3Value::Bool),
+        (-0x1000_0000..0x1000_0000).prop_map(Amf3Value::Integer),
+        any::<f64>().prop_map(Amf3Value::Double),
+        text().prop_map(Amf3Value::String),
+        text().prop_map(Amf3Value::XmlDocument),
+        text().prop_map(Amf3Value::Xml),
+        any::<f64>().prop_map(Amf3Value::Date),
+        vec(any::<u8>(), 0..16).prop_map(Amf3Value::ByteArray),
+        (vec(any::<i32>(), 0..4), any::<bool>()).prop_map(|(v, f)| Amf3Value::VectorInt(v, f)),
+        (vec(any::<u32>(), 0..4), any::<bool>()).prop_map(|(v, f)| Amf3Value::VectorUInt(v, f)),
+        (vec(any::<f64>(), 0..4), any::<bool>()).prop_map(|(v, f)| Amf3Value::VectorDouble(v, f)),
+        any::<usize>().prop_map(Amf3Value::Reference),
+    ];
+    leaf.prop_recursive(4, 48, 4, |inner| {
+        prop_oneof![
+            (vec((key(), inner.clone()), 0..4), vec(inner.clone(), 0..4))
+                .prop_map(|(associative, dense)| Amf3Value::Array(associative, dense)),
+            (
+                class_name(),
+                vec((key(), inner.clone()), 0..4),
+                option::of(vec((key(), inner.clone()), 0..4))
+            )
+                .prop_map(|(class, sealed, dynamic)| Amf3Value::Object {
+                    class,
+                    sealed,
+                    dynamic
+                }),
+            inner
+                .clone()
+                .prop_map(|source| Amf3Value::ArrayCollection(Box::new(source))),
+            (class_name(), vec(inner.clone(), 0..4), any::<bool>())
+                .prop_map(|(class, items, f)| Amf3Value::VectorObject(class, items, f)),
+            (vec((inner.clone(), inner.clone()), 0..3), any::<bool>())
+                .prop_map(|(entries, weak)| Amf3Value::Dictionary(entries, weak)),
+        ]
+    })
+}
+
+fn amf0_value() -> impl Strategy<Value = Amf0Value> {
+    let leaf = prop_oneof![
+        any::<f64>().prop_map(Amf0Value::Number),
+        any::<bool>().prop_map(Amf0Value::Bool),
+        text().prop_map(Amf0Value::String),
+        Just(Amf0Value::Null),
+        Just(Amf0Value::Undefined),
+        Just(Amf0Value::Unsupported),
+        any::<f64>().prop_map(Amf0Value::Date),
+        text().prop_map(Amf0Value::LongString),
+        text().prop_map(Amf0Value::Xml),
+        any::<usize>().prop_map(Amf0Value::Reference),
+        amf3_value().prop_map(Amf0Value::Amf3),
+    ];
+    leaf.prop_recursive(4, 48, 4, |inner| {
+        prop_oneof![
+            vec((key(), inner.clone()), 0..4).prop_map(Amf0Value::Object),
+            vec((key(), inner.clone()), 0..4).prop_map(Amf0Value::EcmaArray),
+            (class_name(), vec((key(), inner.clone()), 0..4))
+                .prop_map(|(class, members)| Amf0Value::TypedObject(class, members)),
+            vec(inner.clone(), 0..4).prop_map(Amf0Value::StrictArray),
+        ]
+    })
+}
+
+// Turns generated values into the object model the writer takes. References pick one of the
+// containers that has already been finished, so the writer sees the same ID twice.
+#[derive(Default)]
+struct Builder {
+    objects: HashMap<isize, ObjectInfo<'static>>,
+    amf0_containers: Vec<isize>,
+    amf3_containers: Vec<isize>,
+}
+
+impl Builder {
+    fn insert(
+        &mut self,
+        object_type: ObjectType<'static>,
+        object_properties: TypeProperties,
+    ) -> isize {
+        let object_id = self.objects.len() as isize;
+        self.objects.insert(
+            object_id,
+            ObjectInfo::detached(object_id, object_type, object_properties),
+        );
+        object_id
+    }
+
+    fn members0(&mut self, members: &[(String, Amf0Value)]) -> Vec<(String, isize)> {
+        members
+            .iter()
+            .map(|(key, value)| (key.clone(), self.amf0(value)))
+            .collect()
+    }
+
+    fn members3(&mut self, members: &[(String, Amf3Value)]) -> Vec<(String, isize)> {
+        members
+            .iter()
+            .map(|(key, value)| (key.clone(), self.amf3(value)))
+            .collect()
+    }
+
+    fn amf0(&mut self, value: &Amf0Value) -> isize {
+        let none = TypeProperties::AmfNoProperties;
+        let (object_type, properties) = match value {
+            Amf0Value::Number(n) => (ObjectType::Amf0Number(*n), none),
+            Amf0Value::Bool(b) => (ObjectType::Amf0Bool(*b), none),
+            Amf0Value::String(s) => (
+                ObjectType::Amf0String(s.clone().into()),
+                TypeProperties::Amf0StringProperties,
+            ),
+            Amf0Value::Null => (ObjectType::Amf0Null, none),
+            Amf0Value::Undefined => (ObjectType::Amf0Undefined, none),
+            Amf0Value::Unsupported => (ObjectType::Amf0Unsupported, none),
+            Amf0Value::Date(d) => (ObjectType::Amf0Date(*d), none),
+            Amf0Value::LongString(s) => (ObjectType::Amf0LongString(s.clone().into()), none),
+            Amf0Value::Xml(s) => (ObjectType::Amf0XML(s.clone().into()), none),
+            Amf0Value::Reference(index) => {
+                return match self.amf0_containers.len() {
+                    0 => self.insert(ObjectType::Amf0Null, none),
+                    len => self.amf0_containers[index % len],
+                };
+            }
+            Amf0Value::Amf3(value) => return self.amf3(value),
+            Amf0Value::Object(members) => (
+                ObjectType::Amf0Object(self.members0(members)),
+                TypeProperties::Amf0ObjectProperties,
+            ),
+            Amf0Value::EcmaArray(members) => (
+                ObjectType::Amf0EcmaArray(self.members0(members)),
+                TypeProperties::Amf0EcmaArrayProperties(members.len() as u32),
+            ),
+            Amf0Value::TypedObject(class, members) => (
+                ObjectType::Amf0TypedObject(self.members0(members)),
+                TypeProperties::Amf0TypedObjectProperties(class.clone()),
+            ),
+            Amf0Value::StrictArray(values) => {
+                let values = values.iter().map(|value| self.amf0(value)).collect();
+                (ObjectType::Amf0StrictArray(values), none)
+            }
+        };
+        let is_container = matches!(
+            object_type,
+            ObjectType::Amf0Object(_)
+                | ObjectType::Amf0EcmaArray(_)
+                | ObjectType::Amf0TypedObject(_)
+                | ObjectType::Amf0StrictArray(_)
+        );
+        let object_id = self.insert(object_type, properties);
+        if is_container {
+            self.amf0_containers.push(object_id);
+        }
+        object_id
+    }
+
+    fn amf3(&mut self, value: &Amf3Value) -> isize {
+        let none = TypeProperties::AmfNoProperties;
+        let vector = |is_fixed: bool, class: &str| {
+            TypeProperties::Amf3VectorProperties(VectorProperties::new(is_fixed, class.to_string()))
+        };
+        let (object_type, properties, referencable) = match value {
+            Amf3Value::Undefined => (ObjectType::Amf3Undefined, none, false),
+            Amf3Value::Null => (ObjectType::Amf3Null, none, false),
+            Amf3Value::Bool(true) => (ObjectType::Amf3True, none, false),
+            Amf3Value::Bool(false) => (ObjectType::Amf3False, none, false),
+            Amf3Value::Integer(i) => (ObjectType::Amf3Integer(*i), none, false),
+            Amf3Value::Double(d) => (ObjectType::Amf3Double(*d), none, false),
+            Amf3Value::String(s) => (ObjectType::Amf3String(s.clone().into()), none, false),
+            Amf3Value::XmlDocument(s) => {
+                (ObjectType::Amf3XMLDocument(s.clone().into()), none, true)
+            }
+            Amf3Value::Xml(s) => (ObjectType::Amf3XML(s.clone().into()), none, true),
+            Amf3Value::Date(d) => (ObjectType::Amf3Date(*d), none, true),
+            Amf3Value::ByteArray(b) => (ObjectType::Amf3ByteArray(b.clone().into()), none, true),
+            Amf3Value::Reference(index) => {
+                return match self.amf3_containers.len() {
+                    0 => self.insert(ObjectType::Amf3Null, none),
+                    len => self.amf3_containers[index % len],
+                };
+            }
+            Amf3Value::Array(associative, dense) => {
+                let associative = self.members3(associative);
+                let dense = dense.iter().map(|value| self.amf3(value)).collect();
+                (ObjectType::Amf3Array(associative, dense), none, true)
+            }
+            Amf3Value::Object {
+                class,
+                sealed,
+                dynamic,
+            } => {
+                let mut members = self.members3(sealed);
+                if let Some(dynamic) = dynamic {
+                    members.extend(self.members3(dynamic));
+                }
+                let properties = ObjectProperties::new(
+                    false,
+                    sealed.len(),
+                    (dynamic.is_some() as usize) << 1,
+                    false,
+                    dynamic.is_some(),
+                    class.clone(),
+                );
+                (
+                    ObjectType::Amf3Object(members),
+                    TypeProperties::Amf3ObjectProperties(properties),
+                    true,
+                )
+            }
+            Amf3Value::ArrayCollection(source) => {
+                let source = self.amf3(source);
+                let properties = ObjectProperties::new(
+                    false,
+                    0,
+                    1,
+                    true,
+                    false,
+                    String::from("flex.messaging.io.ArrayCollection"),
+                );
+                (
+                    ObjectType::Amf3Object(vec![(String::from("source"), source)]),
+                    TypeProperties::Amf3ObjectProperties(properties),
+                    true,
+                )
+            }
+            Amf3Value::VectorInt(items, f) => (
+                ObjectType::Amf3VectorInt(items.clone()),
+                vector(*f, ""),
+                true,
+            ),
+            Amf3Value::VectorUInt(items, f) => (
+                ObjectType::Amf3VectorUInt(items.clone()),
+                vector(*f, ""),
+                true,
+            ),
+            Amf3Value::VectorDouble(items, f) => (
+                ObjectType::Amf3VectorDouble(items.clone()),
+                vector(*f, ""),
+                true,
+            ),
+            Amf3Value::VectorObject(class, items, f) => {
+                let items = items.iter().map(|value| self.amf3(value)).collect();
+                (ObjectType::Amf3VectorObject(items), vector(*f, class), true)
+            }
+            Amf3Value::Dictionary(entries, weak_keys) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| (self.amf3(key), self.amf3(value)))
+                    .collect();
+                (
+                    ObjectType::Amf3Dictionary(entries),
+                    TypeProperties::Amf3DictionaryProperties(DictionaryProperties::new(*weak_keys)),
+                    true,
+                )
+            }
+        };
+        let object_id = self.insert(object_type, properties);
+        if referencable {
+            self.amf3_containers.push(object_id);
+        }
+        object_id
+    }
+}
+
+// Expands an object graph into text, following references and switches, so two graphs compare
+// equal when they hold the same values whatever IDs they were given
+fn render(objects: &HashMap<isize, ObjectInfo>, object_id: isize, out: &mut String) {
+    let info = &objects[&object_id];
+    let members = |out: &mut String, members: &[(String, isize)]| {
+        out.push('{');
+        for (key, value) in members {
+            write!(out, "{:?}:", key).unwrap();
+            render(objects, *value, out);
+            out.push(',');
+        }
+        out.push('}');
+    };
+    let values = |out: &mut String, values: &[isize]| {
+        out.push('[');
+        for value in values {
+            render(objects, *value, out);
+            out.push(',');
+        }
+        out.push(']');
+    };
+    match (&info.object_type, &info.object_properties) {
+        (ObjectType::Amf0Switch(inner), _) => render(objects, *inner, out),
+        (ObjectType::Amf0Object(m), _) => {
+            out.push_str("Object");
+            members(out, m);
+        }
+        (ObjectType::Amf0EcmaArray(m), _) => {
+            out.push_str("EcmaArray");
+            members(out, m);
+        }
+        (ObjectType::Amf0TypedObject(m), properties) => {
+            write!(out, "TypedObject({:?})", properties).unwrap();
+            members(out, m);
+        }
+        (ObjectType::Amf0StrictArray(v), _) => {
+            out.push_str("StrictArray");
+            values(out, v);
+        }
+        (ObjectType::Amf3Array(associative, dense), _) => {
+            out.push_str("Array");
+            members(out, associative);
+            values(out, dense);
+        }
+        (ObjectType::Amf3Object(m), TypeProperties::Amf3ObjectProperties(p)) => {
+            // Whether the traits were sent inline or as a reference doesn't matter
+            write!(
+                out,
+                "Object({:?},{},{},{})",
+                p.object_type, p.property_count, p.dynamic, p.externalisable
+            )
+            .unwrap();
+            members(out, m);
+        }
+        (ObjectType::Amf3VectorObject(v), properties) => {
+            write!(out, "VectorObject({:?})", properties).unwrap();
+            values(out, v);
+        }
+        (ObjectType::Amf3Dictionary(entries), properties) => {
+            write!(out, "Dictionary({:?})[", properties).unwrap();
+            for (key, value) in entries {
+                render(objects, *key, out);
+                out.push(':');
+                render(objects, *value, out);
+                out.push(',');
+            }
+            out.push(']');
+        }
+        (
+            ObjectType::Amf3VectorInt(_)
+            | ObjectType::Amf3VectorUInt(_)
+            | ObjectType::Amf3VectorDouble(_),
+            properties,
+        ) => write!(out, "{:?}{:?}", info.object_type, properties).unwrap(),
+        (object_type, _) => write!(out, "{:?}", object_type).unwrap(),
+    }
+}
+
+// A U29 with the low bit clear, which in a header means "see object n" rather than a length
+fn is_reference_header(bytes: &[u8]) -> bool {
+    let Some((last, rest)) = bytes.split_last() else {
+        return false;
+    };
+    rest.len() < 4
+        && rest.iter().all(|b| b & 0x80 != 0)
+        && (rest.len() == 3 || last & 0x80 == 0)
+        && last & 0x01 == 0
+}
+
+fn check_spans(reader: &AMFReader, bytes: &[u8]) {
+    // Every byte is in exactly one span, in order
+    let mut head = 0;
+    for span in &reader.out {
+        assert_eq!(span.start, head, "gap or overlap at {}", head);
+        assert!(span.end > span.start, "empty span at {}", head);
+        head = span.end;
+    }
+    assert_eq!(head, bytes.len(), "spans stop short of the input");
+
+    // and each one is inside the object it names, unless it encodes a reference back to that
+    // object: an AMF0 0x07 and index, or an AMF3 marker then a header with the low bit clear
+    let chunks: Vec<&[u8]> = reader.out.iter().map(|s| &bytes[s.start..s.end]).collect();
+    let is_marker = |chunk: &[u8]| matches!(chunk, [0x07..=0x11]);
+    for (i, span) in reader.out.iter().enumerate() {
+        let object = &reader.objects[&span.object_id].span;
+        let inside = object.start <= span.start && span.end <= object.end;
+        let same = |j: usize| reader.out.get(j).map(|s| s.object_id) == Some(span.object_id);
+        let reference = match chunks[i] {
+            [0x07, _, _] => true,
+            chunk if is_marker(chunk) && same(i + 1) && is_reference_header(chunks[i + 1]) => true,
+            chunk => i > 0 && same(i - 1) && is_marker(chunks[i - 1]) && is_reference_header(chunk),
+        };
+        assert!(
+            inside || (object.end <= span.start && reference),
+            "{:?} doesn't belong to object {} at {:?}",
+            span.start..span.end,
+            span.object_id,
+            object
+        );
+    }
+}
+
+proptest! {
+    #[test]
+    fn round_trip(values in vec(amf0_value(), 1..4)) {
+        let mut builder = Builder::default();
+        let roots: Vec<isize> = values.iter().map(|value| builder.amf0(value)).collect();
+
+        let mut writer = AMFWriter::new(false);
+        writer.write_document(&builder.objects, &roots).unwrap();
+        let bytes = writer.into_bytes();
+
+        let mut reader = AMFReader::new(&bytes, false);
+        reader.highlight();
+        prop_assert!(!reader.objects.contains_key(&-1), "decode failed for {:02X?}", bytes);
+        prop_assert_eq!(reader.roots.len(), roots.len());
+
+        for (expected, actual) in roots.iter().zip(reader.roots.iter()) {
+            let mut want = String::new();
+            render(&builder.objects, *expected, &mut want);
+            let mut got = String::new();
+            render(&reader.objects, *actual, &mut got);
+            prop_assert_eq!(want, got);
+        }
+        check_spans(&reader, &bytes);
+    }
+}